@@ -1,21 +1,511 @@
-use crate::scanner::Token;
+use crate::formatter::operator_text;
+use crate::interpreter::{STACK_RED_ZONE, STACK_SEGMENT};
+use crate::scanner::{check_parens, render_span, Span, Token};
 use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
 
 // TODO: Use recursion to remove mutability
 
-#[derive(Debug, PartialEq, Eq)]
+/// The name that binds nothing: `def _ = f();` evaluates `f()` for its effects
+/// and `fn _, x { x }` ignores its first argument. It is not a variable, so it
+/// can't be used as a value.
+pub const DISCARD: &str = "_";
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
 pub enum Expr {
     Binary {
         left: Box<Expr>,
         operator: Token,
         right: Box<Expr>,
+        span: Span,
+    },
+    Call {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+        span: Span,
     },
     Grouping {
         expr: Box<Expr>,
+        span: Span,
+    },
+    BooleanLiteral {
+        value: bool,
+        span: Span,
     },
+    // `value` is the literal as written; `number` is parsed from it once, when
+    // the literal is parsed.
     NumericLiteral {
         value: String,
+        number: f64,
+        span: Span,
+    },
+    StringLiteral {
+        value: String,
+        span: Span,
+    },
+    // The imaginary part of a complex number, e.g. `3i`.
+    #[cfg(feature = "complex")]
+    ImaginaryLiteral {
+        value: String,
+        number: f64,
+        span: Span,
+    },
+    Postfix {
+        left: Box<Expr>,
+        operator: Token,
+        span: Span,
+    },
+    Unary {
+        operator: Token,
+        right: Box<Expr>,
+        span: Span,
+    },
+    Variable {
+        name: String,
+        span: Span,
+    },
+    Function {
+        params: Vec<String>,
+        body: Box<Expr>,
+        span: Span,
     },
+    While {
+        condition: Box<Expr>,
+        body: Box<Expr>,
+        span: Span,
+    },
+    // `cond { guard => value, ..., else => default }`; the value of the first
+    // arm whose guard is true, or the default when none is. An `if` chain is
+    // parsed to the same node, with `keyword` recording which was written so
+    // errors can name it.
+    Cond {
+        arms: Vec<(Expr, Expr)>,
+        default: Option<Box<Expr>>,
+        keyword: Token,
+        span: Span,
+    },
+    // `let name = value in body`; `name` is bound only while evaluating `body`.
+    Let {
+        name: String,
+        value: Box<Expr>,
+        body: Box<Expr>,
+        span: Span,
+    },
+    Array {
+        elements: Vec<Expr>,
+        span: Span,
+    },
+    // `{ name: value, ... }`, with the fields in source order.
+    Record {
+        fields: Vec<(String, Expr)>,
+        span: Span,
+    },
+    // `record.name`
+    Field {
+        record: Box<Expr>,
+        name: String,
+        span: Span,
+    },
+    // `target[index]`, an element of an array or a character of a string.
+    Index {
+        target: Box<Expr>,
+        index: Box<Expr>,
+        span: Span,
+    },
+    // `try body catch binding { handler }`; a runtime error raised by `body`
+    // binds its message to `binding` while evaluating `handler`.
+    Try {
+        body: Box<Expr>,
+        binding: String,
+        handler: Box<Expr>,
+        span: Span,
+    },
+}
+
+impl Expr {
+    /// Byte offsets of the source text this expression was parsed from.
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Binary { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Grouping { span, .. }
+            | Expr::BooleanLiteral { span, .. }
+            | Expr::NumericLiteral { span, .. }
+            | Expr::StringLiteral { span, .. }
+            | Expr::Postfix { span, .. }
+            | Expr::Unary { span, .. }
+            | Expr::Variable { span, .. }
+            | Expr::Function { span, .. }
+            | Expr::While { span, .. }
+            | Expr::Cond { span, .. }
+            | Expr::Let { span, .. }
+            | Expr::Array { span, .. }
+            | Expr::Record { span, .. }
+            | Expr::Field { span, .. }
+            | Expr::Index { span, .. }
+            | Expr::Try { span, .. } => *span,
+            #[cfg(feature = "complex")]
+            Expr::ImaginaryLiteral { span, .. } => *span,
+        }
+    }
+
+    /// The name of this expression's variant, e.g. `"Binary"`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Expr::Binary { .. } => "Binary",
+            Expr::Call { .. } => "Call",
+            Expr::Grouping { .. } => "Grouping",
+            Expr::BooleanLiteral { .. } => "BooleanLiteral",
+            Expr::NumericLiteral { .. } => "NumericLiteral",
+            #[cfg(feature = "complex")]
+            Expr::ImaginaryLiteral { .. } => "ImaginaryLiteral",
+            Expr::StringLiteral { .. } => "StringLiteral",
+            Expr::Postfix { .. } => "Postfix",
+            Expr::Unary { .. } => "Unary",
+            Expr::Variable { .. } => "Variable",
+            Expr::Function { .. } => "Function",
+            Expr::While { .. } => "While",
+            Expr::Cond { .. } => "Cond",
+            Expr::Let { .. } => "Let",
+            Expr::Array { .. } => "Array",
+            Expr::Record { .. } => "Record",
+            Expr::Field { .. } => "Field",
+            Expr::Index { .. } => "Index",
+            Expr::Try { .. } => "Try",
+        }
+    }
+
+    /// The base to show the value of this expression in, when it is
+    /// arithmetic on numeric literals: 16 if any of them is hex, as in
+    /// `0xFF + 1`, 10 for `2 * 3`, and None for `0xFF + x`.
+    pub fn radix(&self) -> Option<u32> {
+        match self {
+            Expr::NumericLiteral { value, .. } => Some(literal_radix(value)),
+            Expr::Binary { left, right, .. } => Some(left.radix()?.max(right.radix()?)),
+            Expr::Grouping { expr, .. }
+            | Expr::Unary { right: expr, .. }
+            | Expr::Postfix { left: expr, .. } => expr.radix(),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a numeric literal is written as a float rather than an integer.
+pub fn literal_is_float(literal: &str) -> bool {
+    match split_number_suffix(literal) {
+        (_, Some(suffix)) => suffix == "_f64",
+        (_, None) if literal_radix(literal) == 16 => false,
+        (digits, None) => digits.contains(['.', 'e', 'E']),
+    }
+}
+
+/// The value of an integer literal, or None for a literal written as a float
+/// or one outside the i64 range: `Some(255)` for `0xFF`, None for `7.0`.
+pub fn literal_integer(literal: &str) -> Option<i64> {
+    if literal_is_float(literal) {
+        return None;
+    }
+    let (digits, _) = split_number_suffix(literal);
+    match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(digits) => i64::from_str_radix(digits, 16).ok(),
+        None => digits.parse().ok(),
+    }
+}
+
+/// The base a numeric literal is written in, judged from its prefix.
+pub fn literal_radix(literal: &str) -> u32 {
+    if literal.starts_with("0x") || literal.starts_with("0X") {
+        16
+    } else {
+        10
+    }
+}
+
+// Numeric literals compare by their source text, which determines the parsed
+// number, so equality is total.
+impl Eq for Expr {}
+
+// Spans are ignored when comparing expressions, so trees parsed from
+// differently laid out source compare equal.
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                Expr::Binary {
+                    left,
+                    operator,
+                    right,
+                    ..
+                },
+                Expr::Binary {
+                    left: other_left,
+                    operator: other_operator,
+                    right: other_right,
+                    ..
+                },
+            ) => left == other_left && operator == other_operator && right == other_right,
+            (
+                Expr::Call { callee, args, .. },
+                Expr::Call {
+                    callee: other_callee,
+                    args: other_args,
+                    ..
+                },
+            ) => callee == other_callee && args == other_args,
+            (Expr::Grouping { expr, .. }, Expr::Grouping { expr: other, .. }) => expr == other,
+            (Expr::BooleanLiteral { value, .. }, Expr::BooleanLiteral { value: other, .. }) => {
+                value == other
+            }
+            (Expr::NumericLiteral { value, .. }, Expr::NumericLiteral { value: other, .. }) => {
+                value == other
+            }
+            (Expr::StringLiteral { value, .. }, Expr::StringLiteral { value: other, .. }) => {
+                value == other
+            }
+            (
+                Expr::Postfix { left, operator, .. },
+                Expr::Postfix {
+                    left: other_left,
+                    operator: other_operator,
+                    ..
+                },
+            ) => left == other_left && operator == other_operator,
+            (
+                Expr::Unary {
+                    operator, right, ..
+                },
+                Expr::Unary {
+                    operator: other_operator,
+                    right: other_right,
+                    ..
+                },
+            ) => operator == other_operator && right == other_right,
+            (Expr::Variable { name, .. }, Expr::Variable { name: other, .. }) => name == other,
+            (
+                Expr::Function { params, body, .. },
+                Expr::Function {
+                    params: other_params,
+                    body: other_body,
+                    ..
+                },
+            ) => params == other_params && body == other_body,
+            (
+                Expr::While {
+                    condition, body, ..
+                },
+                Expr::While {
+                    condition: other_condition,
+                    body: other_body,
+                    ..
+                },
+            ) => condition == other_condition && body == other_body,
+            (
+                Expr::Cond { arms, default, .. },
+                Expr::Cond {
+                    arms: other_arms,
+                    default: other_default,
+                    ..
+                },
+            ) => arms == other_arms && default == other_default,
+            (
+                Expr::Let {
+                    name, value, body, ..
+                },
+                Expr::Let {
+                    name: other_name,
+                    value: other_value,
+                    body: other_body,
+                    ..
+                },
+            ) => name == other_name && value == other_value && body == other_body,
+            (
+                Expr::Array { elements, .. },
+                Expr::Array {
+                    elements: other, ..
+                },
+            ) => elements == other,
+            (Expr::Record { fields, .. }, Expr::Record { fields: other, .. }) => fields == other,
+            (
+                Expr::Field { record, name, .. },
+                Expr::Field {
+                    record: other_record,
+                    name: other_name,
+                    ..
+                },
+            ) => record == other_record && name == other_name,
+            (
+                Expr::Index { target, index, .. },
+                Expr::Index {
+                    target: other_target,
+                    index: other_index,
+                    ..
+                },
+            ) => target == other_target && index == other_index,
+            (
+                Expr::Try {
+                    body,
+                    binding,
+                    handler,
+                    ..
+                },
+                Expr::Try {
+                    body: other_body,
+                    binding: other_binding,
+                    handler: other_handler,
+                    ..
+                },
+            ) => body == other_body && binding == other_binding && handler == other_handler,
+            #[cfg(feature = "complex")]
+            (Expr::ImaginaryLiteral { value, .. }, Expr::ImaginaryLiteral { value: other, .. }) => {
+                value == other
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Removes every `Grouping` from `expr`. The tree's shape already records how
+/// the parentheses grouped their contents, so the result evaluates the same,
+/// and formatting it writes only the parentheses that shape needs.
+pub fn strip_groupings(expr: Expr) -> Expr {
+    let strip = |expr: Box<Expr>| Box::new(strip_groupings(*expr));
+    match expr {
+        Expr::Grouping { expr, .. } => strip_groupings(*expr),
+        Expr::Binary {
+            left,
+            operator,
+            right,
+            span,
+        } => Expr::Binary {
+            left: strip(left),
+            operator,
+            right: strip(right),
+            span,
+        },
+        Expr::Call { callee, args, span } => Expr::Call {
+            callee: strip(callee),
+            args: args.into_iter().map(strip_groupings).collect(),
+            span,
+        },
+        Expr::Postfix {
+            left,
+            operator,
+            span,
+        } => Expr::Postfix {
+            left: strip(left),
+            operator,
+            span,
+        },
+        Expr::Unary {
+            operator,
+            right,
+            span,
+        } => Expr::Unary {
+            operator,
+            right: strip(right),
+            span,
+        },
+        Expr::Function { params, body, span } => Expr::Function {
+            params,
+            body: strip(body),
+            span,
+        },
+        Expr::While {
+            condition,
+            body,
+            span,
+        } => Expr::While {
+            condition: strip(condition),
+            body: strip(body),
+            span,
+        },
+        Expr::Cond {
+            arms,
+            default,
+            keyword,
+            span,
+        } => Expr::Cond {
+            arms: arms
+                .into_iter()
+                .map(|(guard, value)| (strip_groupings(guard), strip_groupings(value)))
+                .collect(),
+            default: default.map(strip),
+            keyword,
+            span,
+        },
+        Expr::Let {
+            name,
+            value,
+            body,
+            span,
+        } => Expr::Let {
+            name,
+            value: strip(value),
+            body: strip(body),
+            span,
+        },
+        Expr::Array { elements, span } => Expr::Array {
+            elements: elements.into_iter().map(strip_groupings).collect(),
+            span,
+        },
+        Expr::Record { fields, span } => Expr::Record {
+            fields: fields
+                .into_iter()
+                .map(|(name, value)| (name, strip_groupings(value)))
+                .collect(),
+            span,
+        },
+        Expr::Field { record, name, span } => Expr::Field {
+            record: strip(record),
+            name,
+            span,
+        },
+        Expr::Index {
+            target,
+            index,
+            span,
+        } => Expr::Index {
+            target: strip(target),
+            index: strip(index),
+            span,
+        },
+        Expr::Try {
+            body,
+            binding,
+            handler,
+            span,
+        } => Expr::Try {
+            body: strip(body),
+            binding,
+            handler: strip(handler),
+            span,
+        },
+        literal_or_variable => literal_or_variable,
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+pub enum Stmt {
+    // A later definition of the same name replaces the earlier binding.
+    NamedValue { name: String, expr: Expr },
+    Print { expr: Expr },
+    // An expression terminated by `;`, evaluated for its value.
+    Expr { expr: Expr },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+pub struct Program {
+    pub stmts: Vec<Stmt>,
+    // None when the program consists only of statements; it then evaluates to
+    // unit.
+    pub expr: Option<Expr>,
 }
 
 struct ExprInfo {
@@ -23,74 +513,686 @@ struct ExprInfo {
     used: usize,
 }
 
+struct StmtInfo {
+    stmt: Stmt,
+    used: usize,
+}
+
+/// A syntax error, with the span of the source it was found at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+impl ParseError {
+    /// Renders the error over the `source` it came from, with its span
+    /// underlined.
+    pub fn render(&self, source: &str) -> String {
+        render_span(&self.message, self.span, source)
+    }
+}
+
+// The error `message` at `tokens[current]`, or at the end of the input when
+// the tokens have run out.
+fn error_at(tokens: &Tokens, current: usize, message: impl Into<String>) -> Box<dyn Error> {
+    let span = match tokens.get(current) {
+        Some((_, span)) => *span,
+        None => tokens.last().map_or((0, 0), |(_, (_, end))| (*end, *end)),
+    };
+    ParseError {
+        message: message.into(),
+        span,
+    }
+    .into()
+}
+
 type ExprResult = Result<ExprInfo, Box<dyn Error>>;
-type Tokens = Vec<Token>;
+type StmtResult = Result<StmtInfo, Box<dyn Error>>;
+type Tokens = [(Token, Span)];
+
+pub fn parse(tokens: &Tokens) -> Result<Program, Box<dyn Error>> {
+    let mut stmts = vec![];
+    let mut used: usize = 0;
+    loop {
+        if !is_eos(tokens, used) && is_statement_start(&tokens[used].0) {
+            let stmt = statement(tokens, used)?;
+            used += stmt.used;
+            stmts.push(stmt.stmt);
+            continue;
+        }
 
-pub fn parse(tokens: &Tokens) -> Result<Expr, Box<dyn Error>> {
-    let root = expression(tokens, 0);
-    match root {
-        Ok(root) => Ok(root.expr),
-        Err(err) => Err(err),
+        if !stmts.is_empty() && is_eos(tokens, used) {
+            return Ok(Program { stmts, expr: None });
+        }
+
+        let root = expression(tokens, used)?;
+        used += root.used;
+        if is_eos(tokens, used) {
+            return Ok(Program {
+                stmts,
+                expr: Some(root.expr),
+            });
+        }
+        if tokens[used].0 != Token::Semicolon {
+            return Err(error_at(
+                tokens,
+                used,
+                format!("Unexpected token: {:?}", tokens[used].0),
+            ));
+        }
+        used += 1;
+        stmts.push(Stmt::Expr { expr: root.expr });
+    }
+}
+
+fn is_statement_start(token: &Token) -> bool {
+    matches!(token, Token::Def | Token::Print)
+}
+
+fn statement(tokens: &Tokens, current: usize) -> StmtResult {
+    match tokens[current].0 {
+        Token::Print => print_statement(tokens, current),
+        _ => named_value_definition(tokens, current),
+    }
+}
+
+// Statements are terminated by `;`, which may be left off the last statement
+// in the program.
+fn statement_end(tokens: &Tokens, current: usize) -> Result<usize, Box<dyn Error>> {
+    if is_eos(tokens, current) {
+        Ok(0)
+    } else {
+        expect(tokens, current, Token::Semicolon)?;
+        Ok(1)
     }
 }
 
+fn print_statement(tokens: &Tokens, current: usize) -> StmtResult {
+    let mut used: usize = 0;
+    expect(tokens, current + used, Token::Print)?;
+    used += 1;
+
+    let expr = expression(tokens, current + used)?;
+    used += expr.used;
+    used += statement_end(tokens, current + used)?;
+
+    Ok(StmtInfo {
+        stmt: Stmt::Print { expr: expr.expr },
+        used,
+    })
+}
+
 fn is_eos(tokens: &Tokens, current: usize) -> bool {
-    tokens.len() <= current || tokens[current] == Token::EOF
+    tokens.len() <= current || tokens[current].0 == Token::EOF
+}
+
+fn expect(tokens: &Tokens, current: usize, expected: Token) -> Result<(), Box<dyn Error>> {
+    if is_eos(tokens, current) {
+        Err(error_at(
+            tokens,
+            current,
+            format!("Expected {:?}, but found end of file", expected),
+        ))
+    } else if tokens[current].0 != expected {
+        Err(error_at(
+            tokens,
+            current,
+            format!(
+                "Expected {:?}, but found: {:?}",
+                expected, tokens[current].0
+            ),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn named_value_definition(tokens: &Tokens, current: usize) -> StmtResult {
+    let mut used: usize = 0;
+    expect(tokens, current + used, Token::Def)?;
+    used += 1;
+
+    let name = match tokens.get(current + used).map(|(token, _)| token) {
+        Some(Token::Identifier { name }) => name.clone(),
+        Some(token) if *token != Token::EOF => {
+            return Err(error_at(
+                tokens,
+                current + used,
+                format!("Expected a name after def, but found: {:?}", token),
+            ))
+        }
+        _ => {
+            return Err(error_at(
+                tokens,
+                current + used,
+                "Expected a name after def, but found end of file",
+            ))
+        }
+    };
+    used += 1;
+
+    // `def f a b = body` is shorthand for `def f = fn a, b { body }`.
+    let mut params = vec![];
+    while let Some((Token::Identifier { name }, _)) = tokens.get(current + used) {
+        params.push(name.clone());
+        used += 1;
+    }
+
+    expect(tokens, current + used, Token::Equal)?;
+    used += 1;
+
+    let expr = expression(tokens, current + used)?;
+    used += expr.used;
+    used += statement_end(tokens, current + used)?;
+
+    let expr = if params.is_empty() {
+        expr.expr
+    } else {
+        let span = expr.expr.span();
+        Expr::Function {
+            params,
+            body: Box::new(expr.expr),
+            span,
+        }
+    };
+
+    Ok(StmtInfo {
+        stmt: Stmt::NamedValue { name, expr },
+        used,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OperatorInfo {
+    pub precedence: u8,
+    pub associativity: Associativity,
+}
+
+// Binary operators understood by the parser. Higher precedence binds tighter.
+// Adding an operator, or changing how an existing one binds, only requires an
+// entry here.
+const OPERATORS: &[(Token, OperatorInfo)] = &[
+    (
+        Token::PipeGreater,
+        OperatorInfo {
+            precedence: 0,
+            associativity: Associativity::Left,
+        },
+    ),
+    (
+        Token::PipePipe,
+        OperatorInfo {
+            precedence: 1,
+            associativity: Associativity::Left,
+        },
+    ),
+    (
+        Token::AndAnd,
+        OperatorInfo {
+            precedence: 2,
+            associativity: Associativity::Left,
+        },
+    ),
+    (
+        Token::EqualEqual,
+        OperatorInfo {
+            precedence: 3,
+            associativity: Associativity::Left,
+        },
+    ),
+    (
+        Token::BangEqual,
+        OperatorInfo {
+            precedence: 3,
+            associativity: Associativity::Left,
+        },
+    ),
+    (
+        Token::Less,
+        OperatorInfo {
+            precedence: 3,
+            associativity: Associativity::Left,
+        },
+    ),
+    (
+        Token::LessEqual,
+        OperatorInfo {
+            precedence: 3,
+            associativity: Associativity::Left,
+        },
+    ),
+    (
+        Token::Greater,
+        OperatorInfo {
+            precedence: 3,
+            associativity: Associativity::Left,
+        },
+    ),
+    (
+        Token::GreaterEqual,
+        OperatorInfo {
+            precedence: 3,
+            associativity: Associativity::Left,
+        },
+    ),
+    (
+        Token::DotDot,
+        OperatorInfo {
+            precedence: 4,
+            associativity: Associativity::Left,
+        },
+    ),
+    (
+        Token::Plus,
+        OperatorInfo {
+            precedence: 5,
+            associativity: Associativity::Left,
+        },
+    ),
+    (
+        Token::Minus,
+        OperatorInfo {
+            precedence: 5,
+            associativity: Associativity::Left,
+        },
+    ),
+    (
+        Token::Astrix,
+        OperatorInfo {
+            precedence: 6,
+            associativity: Associativity::Left,
+        },
+    ),
+    (
+        Token::Slash,
+        OperatorInfo {
+            precedence: 6,
+            associativity: Associativity::Left,
+        },
+    ),
+    (
+        Token::SlashSlash,
+        OperatorInfo {
+            precedence: 6,
+            associativity: Associativity::Left,
+        },
+    ),
+    (
+        Token::Percent,
+        OperatorInfo {
+            precedence: 6,
+            associativity: Associativity::Left,
+        },
+    ),
+    (
+        Token::Caret,
+        OperatorInfo {
+            precedence: POWER_PRECEDENCE,
+            associativity: Associativity::Right,
+        },
+    ),
+];
+
+// `^` binds tighter than unary minus on its left, so `-2^2` is `-(2^2)`, but
+// its right operand may itself be negated, as in `2^-2`.
+pub(crate) const POWER_PRECEDENCE: u8 = 7;
+
+// The name of the grammar production for each precedence level of
+// `OPERATORS`, from the loosest binding.
+const LEVEL_NAMES: &[&str] = &[
+    "pipe",
+    "or",
+    "and",
+    "comparison",
+    "range",
+    "term",
+    "factor",
+    "power",
+];
+
+// The productions above and below the binary operators, which `grammar` fills
+// in from `OPERATORS`.
+const GRAMMAR_HEAD: &str = r#"program            -> statement+ expression? | expression ;
+statement          -> named_value | print | expression_stmt ;
+named_value        -> "def" IDENTIFIER IDENTIFIER* "=" expression ";" ;
+print              -> "print" expression ";" ;
+expression_stmt    -> expression ";" ;
+"#;
+
+const GRAMMAR_TAIL: &str = r#"postfix            -> call "%"* ;
+call               -> primary ( "(" arguments? ")" | "." IDENTIFIER | "[" expression "]" )* ;
+arguments          -> expression ( "," expression )* ;
+primary            -> literal | IDENTIFIER | "(" expression ")" | function | while
+                    | if | cond | let | try | array | record ;
+literal            -> NUMBER | STRING | "true" | "false" ;
+function           -> "fn" parameters? "{" expression "}" ;
+parameters         -> IDENTIFIER ( "," IDENTIFIER )* ;
+while              -> "while" expression "{" expression "}" ;
+if                 -> "if" expression "{" expression "}" "else" ( if | "{" expression "}" ) ;
+cond               -> "cond" "{" ( arm ( "," arm )* ( "," "else" "=>" expression )?
+                                 | "else" "=>" expression )? ","? "}" ;
+arm                -> expression "=>" expression ;
+let                -> "let" IDENTIFIER "=" expression "in" expression ;
+try                -> "try" expression "catch" IDENTIFIER "{" expression "}" ;
+array              -> "[" arguments? "]" ;
+record             -> "{" ( IDENTIFIER ":" expression ( "," IDENTIFIER ":" expression )* ","? )? "}" ;
+"#;
+
+/// The grammar of the language in EBNF. The productions for binary operators
+/// are generated from the parser's operator table, one per precedence level,
+/// so they always match how expressions are parsed. `docs/grammar.md` shows
+/// the same text.
+pub fn grammar() -> String {
+    let production = |name: &str, rule: String| format!("{:<18} -> {} ;\n", name, rule);
+    let levels = OPERATORS
+        .iter()
+        .map(|(_, info)| info.precedence)
+        .max()
+        .unwrap_or(0);
+    let mut text = String::from(GRAMMAR_HEAD);
+    text += &production("expression", LEVEL_NAMES[0].to_string());
+    for precedence in 0..=levels {
+        let name = LEVEL_NAMES[precedence as usize];
+        let operand = LEVEL_NAMES.get(precedence as usize + 1).unwrap_or(&"unary");
+        let operators: Vec<&Token> = OPERATORS
+            .iter()
+            .filter(|(_, info)| info.precedence == precedence)
+            .map(|(operator, _)| operator)
+            .collect();
+        let choice = operators
+            .iter()
+            .map(|operator| format!("\"{}\"", operator_text(operator)))
+            .collect::<Vec<_>>()
+            .join(" | ");
+        let choice = if operators.len() > 1 {
+            format!("( {} )", choice)
+        } else {
+            choice
+        };
+        let right_associative = binary_operator(operators[0])
+            .is_some_and(|info| info.associativity == Associativity::Right);
+        let rule = if right_associative {
+            format!("{} ( {} {} )?", operand, choice, name)
+        } else if operators.iter().all(|operator| is_comparison(operator)) {
+            // Comparisons, equality included, share a level and can't be
+            // chained, so `1 < 2 == true` needs parentheses.
+            format!("{} ( {} {} )?", operand, choice, operand)
+        } else {
+            format!("{} ( {} {} )*", operand, choice, operand)
+        };
+        text += &production(name, rule);
+    }
+    let power = LEVEL_NAMES[POWER_PRECEDENCE as usize];
+    text += &production("unary", format!("\"-\" {} | postfix", power));
+    text + GRAMMAR_TAIL
+}
+
+pub fn is_comparison(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::EqualEqual
+            | Token::BangEqual
+            | Token::Less
+            | Token::LessEqual
+            | Token::Greater
+            | Token::GreaterEqual
+    )
+}
+
+fn is_comparison_expr(expr: &Expr) -> bool {
+    match expr {
+        Expr::Binary { operator, .. } => is_comparison(operator),
+        _ => false,
+    }
+}
+
+pub fn binary_operator(token: &Token) -> Option<OperatorInfo> {
+    OPERATORS
+        .iter()
+        .find(|(operator, _)| operator == token)
+        .map(|(_, info)| *info)
 }
 
 fn expression(tokens: &Tokens, current: usize) -> ExprResult {
-    term(tokens, current)
+    binary(tokens, current, 0)
 }
 
-fn term(tokens: &Tokens, current: usize) -> ExprResult {
-    let fact = factor(tokens, current)?;
-    let mut expr = fact.expr;
-    let mut used = fact.used;
+// Pratt parser: parse an operand, then keep folding in operators that bind at
+// least as tightly as `min_precedence`. Every nested expression is parsed
+// through here, so this is where deeply nested input grows the stack.
+fn binary(tokens: &Tokens, current: usize, min_precedence: u8) -> ExprResult {
+    stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || {
+        let operand = unary(tokens, current)?;
+        binary_from(tokens, current, operand, min_precedence)
+    })
+}
+
+// The rest of `binary` after its first operand, which started at `current`.
+fn binary_from(
+    tokens: &Tokens,
+    current: usize,
+    operand: ExprInfo,
+    min_precedence: u8,
+) -> ExprResult {
+    let mut expr = operand.expr;
+    let mut used = operand.used;
 
     while !is_eos(tokens, current + used) {
-        match tokens[current + used] {
-            Token::Plus | Token::Minus => {
-                let operator = tokens[current + used].clone();
-                used += 1;
-                let fact = factor(tokens, current + used)?;
-                let right = fact.expr;
-                used += fact.used;
-                expr = Expr::Binary {
-                    left: Box::new(expr),
-                    right: Box::new(right),
-                    operator,
-                };
-            }
+        let info = match binary_operator(&tokens[current + used].0) {
+            Some(info) if info.precedence >= min_precedence => info,
             _ => {
                 break;
             }
+        };
+        let operator = tokens[current + used].0.clone();
+        used += 1;
+        let next_precedence = match info.associativity {
+            Associativity::Left => info.precedence + 1,
+            Associativity::Right => info.precedence,
+        };
+        let right = binary(tokens, current + used, next_precedence)?;
+        used += right.used;
+        // `1 < 2 < 3` would otherwise compare a bool with a number.
+        if is_comparison(&operator)
+            && (is_comparison_expr(&expr) || is_comparison_expr(&right.expr))
+        {
+            return Err(ParseError {
+                message: "Chained comparisons are not supported; use && instead".into(),
+                span: (expr.span().0, right.expr.span().1),
+            }
+            .into());
         }
+        let span = (expr.span().0, right.expr.span().1);
+        expr = if operator == Token::PipeGreater {
+            pipe(expr, right.expr, span)
+        } else {
+            Expr::Binary {
+                left: Box::new(expr),
+                right: Box::new(right.expr),
+                operator,
+                span,
+            }
+        };
     }
 
     Ok(ExprInfo { expr, used })
 }
 
-fn factor(tokens: &Tokens, current: usize) -> ExprResult {
-    let lit = primary(tokens, current)?;
-    let mut expr = lit.expr;
-    let mut used: usize = lit.used;
-    while !is_eos(tokens, current + used) {
-        match tokens[current + used] {
-            Token::Astrix | Token::Slash => {
-                let operator = tokens[current + used].clone();
+// `x |> f` is the call `f(x)`. When the right side is already a call, the
+// piped value becomes its first argument, so `x |> f(y)` is `f(x, y)`.
+fn pipe(value: Expr, function: Expr, span: Span) -> Expr {
+    match function {
+        Expr::Call {
+            callee, mut args, ..
+        } => {
+            args.insert(0, value);
+            Expr::Call { callee, args, span }
+        }
+        callee => Expr::Call {
+            callee: Box::new(callee),
+            args: vec![value],
+            span,
+        },
+    }
+}
+
+fn unary(tokens: &Tokens, current: usize) -> ExprResult {
+    if !is_eos(tokens, current) && tokens[current].0 == Token::Minus {
+        let right = binary(tokens, current + 1, POWER_PRECEDENCE)?;
+        let span = (tokens[current].1 .0, right.expr.span().1);
+        Ok(ExprInfo {
+            expr: Expr::Unary {
+                operator: Token::Minus,
+                right: Box::new(right.expr),
+                span,
+            },
+            used: right.used + 1,
+        })
+    } else {
+        postfix(tokens, current)
+    }
+}
+
+// Tokens that can begin an operand, other than unary minus.
+fn is_operand_start(token: &Token) -> bool {
+    #[cfg(feature = "complex")]
+    if let Token::ImaginaryLiteral { .. } = token {
+        return true;
+    }
+    matches!(
+        token,
+        Token::NumericLiteral { .. }
+            | Token::StringLiteral { .. }
+            | Token::Identifier { .. }
+            | Token::True
+            | Token::False
+            | Token::OpenParen
+            | Token::Fn
+            | Token::While
+            | Token::If
+            | Token::Cond
+            | Token::Let
+            | Token::Try
+            | Token::OpenBracket
+            | Token::OpenBrace
+    )
+}
+
+// A `%` is the binary modulo operator when an operand follows it and a
+// percentage (`5%` is `0.05`) otherwise. `x % -1` is therefore `(x%) - 1`;
+// write `x % (-1)` for a negative modulus.
+fn postfix(tokens: &Tokens, current: usize) -> ExprResult {
+    let operand = call(tokens, current)?;
+    postfix_from(tokens, current, operand)
+}
+
+fn postfix_from(tokens: &Tokens, current: usize, operand: ExprInfo) -> ExprResult {
+    let mut expr = operand.expr;
+    let mut used = operand.used;
+    while !is_eos(tokens, current + used)
+        && tokens[current + used].0 == Token::Percent
+        && (is_eos(tokens, current + used + 1) || !is_operand_start(&tokens[current + used + 1].0))
+    {
+        let span = (expr.span().0, tokens[current + used].1 .1);
+        used += 1;
+        expr = Expr::Postfix {
+            left: Box::new(expr),
+            operator: Token::Percent,
+            span,
+        };
+    }
+
+    Ok(ExprInfo { expr, used })
+}
+
+// Comma separated expressions up to, but not including, `close`.
+fn expression_list(
+    tokens: &Tokens,
+    current: usize,
+    close: &Token,
+) -> Result<(Vec<Expr>, usize), Box<dyn Error>> {
+    let mut used: usize = 0;
+    let mut exprs = vec![];
+    if is_eos(tokens, current) || tokens[current].0 != *close {
+        loop {
+            let expr = expression(tokens, current + used)?;
+            used += expr.used;
+            exprs.push(expr.expr);
+            if is_eos(tokens, current + used) || tokens[current + used].0 != Token::Comma {
+                break;
+            }
+            used += 1;
+        }
+    }
+    Ok((exprs, used))
+}
+
+// Calls, field accesses and indexing, which chain left to right:
+// `f(x).y[0](z)`.
+fn call(tokens: &Tokens, current: usize) -> ExprResult {
+    let prim = primary(tokens, current)?;
+    call_from(tokens, current, prim)
+}
+
+fn call_from(tokens: &Tokens, current: usize, prim: ExprInfo) -> ExprResult {
+    let mut expr = prim.expr;
+    let mut used = prim.used;
+    while let Some((token, _)) = tokens.get(current + used) {
+        match token {
+            Token::Dot => {
                 used += 1;
-                let lit = primary(tokens, current + used)?;
-                let right = lit.expr;
-                used += lit.used;
-                expr = Expr::Binary {
-                    left: Box::new(expr),
-                    right: Box::new(right),
-                    operator,
+                let name = field_name(tokens, current + used, "after '.'")?;
+                let span = (expr.span().0, tokens[current + used].1 .1);
+                used += 1;
+                expr = Expr::Field {
+                    record: Box::new(expr),
+                    name,
+                    span,
                 };
             }
-            _ => {
-                break;
+            Token::OpenParen => {
+                used += 1;
+                let (args, args_used) =
+                    expression_list(tokens, current + used, &Token::CloseParen)?;
+                used += args_used;
+                expect(tokens, current + used, Token::CloseParen)?;
+                let span = (expr.span().0, tokens[current + used].1 .1);
+                used += 1;
+                expr = Expr::Call {
+                    callee: Box::new(expr),
+                    args,
+                    span,
+                };
+            }
+            Token::OpenBracket => {
+                used += 1;
+                let index = expression(tokens, current + used)?;
+                used += index.used;
+                expect(tokens, current + used, Token::CloseBracket)?;
+                let span = (expr.span().0, tokens[current + used].1 .1);
+                used += 1;
+                expr = Expr::Index {
+                    target: Box::new(expr),
+                    index: Box::new(index.expr),
+                    span,
+                };
             }
+            _ => break,
         }
     }
 
@@ -99,104 +1201,643 @@ fn factor(tokens: &Tokens, current: usize) -> ExprResult {
 
 fn primary(tokens: &Tokens, current: usize) -> ExprResult {
     if is_eos(tokens, current) {
-        return Err("Unexpected end of file".into());
+        return Err(error_at(tokens, current, "Unexpected end of file"));
     }
 
-    match tokens[current] {
+    let span = tokens[current].1;
+    match tokens[current].0 {
         Token::NumericLiteral { .. } => literal(&tokens[current]),
+        #[cfg(feature = "complex")]
+        Token::ImaginaryLiteral { .. } => literal(&tokens[current]),
+        Token::StringLiteral { ref value } => Ok(ExprInfo {
+            expr: Expr::StringLiteral {
+                value: value.clone(),
+                span,
+            },
+            used: 1,
+        }),
+        Token::Fn => function(tokens, current),
+        Token::While => while_loop(tokens, current),
+        Token::If => if_expression(tokens, current),
+        Token::Cond => cond(tokens, current),
+        Token::Let => let_binding(tokens, current),
+        Token::Try => try_catch(tokens, current),
+        Token::OpenBrace if is_record_start(tokens, current) => record(tokens, current),
+        Token::OpenBracket => {
+            let mut used: usize = 1;
+            let (elements, elements_used) =
+                expression_list(tokens, current + used, &Token::CloseBracket)?;
+            used += elements_used;
+            expect(tokens, current + used, Token::CloseBracket)?;
+            Ok(ExprInfo {
+                expr: Expr::Array {
+                    elements,
+                    span: (span.0, tokens[current + used].1 .1),
+                },
+                used: used + 1,
+            })
+        }
+        Token::True | Token::False => Ok(ExprInfo {
+            expr: Expr::BooleanLiteral {
+                value: tokens[current].0 == Token::True,
+                span,
+            },
+            used: 1,
+        }),
+        Token::Identifier { ref name } if name == DISCARD => Err(error_at(
+            tokens,
+            current,
+            "'_' can only be used as a name to bind, not as a value",
+        )),
+        Token::Identifier { ref name } => Ok(ExprInfo {
+            expr: Expr::Variable {
+                name: name.clone(),
+                span,
+            },
+            used: 1,
+        }),
         Token::OpenParen => {
             let mut used: usize = 1;
             let expr = expression(tokens, current + used)?;
             used += expr.used;
             let expr = expr.expr;
-            match tokens[current + used] {
-                Token::CloseParen => Ok(ExprInfo {
-                    expr: Expr::Grouping {
-                        expr: Box::new(expr),
-                    },
-                    used: used + 1,
-                }),
-                _ => Err(format!(
-                    "Expected to find Close Parentheses, but found: {:?}",
-                    tokens[current + used]
-                )
-                .into()),
+            // Only when the `)` is missing, look for whether this `(` is ever
+            // closed, to tell an unclosed `(` from something else in the way.
+            if tokens.get(current + used).map(|(token, _)| token) != Some(&Token::CloseParen)
+                && check_parens(&tokens[current..]).err().map(|err| err.span) == Some(span)
+            {
+                // The span is the `(` itself, so a rendered error shows the
+                // line and column it was opened at.
+                return Err(ParseError {
+                    message: "Unclosed '('".into(),
+                    span,
+                }
+                .into());
             }
+            expect(tokens, current + used, Token::CloseParen)?;
+            Ok(ExprInfo {
+                expr: Expr::Grouping {
+                    expr: Box::new(expr),
+                    span: (span.0, tokens[current + used].1 .1),
+                },
+                used: used + 1,
+            })
         }
-        _ => Err(format!("Unexpected token: {:?}", tokens[current]).into()),
+        _ => Err(error_at(
+            tokens,
+            current,
+            format!("Unexpected token: {:?}", tokens[current].0),
+        )),
     }
 }
 
-fn literal(token: &Token) -> ExprResult {
-    match token {
-        Token::NumericLiteral { value } => Ok(ExprInfo {
-            expr: Expr::NumericLiteral {
-                value: value.to_string(),
-            },
-            used: 1,
-        }),
-        _ => Err(format!("Token not a literal: {:?}", token).into()),
-    }
+// A `{` starts a record when it is followed by `name:` or is empty, which
+// tells it apart from the braces of a function or loop body.
+fn is_record_start(tokens: &Tokens, current: usize) -> bool {
+    matches!(
+        (tokens.get(current + 1), tokens.get(current + 2)),
+        (Some((Token::CloseBrace, _)), _)
+            | (Some((Token::Identifier { .. }, _)), Some((Token::Colon, _)))
+    )
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::scanner::tokenize;
-
-    #[test]
-    fn parse_empty() {
-        let tokens: Vec<Token> = vec![];
-        let err = parse(&tokens).unwrap_err();
-        assert_eq!(format!("{}", err), String::from("Unexpected end of file"));
+fn field_name(tokens: &Tokens, current: usize, context: &str) -> Result<String, Box<dyn Error>> {
+    match tokens.get(current).map(|(token, _)| token) {
+        Some(Token::Identifier { name }) => Ok(name.clone()),
+        Some(token) if *token != Token::EOF => Err(error_at(
+            tokens,
+            current,
+            format!("Expected a field name {}, but found: {:?}", context, token),
+        )),
+        _ => Err(error_at(
+            tokens,
+            current,
+            format!("Expected a field name {}, but found end of file", context),
+        )),
     }
+}
 
-    #[test]
-    fn parse_number() {
-        let tokens: Vec<Token> = vec![
-            Token::NumericLiteral {
-                value: "123.345".into(),
-            },
-            Token::EOF,
-        ];
-        let ast = parse(&tokens).unwrap();
-        assert_eq!(
-            ast,
-            Expr::NumericLiteral {
-                value: "123.345".into()
-            }
-        );
+// `{ name: value, ... }`, with an optional trailing comma. A name may only
+// appear once.
+fn record(tokens: &Tokens, current: usize) -> ExprResult {
+    let start = tokens[current].1 .0;
+    let mut used: usize = 1;
+    let mut fields: Vec<(String, Expr)> = vec![];
+    while !is_eos(tokens, current + used) && tokens[current + used].0 != Token::CloseBrace {
+        let name = field_name(tokens, current + used, "in a record")?;
+        if fields.iter().any(|(field, _)| *field == name) {
+            return Err(error_at(
+                tokens,
+                current + used,
+                format!("Duplicate field in record: {}", name),
+            ));
+        }
+        used += 1;
+        expect(tokens, current + used, Token::Colon)?;
+        used += 1;
+        let value = expression(tokens, current + used)?;
+        used += value.used;
+        fields.push((name, value.expr));
+        if is_eos(tokens, current + used) || tokens[current + used].0 != Token::Comma {
+            break;
+        }
+        used += 1;
     }
+    expect(tokens, current + used, Token::CloseBrace)?;
+    let end = tokens[current + used].1 .1;
+    used += 1;
 
-    #[test]
-    fn addition_is_a_binary_operation() {
-        let tokens: Vec<Token> = vec![
-            Token::NumericLiteral {
-                value: "123.345".into(),
-            },
-            Token::Plus,
-            Token::NumericLiteral {
-                value: "1.0".into(),
-            },
-            Token::EOF,
-        ];
-        let ast = parse(&tokens).unwrap();
-        assert_eq!(
-            ast,
-            Expr::Binary {
-                left: Box::new(Expr::NumericLiteral {
-                    value: "123.345".into()
-                }),
-                right: Box::new(Expr::NumericLiteral {
-                    value: "1.0".into()
-                }),
-                operator: Token::Plus
-            }
-        );
+    Ok(ExprInfo {
+        expr: Expr::Record {
+            fields,
+            span: (start, end),
+        },
+        used,
+    })
+}
+
+// `fn a, b { body }`; a function without parameters is `fn { body }`.
+fn function(tokens: &Tokens, current: usize) -> ExprResult {
+    let start = tokens[current].1 .0;
+    let mut used: usize = 1;
+    let mut params = vec![];
+    while let Some((Token::Identifier { name }, _)) = tokens.get(current + used) {
+        params.push(name.clone());
+        used += 1;
+        if is_eos(tokens, current + used) || tokens[current + used].0 != Token::Comma {
+            break;
+        }
+        used += 1;
     }
 
-    #[test]
-    fn subtraction_is_a_binary_operation() {
+    expect(tokens, current + used, Token::OpenBrace)?;
+    used += 1;
+    let body = expression(tokens, current + used)?;
+    used += body.used;
+    expect(tokens, current + used, Token::CloseBrace)?;
+    let end = tokens[current + used].1 .1;
+    used += 1;
+
+    Ok(ExprInfo {
+        expr: Expr::Function {
+            params,
+            body: Box::new(body.expr),
+            span: (start, end),
+        },
+        used,
+    })
+}
+
+// `while condition { body }`
+fn while_loop(tokens: &Tokens, current: usize) -> ExprResult {
+    let start = tokens[current].1 .0;
+    let mut used: usize = 1;
+    let condition = expression(tokens, current + used)?;
+    used += condition.used;
+
+    expect(tokens, current + used, Token::OpenBrace)?;
+    used += 1;
+    let body = expression(tokens, current + used)?;
+    used += body.used;
+    expect(tokens, current + used, Token::CloseBrace)?;
+    let end = tokens[current + used].1 .1;
+    used += 1;
+
+    Ok(ExprInfo {
+        expr: Expr::While {
+            condition: Box::new(condition.expr),
+            body: Box::new(body.expr),
+            span: (start, end),
+        },
+        used,
+    })
+}
+
+// `if condition { value } else { other }`, which is a `cond` with one arm and
+// an `else`. `else if` adds another arm to the same `cond`. The `else` is
+// required, since an `if` always has a value.
+fn if_expression(tokens: &Tokens, current: usize) -> ExprResult {
+    let start = tokens[current].1 .0;
+    let mut used: usize = 0;
+    let mut arms = vec![];
+    let default = loop {
+        used += 1;
+        let condition = expression(tokens, current + used)?;
+        used += condition.used;
+        let value = block(tokens, current + used)?;
+        used += value.used;
+        arms.push((condition.expr, value.expr));
+
+        expect(tokens, current + used, Token::Else)?;
+        used += 1;
+        if tokens.get(current + used).map(|(token, _)| token) != Some(&Token::If) {
+            let default = block(tokens, current + used)?;
+            used += default.used;
+            break default.expr;
+        }
+    };
+    let end = tokens[current + used - 1].1 .1;
+
+    Ok(ExprInfo {
+        expr: Expr::Cond {
+            arms,
+            default: Some(Box::new(default)),
+            keyword: Token::If,
+            span: (start, end),
+        },
+        used,
+    })
+}
+
+// `{ expression }`, the body of an `if` or `else`.
+fn block(tokens: &Tokens, current: usize) -> ExprResult {
+    let mut used: usize = 0;
+    expect(tokens, current + used, Token::OpenBrace)?;
+    used += 1;
+    let expr = expression(tokens, current + used)?;
+    used += expr.used;
+    expect(tokens, current + used, Token::CloseBrace)?;
+    used += 1;
+    Ok(ExprInfo {
+        expr: expr.expr,
+        used,
+    })
+}
+
+// `cond { guard => value, ... }`, optionally ending with `else => default`.
+// Arms are separated by commas, and a trailing comma is allowed.
+fn cond(tokens: &Tokens, current: usize) -> ExprResult {
+    let start = tokens[current].1 .0;
+    let mut used: usize = 1;
+    expect(tokens, current + used, Token::OpenBrace)?;
+    used += 1;
+
+    let mut arms = vec![];
+    let mut default = None;
+    while !is_eos(tokens, current + used) && tokens[current + used].0 != Token::CloseBrace {
+        let is_default = tokens[current + used].0 == Token::Else;
+        let guard = if is_default {
+            used += 1;
+            None
+        } else {
+            let guard = expression(tokens, current + used)?;
+            used += guard.used;
+            Some(guard.expr)
+        };
+        expect(tokens, current + used, Token::EqualGreater)?;
+        used += 1;
+        let value = expression(tokens, current + used)?;
+        used += value.used;
+        match guard {
+            Some(guard) => arms.push((guard, value.expr)),
+            None => default = Some(Box::new(value.expr)),
+        }
+
+        let has_comma = !is_eos(tokens, current + used) && tokens[current + used].0 == Token::Comma;
+        if has_comma {
+            used += 1;
+        }
+        // The default arm must come last.
+        if is_default || !has_comma {
+            break;
+        }
+    }
+    expect(tokens, current + used, Token::CloseBrace)?;
+    let end = tokens[current + used].1 .1;
+    used += 1;
+
+    Ok(ExprInfo {
+        expr: Expr::Cond {
+            arms,
+            default,
+            keyword: Token::Cond,
+            span: (start, end),
+        },
+        used,
+    })
+}
+
+// `let name = value in body`. The body extends as far as an expression can, so
+// `let x = 1 in x + 1` is `2`.
+fn let_binding(tokens: &Tokens, current: usize) -> ExprResult {
+    let start = tokens[current].1 .0;
+    let mut used: usize = 1;
+    let name = match tokens.get(current + used).map(|(token, _)| token) {
+        Some(Token::Identifier { name }) => name.clone(),
+        Some(token) if *token != Token::EOF => {
+            return Err(error_at(
+                tokens,
+                current + used,
+                format!("Expected a name after let, but found: {:?}", token),
+            ))
+        }
+        _ => {
+            return Err(error_at(
+                tokens,
+                current + used,
+                "Expected a name after let, but found end of file",
+            ))
+        }
+    };
+    used += 1;
+
+    expect(tokens, current + used, Token::Equal)?;
+    used += 1;
+    let value = expression(tokens, current + used)?;
+    used += value.used;
+
+    expect(tokens, current + used, Token::In)?;
+    used += 1;
+    let body = expression(tokens, current + used)?;
+    used += body.used;
+    let end = body.expr.span().1;
+
+    Ok(ExprInfo {
+        expr: Expr::Let {
+            name,
+            value: Box::new(value.expr),
+            body: Box::new(body.expr),
+            span: (start, end),
+        },
+        used,
+    })
+}
+
+// `try body catch name { handler }`. The body extends as far as an expression
+// can, like a `let` body. `try(x)` without a `catch` is still a call of the
+// `try` built-in, so `try(1 / 0) + 1` keeps meaning `nil + 1`.
+fn try_catch(tokens: &Tokens, current: usize) -> ExprResult {
+    let start = tokens[current].1 .0;
+    let mut used: usize = 1;
+    if tokens.get(current + used).map(|(token, _)| token) != Some(&Token::OpenParen) {
+        let body = expression(tokens, current + used)?;
+        used += body.used;
+        return catch_clause(tokens, current, body.expr, used);
+    }
+    used += 1;
+    let (mut args, args_used) = expression_list(tokens, current + used, &Token::CloseParen)?;
+    used += args_used;
+    expect(tokens, current + used, Token::CloseParen)?;
+    let end = tokens[current + used].1 .1;
+    used += 1;
+    if args.len() != 1 || !catch_follows(tokens, current + used) {
+        let callee = Expr::Variable {
+            name: "try".into(),
+            span: tokens[current].1,
+        };
+        return Ok(ExprInfo {
+            expr: Expr::Call {
+                callee: Box::new(callee),
+                args,
+                span: (start, end),
+            },
+            used,
+        });
+    }
+    if tokens[current + used].0 == Token::Catch {
+        return catch_clause(tokens, current, args.remove(0), used);
+    }
+    // The parentheses only start the body, as in `try (x).y catch e { e }`, so
+    // carry on parsing the body from them rather than parsing them again.
+    let grouping = ExprInfo {
+        expr: Expr::Grouping {
+            expr: Box::new(args.remove(0)),
+            span: (tokens[current + 1].1 .0, end),
+        },
+        used: used - 1,
+    };
+    let body = call_from(tokens, current + 1, grouping)?;
+    let body = postfix_from(tokens, current + 1, body)?;
+    let body = binary_from(tokens, current + 1, body, 0)?;
+    catch_clause(tokens, current, body.expr, 1 + body.used)
+}
+
+// Whether a `catch` for the `try` being parsed comes after `current`, before
+// the expression it is in ends. Each `try` met on the way claims the next
+// `catch` first, as each `let` does the next `in`.
+fn catch_follows(tokens: &Tokens, current: usize) -> bool {
+    let mut depth: usize = 0;
+    let mut tries: usize = 0;
+    let mut lets: usize = 0;
+    for (token, _) in &tokens[current.min(tokens.len())..] {
+        match token {
+            Token::OpenParen | Token::OpenBracket | Token::OpenBrace => depth += 1,
+            Token::CloseParen | Token::CloseBracket | Token::CloseBrace if depth == 0 => {
+                return false
+            }
+            Token::CloseParen | Token::CloseBracket | Token::CloseBrace => depth -= 1,
+            Token::Comma | Token::Semicolon | Token::EqualGreater | Token::EOF if depth == 0 => {
+                return false
+            }
+            Token::Try if depth == 0 => tries += 1,
+            Token::Catch if depth == 0 && tries == 0 => return true,
+            Token::Catch if depth == 0 => tries -= 1,
+            Token::Let if depth == 0 => lets += 1,
+            Token::In if depth == 0 && lets == 0 => return false,
+            Token::In if depth == 0 => lets -= 1,
+            _ => {}
+        }
+    }
+    false
+}
+
+// The `catch e { handler }` after a try body, which ends `used` tokens after
+// the `try` at `current`.
+fn catch_clause(tokens: &Tokens, current: usize, body: Expr, mut used: usize) -> ExprResult {
+    let start = tokens[current].1 .0;
+    expect(tokens, current + used, Token::Catch)?;
+    used += 1;
+    let binding = match tokens.get(current + used).map(|(token, _)| token) {
+        Some(Token::Identifier { name }) => name.clone(),
+        Some(token) if *token != Token::EOF => {
+            return Err(error_at(
+                tokens,
+                current + used,
+                format!("Expected a name after catch, but found: {:?}", token),
+            ))
+        }
+        _ => {
+            return Err(error_at(
+                tokens,
+                current + used,
+                "Expected a name after catch, but found end of file",
+            ))
+        }
+    };
+    used += 1;
+    expect(tokens, current + used, Token::OpenBrace)?;
+    used += 1;
+    let handler = expression(tokens, current + used)?;
+    used += handler.used;
+    expect(tokens, current + used, Token::CloseBrace)?;
+    let end = tokens[current + used].1 .1;
+    used += 1;
+
+    Ok(ExprInfo {
+        expr: Expr::Try {
+            body: Box::new(body),
+            binding,
+            handler: Box::new(handler.expr),
+            span: (start, end),
+        },
+        used,
+    })
+}
+
+// Hexadecimal literals such as `0xFF` are integers; everything else, including
+// scientific notation, is read as an f64. Hex literals too large for an integer
+// are rounded to the nearest f64 rather than wrapped.
+fn parse_number(value: &str) -> Option<f64> {
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some("") => None,
+        Some(digits) => digits.chars().try_fold(0.0, |number: f64, digit| {
+            digit.to_digit(16).map(|digit| number * 16.0 + digit as f64)
+        }),
+        None => f64::from_str(value).ok(),
+    }
+}
+
+/// Splits a numeric literal into its number and its type suffix, if it has
+/// one: `("5", Some("_i64"))` for `5_i64`.
+pub fn split_number_suffix(literal: &str) -> (&str, Option<&str>) {
+    match literal.find('_') {
+        Some(start) => (&literal[..start], Some(&literal[start..])),
+        None => (literal, None),
+    }
+}
+
+// `_f64` makes the literal a float even when it is a whole number, and `_i64`
+// requires an integer in the i64 range, decimal or hex, written without a
+// fraction or exponent. The interpreter reads the integer from the text, see
+// `literal_integer`.
+fn literal_number(value: &str) -> Result<f64, String> {
+    let (digits, suffix) = split_number_suffix(value);
+    match suffix {
+        None | Some("_f64") => {
+            parse_number(digits).ok_or_else(|| format!("Invalid number: {}", value))
+        }
+        Some("_i64") => match literal_integer(value) {
+            Some(integer) => Ok(integer as f64),
+            None => Err(format!("Expected an i64 integer before _i64: {}", value)),
+        },
+        Some(suffix) => Err(format!("Unknown number suffix: {}", suffix)),
+    }
+}
+
+fn literal((token, span): &(Token, Span)) -> ExprResult {
+    match token {
+        Token::NumericLiteral { value } => Ok(ExprInfo {
+            expr: Expr::NumericLiteral {
+                value: value.to_string(),
+                number: literal_number(value).map_err(|message| ParseError {
+                    message,
+                    span: *span,
+                })?,
+                span: *span,
+            },
+            used: 1,
+        }),
+        #[cfg(feature = "complex")]
+        Token::ImaginaryLiteral { value } => match f64::from_str(value) {
+            Ok(number) => Ok(ExprInfo {
+                expr: Expr::ImaginaryLiteral {
+                    value: value.to_string(),
+                    number,
+                    span: *span,
+                },
+                used: 1,
+            }),
+            Err(_) => Err(ParseError {
+                message: format!("Invalid number: {}i", value),
+                span: *span,
+            }
+            .into()),
+        },
+        _ => Err(ParseError {
+            message: format!("Token not a literal: {:?}", token),
+            span: *span,
+        }
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::tokenize_with_spans;
+
+    // Hand-built token streams get one "byte" per token.
+    fn with_spans(tokens: Vec<Token>) -> Vec<(Token, Span)> {
+        tokens
+            .into_iter()
+            .enumerate()
+            .map(|(i, token)| (token, (i, i + 1)))
+            .collect()
+    }
+
+    #[test]
+    fn parse_empty() {
+        let tokens: Vec<Token> = vec![];
+        let err = parse(&with_spans(tokens)).unwrap_err();
+        assert_eq!(format!("{}", err), String::from("Unexpected end of file"));
+    }
+
+    #[test]
+    fn parse_number() {
+        let tokens: Vec<Token> = vec![
+            Token::NumericLiteral {
+                value: "123.345".into(),
+            },
+            Token::EOF,
+        ];
+        let ast = parse(&with_spans(tokens)).unwrap();
+        assert_eq!(
+            ast.expr.unwrap(),
+            Expr::NumericLiteral {
+                value: "123.345".into(),
+                number: 123.345,
+                span: (0, 0)
+            }
+        );
+    }
+
+    #[test]
+    fn addition_is_a_binary_operation() {
+        let tokens: Vec<Token> = vec![
+            Token::NumericLiteral {
+                value: "123.345".into(),
+            },
+            Token::Plus,
+            Token::NumericLiteral {
+                value: "1.0".into(),
+            },
+            Token::EOF,
+        ];
+        let ast = parse(&with_spans(tokens)).unwrap();
+        assert_eq!(
+            ast.expr.unwrap(),
+            Expr::Binary {
+                left: Box::new(Expr::NumericLiteral {
+                    value: "123.345".into(),
+                    number: 123.345,
+                    span: (0, 0)
+                }),
+                right: Box::new(Expr::NumericLiteral {
+                    value: "1.0".into(),
+                    number: 1.0,
+                    span: (0, 0)
+                }),
+                operator: Token::Plus,
+                span: (0, 0)
+            }
+        );
+    }
+
+    #[test]
+    fn subtraction_is_a_binary_operation() {
         let tokens: Vec<Token> = vec![
             Token::NumericLiteral {
                 value: "123.345".into(),
@@ -207,17 +1848,22 @@ mod tests {
             },
             Token::EOF,
         ];
-        let ast = parse(&tokens).unwrap();
+        let ast = parse(&with_spans(tokens)).unwrap();
         assert_eq!(
-            ast,
+            ast.expr.unwrap(),
             Expr::Binary {
                 left: Box::new(Expr::NumericLiteral {
-                    value: "123.345".into()
+                    value: "123.345".into(),
+                    number: 123.345,
+                    span: (0, 0)
                 }),
                 right: Box::new(Expr::NumericLiteral {
-                    value: "1.0".into()
+                    value: "1.0".into(),
+                    number: 1.0,
+                    span: (0, 0)
                 }),
-                operator: Token::Minus
+                operator: Token::Minus,
+                span: (0, 0)
             }
         );
     }
@@ -242,29 +1888,40 @@ mod tests {
             },
             Token::EOF,
         ];
-        let ast = parse(&tokens).unwrap();
+        let ast = parse(&with_spans(tokens)).unwrap();
         assert_eq!(
-            ast,
+            ast.expr.unwrap(),
             Expr::Binary {
                 left: Box::new(Expr::Binary {
                     left: Box::new(Expr::Binary {
                         left: Box::new(Expr::NumericLiteral {
-                            value: "123.345".into()
+                            value: "123.345".into(),
+                            number: 123.345,
+                            span: (0, 0)
                         }),
                         right: Box::new(Expr::NumericLiteral {
-                            value: "1.0".into()
+                            value: "1.0".into(),
+                            number: 1.0,
+                            span: (0, 0)
                         }),
-                        operator: Token::Plus
+                        operator: Token::Plus,
+                        span: (0, 0)
                     }),
                     right: Box::new(Expr::NumericLiteral {
-                        value: "1.345".into()
+                        value: "1.345".into(),
+                        number: 1.345,
+                        span: (0, 0)
                     }),
-                    operator: Token::Minus
+                    operator: Token::Minus,
+                    span: (0, 0)
                 }),
                 right: Box::new(Expr::NumericLiteral {
-                    value: "10.0".into()
+                    value: "10.0".into(),
+                    number: 10.0,
+                    span: (0, 0)
                 }),
-                operator: Token::Plus
+                operator: Token::Plus,
+                span: (0, 0)
             }
         );
     }
@@ -281,17 +1938,22 @@ mod tests {
             },
             Token::EOF,
         ];
-        let ast = parse(&tokens).unwrap();
+        let ast = parse(&with_spans(tokens)).unwrap();
         assert_eq!(
-            ast,
+            ast.expr.unwrap(),
             Expr::Binary {
                 left: Box::new(Expr::NumericLiteral {
-                    value: "123.345".into()
+                    value: "123.345".into(),
+                    number: 123.345,
+                    span: (0, 0)
                 }),
                 right: Box::new(Expr::NumericLiteral {
-                    value: "1.0".into()
+                    value: "1.0".into(),
+                    number: 1.0,
+                    span: (0, 0)
                 }),
-                operator: Token::Astrix
+                operator: Token::Astrix,
+                span: (0, 0)
             }
         );
     }
@@ -308,17 +1970,22 @@ mod tests {
             },
             Token::EOF,
         ];
-        let ast = parse(&tokens).unwrap();
+        let ast = parse(&with_spans(tokens)).unwrap();
         assert_eq!(
-            ast,
+            ast.expr.unwrap(),
             Expr::Binary {
                 left: Box::new(Expr::NumericLiteral {
-                    value: "123.345".into()
+                    value: "123.345".into(),
+                    number: 123.345,
+                    span: (0, 0)
                 }),
                 right: Box::new(Expr::NumericLiteral {
-                    value: "1.0".into()
+                    value: "1.0".into(),
+                    number: 1.0,
+                    span: (0, 0)
                 }),
-                operator: Token::Slash
+                operator: Token::Slash,
+                span: (0, 0)
             }
         );
     }
@@ -343,29 +2010,40 @@ mod tests {
             },
             Token::EOF,
         ];
-        let ast = parse(&tokens).unwrap();
+        let ast = parse(&with_spans(tokens)).unwrap();
         assert_eq!(
-            ast,
+            ast.expr.unwrap(),
             Expr::Binary {
                 left: Box::new(Expr::Binary {
                     left: Box::new(Expr::Binary {
                         left: Box::new(Expr::NumericLiteral {
-                            value: "123.345".into()
+                            value: "123.345".into(),
+                            number: 123.345,
+                            span: (0, 0)
                         }),
                         right: Box::new(Expr::NumericLiteral {
-                            value: "1.0".into()
+                            value: "1.0".into(),
+                            number: 1.0,
+                            span: (0, 0)
                         }),
-                        operator: Token::Astrix
+                        operator: Token::Astrix,
+                        span: (0, 0)
                     }),
                     right: Box::new(Expr::NumericLiteral {
-                        value: "1.345".into()
+                        value: "1.345".into(),
+                        number: 1.345,
+                        span: (0, 0)
                     }),
-                    operator: Token::Slash
+                    operator: Token::Slash,
+                    span: (0, 0)
                 }),
                 right: Box::new(Expr::NumericLiteral {
-                    value: "10.0".into()
+                    value: "10.0".into(),
+                    number: 10.0,
+                    span: (0, 0)
                 }),
-                operator: Token::Astrix
+                operator: Token::Astrix,
+                span: (0, 0)
             }
         );
     }
@@ -399,37 +2077,1361 @@ mod tests {
             },
             Token::EOF,
         ];
-        let ast = parse(&tokens).unwrap();
+        let ast = parse(&with_spans(tokens)).unwrap();
         assert_eq!(
-            ast,
+            ast.expr.unwrap(),
             Expr::Binary {
                 left: Box::new(Expr::Binary {
                     left: Box::new(Expr::NumericLiteral {
-                        value: "123.345".into()
+                        value: "123.345".into(),
+                        number: 123.345,
+                        span: (0, 0)
                     }),
                     right: Box::new(Expr::Binary {
                         left: Box::new(Expr::NumericLiteral {
-                            value: "1.0".into()
+                            value: "1.0".into(),
+                            number: 1.0,
+                            span: (0, 0)
                         }),
                         right: Box::new(Expr::NumericLiteral {
-                            value: "1.345".into()
+                            value: "1.345".into(),
+                            number: 1.345,
+                            span: (0, 0)
                         }),
                         operator: Token::Slash,
+                        span: (0, 0),
                     }),
                     operator: Token::Plus,
+                    span: (0, 0),
                 }),
                 right: Box::new(Expr::NumericLiteral {
-                    value: "10.0".into()
+                    value: "10.0".into(),
+                    number: 10.0,
+                    span: (0, 0)
                 }),
                 operator: Token::Minus,
+                span: (0, 0),
             }
         );
     }
 
     #[test]
     fn integrates_with_scanner() {
-        let tokens = tokenize("10 + 11").unwrap();
+        let tokens = tokenize_with_spans("10 + 11").unwrap();
         let ast = parse(&tokens).unwrap();
         println!("{:?}", ast);
     }
+
+    #[test]
+    fn parse_variable() {
+        let tokens = tokenize_with_spans("width * 2").unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(
+            ast.expr.unwrap(),
+            Expr::Binary {
+                left: Box::new(Expr::Variable {
+                    name: "width".into(),
+                    span: (0, 0)
+                }),
+                right: Box::new(Expr::NumericLiteral {
+                    value: "2".into(),
+                    number: 2.0,
+                    span: (0, 0)
+                }),
+                operator: Token::Astrix,
+                span: (0, 0),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_named_value_definitions() {
+        let tokens = tokenize_with_spans("def x = 1; def y = x + 1; y").unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(
+            ast,
+            Program {
+                stmts: vec![
+                    Stmt::NamedValue {
+                        name: "x".into(),
+                        expr: Expr::NumericLiteral {
+                            value: "1".into(),
+                            number: 1.0,
+                            span: (0, 0)
+                        },
+                    },
+                    Stmt::NamedValue {
+                        name: "y".into(),
+                        expr: Expr::Binary {
+                            left: Box::new(Expr::Variable {
+                                name: "x".into(),
+                                span: (0, 0)
+                            }),
+                            right: Box::new(Expr::NumericLiteral {
+                                value: "1".into(),
+                                number: 1.0,
+                                span: (0, 0)
+                            }),
+                            operator: Token::Plus,
+                            span: (0, 0),
+                        },
+                    },
+                ],
+                expr: Some(Expr::Variable {
+                    name: "y".into(),
+                    span: (0, 0)
+                }),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_definition_errors() {
+        struct Test {
+            source: &'static str,
+            expected: &'static str,
+        }
+        let tests = vec![
+            Test {
+                source: "def = 1; 1",
+                expected: "Expected a name after def, but found: Equal",
+            },
+            Test {
+                source: "def x 1; 1",
+                expected: "Expected Equal, but found: NumericLiteral { value: \"1\" }",
+            },
+            Test {
+                source: "def x = 1 2",
+                expected: "Expected Semicolon, but found: NumericLiteral { value: \"2\" }",
+            },
+        ];
+        for test in tests {
+            let tokens = tokenize_with_spans(test.source).unwrap();
+            let err = parse(&tokens).unwrap_err();
+            assert_eq!(format!("{}", err), test.expected);
+        }
+    }
+
+    #[test]
+    fn trailing_tokens_are_an_error() {
+        let tokens = tokenize_with_spans("1 2").unwrap();
+        let err = parse(&tokens).unwrap_err();
+        assert_eq!(
+            format!("{}", err),
+            "Unexpected token: NumericLiteral { value: \"2\" }"
+        );
+    }
+
+    #[test]
+    fn parse_calls() {
+        struct Test {
+            source: &'static str,
+            expected: Expr,
+        }
+        let tests = vec![
+            Test {
+                source: "f()",
+                expected: Expr::Call {
+                    callee: Box::new(Expr::Variable {
+                        name: "f".into(),
+                        span: (0, 0),
+                    }),
+                    args: vec![],
+                    span: (0, 0),
+                },
+            },
+            Test {
+                source: "try(1 / 0)",
+                expected: Expr::Call {
+                    callee: Box::new(Expr::Variable {
+                        name: "try".into(),
+                        span: (0, 0),
+                    }),
+                    args: vec![Expr::Binary {
+                        left: Box::new(Expr::NumericLiteral {
+                            value: "1".into(),
+                            number: 1.0,
+                            span: (0, 0),
+                        }),
+                        right: Box::new(Expr::NumericLiteral {
+                            value: "0".into(),
+                            number: 0.0,
+                            span: (0, 0),
+                        }),
+                        operator: Token::Slash,
+                        span: (0, 0),
+                    }],
+                    span: (0, 0),
+                },
+            },
+            Test {
+                source: "f(1, x)(2)",
+                expected: Expr::Call {
+                    callee: Box::new(Expr::Call {
+                        callee: Box::new(Expr::Variable {
+                            name: "f".into(),
+                            span: (0, 0),
+                        }),
+                        args: vec![
+                            Expr::NumericLiteral {
+                                value: "1".into(),
+                                number: 1.0,
+                                span: (0, 0),
+                            },
+                            Expr::Variable {
+                                name: "x".into(),
+                                span: (0, 0),
+                            },
+                        ],
+                        span: (0, 0),
+                    }),
+                    args: vec![Expr::NumericLiteral {
+                        value: "2".into(),
+                        number: 2.0,
+                        span: (0, 0),
+                    }],
+                    span: (0, 0),
+                },
+            },
+        ];
+        for test in tests {
+            let tokens = tokenize_with_spans(test.source).unwrap();
+            let ast = parse(&tokens).unwrap();
+            assert_eq!(ast.expr.unwrap(), test.expected);
+        }
+    }
+
+    #[test]
+    fn unclosed_call_is_an_error() {
+        let tokens = tokenize_with_spans("f(1, 2").unwrap();
+        let err = parse(&tokens).unwrap_err();
+        assert_eq!(
+            format!("{}", err),
+            "Expected CloseParen, but found end of file"
+        );
+    }
+
+    #[test]
+    fn operator_table() {
+        assert_eq!(
+            binary_operator(&Token::Plus),
+            Some(OperatorInfo {
+                precedence: 5,
+                associativity: Associativity::Left
+            })
+        );
+        assert_eq!(
+            binary_operator(&Token::Slash),
+            Some(OperatorInfo {
+                precedence: 6,
+                associativity: Associativity::Left
+            })
+        );
+        assert_eq!(binary_operator(&Token::OpenParen), None);
+    }
+
+    #[test]
+    fn mixed_precedence_matches_hand_written_levels() {
+        // 1 * 2 + 3 / 4 - 5 * 6
+        //              -
+        //           /     \
+        //          +       *
+        //        /   \    / \
+        //       *     /  5   6
+        //      / \   / \
+        //     1   2 3   4
+        let tokens = tokenize_with_spans("1 * 2 + 3 / 4 - 5 * 6").unwrap();
+        let ast = parse(&tokens).unwrap();
+        let number = |value: &str| {
+            Box::new(Expr::NumericLiteral {
+                value: value.into(),
+                number: value.parse().unwrap(),
+                span: (0, 0),
+            })
+        };
+        assert_eq!(
+            ast.expr.unwrap(),
+            Expr::Binary {
+                left: Box::new(Expr::Binary {
+                    left: Box::new(Expr::Binary {
+                        left: number("1"),
+                        right: number("2"),
+                        operator: Token::Astrix,
+                        span: (0, 0),
+                    }),
+                    right: Box::new(Expr::Binary {
+                        left: number("3"),
+                        right: number("4"),
+                        operator: Token::Slash,
+                        span: (0, 0),
+                    }),
+                    operator: Token::Plus,
+                    span: (0, 0),
+                }),
+                right: Box::new(Expr::Binary {
+                    left: number("5"),
+                    right: number("6"),
+                    operator: Token::Astrix,
+                    span: (0, 0),
+                }),
+                operator: Token::Minus,
+                span: (0, 0),
+            }
+        );
+    }
+
+    #[test]
+    fn pipe_is_a_call_with_the_lowest_precedence() {
+        let parse_source = |source: &str| parse(&tokenize_with_spans(source).unwrap()).unwrap();
+        for (source, call) in [
+            ("16 |> sqrt", "sqrt(16)"),
+            ("x |> f(1) |> g", "g(f(x, 1))"),
+            ("1 + 2 |> f", "f(1 + 2)"),
+            ("a || b |> f", "f(a || b)"),
+            ("x |> (y |> f)", "(f(y))(x)"),
+        ] {
+            assert_eq!(parse_source(source), parse_source(call), "{}", source);
+        }
+        let program = parse_source("x |> f");
+        assert_eq!(program.expr.unwrap().span(), (0, 6));
+    }
+
+    #[test]
+    fn grouping_overrides_operator_table() {
+        let tokens = tokenize_with_spans("(1 + 2) * 3").unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(
+            ast.expr.unwrap(),
+            Expr::Binary {
+                left: Box::new(Expr::Grouping {
+                    expr: Box::new(Expr::Binary {
+                        left: Box::new(Expr::NumericLiteral {
+                            value: "1".into(),
+                            number: 1.0,
+                            span: (0, 0)
+                        }),
+                        right: Box::new(Expr::NumericLiteral {
+                            value: "2".into(),
+                            number: 2.0,
+                            span: (0, 0)
+                        }),
+                        operator: Token::Plus,
+                        span: (0, 0),
+                    }),
+                    span: (0, 0),
+                }),
+                right: Box::new(Expr::NumericLiteral {
+                    value: "3".into(),
+                    number: 3.0,
+                    span: (0, 0)
+                }),
+                operator: Token::Astrix,
+                span: (0, 0),
+            }
+        );
+    }
+
+    #[test]
+    fn comparisons_bind_looser_than_arithmetic() {
+        let tokens = tokenize_with_spans("1 + 2 < 3 && true").unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(
+            ast.expr.unwrap(),
+            Expr::Binary {
+                left: Box::new(Expr::Binary {
+                    left: Box::new(Expr::Binary {
+                        left: Box::new(Expr::NumericLiteral {
+                            value: "1".into(),
+                            number: 1.0,
+                            span: (0, 0)
+                        }),
+                        right: Box::new(Expr::NumericLiteral {
+                            value: "2".into(),
+                            number: 2.0,
+                            span: (0, 0)
+                        }),
+                        operator: Token::Plus,
+                        span: (0, 0),
+                    }),
+                    right: Box::new(Expr::NumericLiteral {
+                        value: "3".into(),
+                        number: 3.0,
+                        span: (0, 0)
+                    }),
+                    operator: Token::Less,
+                    span: (0, 0),
+                }),
+                right: Box::new(Expr::BooleanLiteral {
+                    value: true,
+                    span: (0, 0)
+                }),
+                operator: Token::AndAnd,
+                span: (0, 0),
+            }
+        );
+    }
+
+    #[test]
+    fn chained_comparisons_are_an_error() {
+        for source in [
+            "1 < 2 < 3",
+            "1 == 2 == false",
+            "1 < 2 == true",
+            "3 >= 2 > 1",
+        ] {
+            let tokens = tokenize_with_spans(source).unwrap();
+            let err = parse(&tokens).unwrap_err();
+            assert_eq!(
+                format!("{}", err),
+                "Chained comparisons are not supported; use && instead"
+            );
+        }
+    }
+
+    #[test]
+    fn comparisons_joined_by_logical_operators_parse() {
+        for source in ["1 < 2 && 2 < 3", "1 == 1 || 2 != 2", "(1 < 2) == true"] {
+            let tokens = tokenize_with_spans(source).unwrap();
+            assert!(parse(&tokens).is_ok(), "{} should parse", source);
+        }
+    }
+
+    #[test]
+    fn parse_unary_minus() {
+        let tokens = tokenize_with_spans("-7 // 2").unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(
+            ast.expr.unwrap(),
+            Expr::Binary {
+                left: Box::new(Expr::Unary {
+                    operator: Token::Minus,
+                    right: Box::new(Expr::NumericLiteral {
+                        value: "7".into(),
+                        number: 7.0,
+                        span: (0, 0)
+                    }),
+                    span: (0, 0),
+                }),
+                right: Box::new(Expr::NumericLiteral {
+                    value: "2".into(),
+                    number: 2.0,
+                    span: (0, 0)
+                }),
+                operator: Token::SlashSlash,
+                span: (0, 0),
+            }
+        );
+    }
+
+    #[test]
+    fn floor_division_binds_like_multiplication() {
+        let tokens = tokenize_with_spans("1 + 7 // 2 * 3").unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(
+            ast.expr.unwrap(),
+            Expr::Binary {
+                left: Box::new(Expr::NumericLiteral {
+                    value: "1".into(),
+                    number: 1.0,
+                    span: (0, 0)
+                }),
+                right: Box::new(Expr::Binary {
+                    left: Box::new(Expr::Binary {
+                        left: Box::new(Expr::NumericLiteral {
+                            value: "7".into(),
+                            number: 7.0,
+                            span: (0, 0)
+                        }),
+                        right: Box::new(Expr::NumericLiteral {
+                            value: "2".into(),
+                            number: 2.0,
+                            span: (0, 0)
+                        }),
+                        operator: Token::SlashSlash,
+                        span: (0, 0),
+                    }),
+                    right: Box::new(Expr::NumericLiteral {
+                        value: "3".into(),
+                        number: 3.0,
+                        span: (0, 0)
+                    }),
+                    operator: Token::Astrix,
+                    span: (0, 0),
+                }),
+                operator: Token::Plus,
+                span: (0, 0),
+            }
+        );
+    }
+
+    #[test]
+    fn double_slash_is_not_a_comment() {
+        for source in ["// comment", "1 // ", "7 / / 2"] {
+            let tokens = tokenize_with_spans(source).unwrap();
+            assert!(parse(&tokens).is_err(), "{} should not parse", source);
+        }
+    }
+
+    #[test]
+    fn trailing_expression_is_optional() {
+        struct Test {
+            source: &'static str,
+            expected: Program,
+        }
+        let tests = vec![
+            Test {
+                source: "def x = 1; print x",
+                expected: Program {
+                    stmts: vec![
+                        Stmt::NamedValue {
+                            name: "x".into(),
+                            expr: Expr::NumericLiteral {
+                                value: "1".into(),
+                                number: 1.0,
+                                span: (0, 0),
+                            },
+                        },
+                        Stmt::Print {
+                            expr: Expr::Variable {
+                                name: "x".into(),
+                                span: (0, 0),
+                            },
+                        },
+                    ],
+                    expr: None,
+                },
+            },
+            Test {
+                source: "def x = 1;",
+                expected: Program {
+                    stmts: vec![Stmt::NamedValue {
+                        name: "x".into(),
+                        expr: Expr::NumericLiteral {
+                            value: "1".into(),
+                            number: 1.0,
+                            span: (0, 0),
+                        },
+                    }],
+                    expr: None,
+                },
+            },
+            Test {
+                source: "print 1; 2",
+                expected: Program {
+                    stmts: vec![Stmt::Print {
+                        expr: Expr::NumericLiteral {
+                            value: "1".into(),
+                            number: 1.0,
+                            span: (0, 0),
+                        },
+                    }],
+                    expr: Some(Expr::NumericLiteral {
+                        value: "2".into(),
+                        number: 2.0,
+                        span: (0, 0),
+                    }),
+                },
+            },
+        ];
+        for test in tests {
+            let tokens = tokenize_with_spans(test.source).unwrap();
+            let ast = parse(&tokens).unwrap();
+            assert_eq!(ast, test.expected, "{}", test.source);
+        }
+    }
+
+    #[test]
+    fn expressions_separated_by_semicolons_are_statements() {
+        let literal = |value: f64| Expr::NumericLiteral {
+            value: value.to_string(),
+            number: value,
+            span: (0, 0),
+        };
+        struct Test {
+            source: &'static str,
+            expected: Program,
+        }
+        let tests = vec![
+            Test {
+                source: "1; 2",
+                expected: Program {
+                    stmts: vec![Stmt::Expr { expr: literal(1.0) }],
+                    expr: Some(literal(2.0)),
+                },
+            },
+            Test {
+                source: "1; def x = 2; 3;",
+                expected: Program {
+                    stmts: vec![
+                        Stmt::Expr { expr: literal(1.0) },
+                        Stmt::NamedValue {
+                            name: "x".into(),
+                            expr: literal(2.0),
+                        },
+                        Stmt::Expr { expr: literal(3.0) },
+                    ],
+                    expr: None,
+                },
+            },
+        ];
+        for test in tests {
+            let tokens = tokenize_with_spans(test.source).unwrap();
+            let ast = parse(&tokens).unwrap();
+            assert_eq!(ast, test.expected, "{}", test.source);
+        }
+        let tokens = tokenize_with_spans("1 2").unwrap();
+        assert_eq!(
+            parse(&tokens).unwrap_err().to_string(),
+            "Unexpected token: NumericLiteral { value: \"2\" }"
+        );
+    }
+
+    #[test]
+    fn percent_is_postfix_without_a_following_operand() {
+        let tokens = tokenize_with_spans("200 * 5%").unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(
+            ast.expr.unwrap(),
+            Expr::Binary {
+                left: Box::new(Expr::NumericLiteral {
+                    value: "200".into(),
+                    number: 200.0,
+                    span: (0, 0)
+                }),
+                right: Box::new(Expr::Postfix {
+                    left: Box::new(Expr::NumericLiteral {
+                        value: "5".into(),
+                        number: 5.0,
+                        span: (0, 0)
+                    }),
+                    operator: Token::Percent,
+                    span: (0, 0),
+                }),
+                operator: Token::Astrix,
+                span: (0, 0),
+            }
+        );
+    }
+
+    #[test]
+    fn percent_is_modulo_before_an_operand() {
+        let tokens = tokenize_with_spans("7 % 3").unwrap();
+        let ast = parse(&tokens).unwrap();
+        assert_eq!(
+            ast.expr.unwrap(),
+            Expr::Binary {
+                left: Box::new(Expr::NumericLiteral {
+                    value: "7".into(),
+                    number: 7.0,
+                    span: (0, 0)
+                }),
+                right: Box::new(Expr::NumericLiteral {
+                    value: "3".into(),
+                    number: 3.0,
+                    span: (0, 0)
+                }),
+                operator: Token::Percent,
+                span: (0, 0),
+            }
+        );
+    }
+
+    #[test]
+    fn binary_span_covers_both_operands() {
+        let tokens = tokenize_with_spans("1 + 2 * 3").unwrap();
+        let ast = parse(&tokens).unwrap().expr.unwrap();
+        assert_eq!(ast.span(), (0, 9));
+        match ast {
+            Expr::Binary { right, .. } => {
+                assert_eq!(right.span(), (4, 9));
+            }
+            _ => panic!("Expected a binary expression, found {:?}", ast),
+        }
+    }
+
+    #[test]
+    fn expression_spans() {
+        struct Test {
+            source: &'static str,
+            expected: Span,
+        }
+        let tests = vec![
+            Test {
+                source: "  42 ",
+                expected: (2, 4),
+            },
+            Test {
+                source: "(1 + 2)",
+                expected: (0, 7),
+            },
+            Test {
+                source: "-x",
+                expected: (0, 2),
+            },
+            Test {
+                source: "5%",
+                expected: (0, 2),
+            },
+            Test {
+                source: "f(1, 2) ",
+                expected: (0, 7),
+            },
+            Test {
+                source: "def x = 1; true",
+                expected: (11, 15),
+            },
+        ];
+        for test in tests {
+            let tokens = tokenize_with_spans(test.source).unwrap();
+            let ast = parse(&tokens).unwrap().expr.unwrap();
+            assert_eq!(ast.span(), test.expected, "{}", test.source);
+        }
+    }
+
+    #[test]
+    fn equality_ignores_spans() {
+        let compact = tokenize_with_spans("1+2").unwrap();
+        let spaced = tokenize_with_spans("1   +   2").unwrap();
+        assert_eq!(parse(&compact).unwrap(), parse(&spaced).unwrap());
+    }
+
+    #[test]
+    fn malformed_numeric_literal_fails_to_parse() {
+        let tokens: Vec<Token> = vec![Token::NumericLiteral {
+            value: "1.2.3".into(),
+        }];
+        let err = parse(&with_spans(tokens)).unwrap_err();
+        assert_eq!(format!("{}", err), "Invalid number: 1.2.3");
+    }
+
+    #[test]
+    fn numeric_literal_is_parsed_once() {
+        let tokens = tokenize_with_spans("0.25").unwrap();
+        match parse(&tokens).unwrap().expr.unwrap() {
+            Expr::NumericLiteral { value, number, .. } => {
+                assert_eq!(value, "0.25");
+                assert_eq!(number, 0.25);
+            }
+            expr => panic!("Expected a numeric literal, found {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn unclosed_paren_reports_where_it_opened() {
+        struct Test {
+            source: &'static str,
+            expected: &'static str,
+            span: Span,
+        }
+        let tests = vec![
+            Test {
+                source: "(1 + 2",
+                expected: "Unclosed '('",
+                span: (0, 1),
+            },
+            Test {
+                source: "((1)",
+                expected: "Unclosed '('",
+                span: (0, 1),
+            },
+            Test {
+                source: "2 * (1 + (3)",
+                expected: "Unclosed '('",
+                span: (4, 5),
+            },
+            Test {
+                source: "(1 2)",
+                expected: "Expected CloseParen, but found: NumericLiteral { value: \"2\" }",
+                span: (3, 4),
+            },
+            Test {
+                source: "(1 2",
+                expected: "Unclosed '('",
+                span: (0, 1),
+            },
+            Test {
+                source: "(1 2) + (3",
+                expected: "Expected CloseParen, but found: NumericLiteral { value: \"2\" }",
+                span: (3, 4),
+            },
+        ];
+        for test in tests {
+            let tokens = tokenize_with_spans(test.source).unwrap();
+            let err = parse(&tokens).unwrap_err();
+            assert_eq!(format!("{}", err), test.expected, "{}", test.source);
+            let err = err.downcast_ref::<ParseError>().unwrap();
+            assert_eq!(err.span, test.span, "{}", test.source);
+        }
+    }
+
+    #[test]
+    fn parse_function_expressions() {
+        let variable = |name: &str| Expr::Variable {
+            name: name.into(),
+            span: (0, 0),
+        };
+        struct Test {
+            source: &'static str,
+            expected: Expr,
+        }
+        let tests = vec![
+            Test {
+                source: "fn a, b { a + b }",
+                expected: Expr::Function {
+                    params: vec!["a".into(), "b".into()],
+                    body: Box::new(Expr::Binary {
+                        left: Box::new(variable("a")),
+                        operator: Token::Plus,
+                        right: Box::new(variable("b")),
+                        span: (0, 0),
+                    }),
+                    span: (0, 0),
+                },
+            },
+            Test {
+                source: "fn { x }()",
+                expected: Expr::Call {
+                    callee: Box::new(Expr::Function {
+                        params: vec![],
+                        body: Box::new(variable("x")),
+                        span: (0, 0),
+                    }),
+                    args: vec![],
+                    span: (0, 0),
+                },
+            },
+        ];
+        for test in tests {
+            let tokens = tokenize_with_spans(test.source).unwrap();
+            let ast = parse(&tokens).unwrap();
+            assert_eq!(ast.expr.unwrap(), test.expected, "{}", test.source);
+        }
+        let tokens = tokenize_with_spans("fn a { a").unwrap();
+        assert_eq!(
+            parse(&tokens).unwrap_err().to_string(),
+            "Expected CloseBrace, but found end of file"
+        );
+    }
+
+    #[test]
+    fn def_shorthand_defines_a_function() {
+        let shorthand = tokenize_with_spans("def f a b = a + b;").unwrap();
+        let explicit = tokenize_with_spans("def f = fn a, b { a + b };").unwrap();
+        assert_eq!(parse(&shorthand).unwrap(), parse(&explicit).unwrap());
+
+        let without_params = tokenize_with_spans("def x = 1;").unwrap();
+        assert!(matches!(
+            &parse(&without_params).unwrap().stmts[0],
+            Stmt::NamedValue {
+                expr: Expr::NumericLiteral { .. },
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn parse_while_loop() {
+        let tokens = tokenize_with_spans("while x < 3 { f(x) }").unwrap();
+        let ast = parse(&tokens).unwrap().expr.unwrap();
+        match &ast {
+            Expr::While {
+                condition, body, ..
+            } => {
+                assert!(matches!(condition.as_ref(), Expr::Binary { .. }));
+                assert!(matches!(body.as_ref(), Expr::Call { .. }));
+            }
+            _ => panic!("Expected a while loop, found {:?}", ast),
+        }
+        assert_eq!(ast.span(), (0, 20));
+
+        let tokens = tokenize_with_spans("while true 1").unwrap();
+        assert_eq!(
+            parse(&tokens).unwrap_err().to_string(),
+            "Expected OpenBrace, but found: NumericLiteral { value: \"1\" }"
+        );
+    }
+
+    #[test]
+    fn radix_of_literal_arithmetic() {
+        let radix = |source: &str| {
+            let tokens = tokenize_with_spans(source).unwrap();
+            parse(&tokens).unwrap().expr.unwrap().radix()
+        };
+        assert_eq!(radix("0xFF"), Some(16));
+        assert_eq!(radix("-(0xFF + 0X1) * 0x2"), Some(16));
+        assert_eq!(radix("1.5e3 // 2"), Some(10));
+        assert_eq!(radix("0xFF + 1"), Some(16));
+        assert_eq!(radix("2 * (1 - 0x1)"), Some(16));
+        assert_eq!(radix("0xFF + x"), None);
+        assert_eq!(radix("abs(0xFF)"), None);
+    }
+
+    #[test]
+    fn truncated_token_streams_are_errors_not_panics() {
+        for source in [
+            "(",
+            "def f a b = fn x, y { [a, (b + x) * y] }(1, 2);",
+            "print -f(1)(2)%; def x = 3",
+            "let x = while a < 2 { g(a) } in x // 2 % 3 || y",
+            "[1, [2], fn { 3 }()] == 4 && 5 >= 6",
+        ] {
+            let tokens = tokenize_with_spans(source).unwrap();
+            for end in 0..tokens.len() {
+                for start in 0..=end {
+                    // Only a panic fails the test; errors are expected.
+                    let _ = parse(&tokens[start..end]);
+                }
+            }
+        }
+
+        // Deeply nested input grows the parser's stack rather than
+        // overflowing it, whether or not it is cut short.
+        let source = format!("{}-1{}", "(".repeat(12000), ")".repeat(12000));
+        let tokens = tokenize_with_spans(&source).unwrap();
+        assert!(parse(&tokens[..12002]).is_err());
+        assert!(parse(&tokens).is_ok());
+    }
+
+    #[test]
+    fn discard_is_not_a_value() {
+        for source in ["_", "def x = _ + 1;", "fn _ { _ }"] {
+            let tokens = tokenize_with_spans(source).unwrap();
+            assert_eq!(
+                parse(&tokens).unwrap_err().to_string(),
+                "'_' can only be used as a name to bind, not as a value",
+                "{}",
+                source
+            );
+        }
+        let tokens = tokenize_with_spans("def _ = 1; def f _ x = x; let _ = 2 in _x").unwrap();
+        assert!(parse(&tokens).is_ok());
+    }
+
+    #[test]
+    fn parse_let_expression() {
+        let tokens = tokenize_with_spans("let x = 2 in x * x").unwrap();
+        let ast = parse(&tokens).unwrap().expr.unwrap();
+        match &ast {
+            Expr::Let {
+                name, value, body, ..
+            } => {
+                assert_eq!(name, "x");
+                assert!(matches!(value.as_ref(), Expr::NumericLiteral { .. }));
+                assert!(matches!(body.as_ref(), Expr::Binary { .. }));
+            }
+            _ => panic!("Expected a let expression, found {:?}", ast),
+        }
+        assert_eq!(ast.span(), (0, 18));
+
+        let tokens = tokenize_with_spans("let x = 2 x").unwrap();
+        assert_eq!(
+            parse(&tokens).unwrap_err().to_string(),
+            "Expected In, but found: Identifier { name: \"x\" }"
+        );
+        let tokens = tokenize_with_spans("let 1 = 2 in 3").unwrap();
+        assert_eq!(
+            parse(&tokens).unwrap_err().to_string(),
+            "Expected a name after let, but found: NumericLiteral { value: \"1\" }"
+        );
+    }
+
+    #[test]
+    fn parse_cond_expression() {
+        let tokens = tokenize_with_spans("cond { x < 0 => -1, x > 0 => 1, else => 0, }").unwrap();
+        let ast = parse(&tokens).unwrap().expr.unwrap();
+        match &ast {
+            Expr::Cond { arms, default, .. } => {
+                assert_eq!(arms.len(), 2);
+                assert!(matches!(arms[0].0, Expr::Binary { .. }));
+                assert!(matches!(arms[0].1, Expr::Unary { .. }));
+                assert!(matches!(
+                    default.as_deref(),
+                    Some(Expr::NumericLiteral { .. })
+                ));
+            }
+            _ => panic!("Expected a cond expression, found {:?}", ast),
+        }
+        assert_eq!(ast.span(), (0, 44));
+
+        let tokens = tokenize_with_spans("cond { true => 1 }").unwrap();
+        assert!(matches!(
+            parse(&tokens).unwrap().expr,
+            Some(Expr::Cond { default: None, .. })
+        ));
+        let tokens = tokenize_with_spans("cond { else => 0, true => 1 }").unwrap();
+        assert_eq!(
+            parse(&tokens).unwrap_err().to_string(),
+            "Expected CloseBrace, but found: True"
+        );
+        let tokens = tokenize_with_spans("cond { true 1 }").unwrap();
+        assert_eq!(
+            parse(&tokens).unwrap_err().to_string(),
+            "Expected EqualGreater, but found: NumericLiteral { value: \"1\" }"
+        );
+    }
+
+    #[test]
+    fn if_is_a_cond_with_an_else() {
+        let parse_source = |source: &str| parse(&tokenize_with_spans(source).unwrap());
+        let ast = parse_source("if a { 1 } else if b { 2 } else { 3 }")
+            .unwrap()
+            .expr
+            .unwrap();
+        let cond = parse_source("cond { a => 1, b => 2, else => 3 }")
+            .unwrap()
+            .expr
+            .unwrap();
+        assert_eq!(ast, cond);
+        assert_eq!(ast.span(), (0, 37));
+        assert_eq!(
+            parse_source("if a { 1 }").unwrap_err().to_string(),
+            "Expected Else, but found end of file"
+        );
+        assert_eq!(
+            parse_source("if a { 1 } else 2").unwrap_err().to_string(),
+            "Expected OpenBrace, but found: NumericLiteral { value: \"2\" }"
+        );
+    }
+
+    #[test]
+    fn parse_try_catch_expression() {
+        let tokens = tokenize_with_spans("try f(x) + 1 catch e { e }").unwrap();
+        let ast = parse(&tokens).unwrap().expr.unwrap();
+        match &ast {
+            Expr::Try {
+                body,
+                binding,
+                handler,
+                ..
+            } => {
+                assert!(matches!(body.as_ref(), Expr::Binary { .. }));
+                assert_eq!(binding, "e");
+                assert!(matches!(handler.as_ref(), Expr::Variable { .. }));
+            }
+            _ => panic!("Expected a try expression, found {:?}", ast),
+        }
+        assert_eq!(ast.span(), (0, 26));
+
+        // A parenthesized body followed by `catch` is the body itself, and
+        // without one it is still a call of the `try` built-in.
+        let parse_expr = |source| {
+            let tokens = tokenize_with_spans(source).unwrap();
+            strip_groupings(parse(&tokens).unwrap().expr.unwrap())
+        };
+        assert_eq!(
+            parse_expr("try (1 / 0) catch e { 0 }"),
+            parse_expr("try 1 / 0 catch e { 0 }")
+        );
+        assert!(matches!(
+            parse_expr("try(1 / 0) + 1"),
+            Expr::Binary { ref left, .. } if matches!(left.as_ref(), Expr::Call { .. })
+        ));
+
+        let tokens = tokenize_with_spans("try 1 catch { 0 }").unwrap();
+        assert_eq!(
+            parse(&tokens).unwrap_err().to_string(),
+            "Expected a name after catch, but found: OpenBrace"
+        );
+        let tokens = tokenize_with_spans("try 1 + 2").unwrap();
+        assert_eq!(
+            parse(&tokens).unwrap_err().to_string(),
+            "Expected Catch, but found end of file"
+        );
+    }
+
+    #[test]
+    fn nested_try_parses_each_body_once() {
+        // Parsing a body again to look for its `catch` would double the work
+        // at every level of nesting.
+        for source in [
+            format!("{}1{}", "try(".repeat(40), ")".repeat(40)),
+            format!("{}1{}", "try (".repeat(40), ") catch e { 0 }".repeat(40)),
+            format!("{}1", "try(1) + ".repeat(40)),
+        ] {
+            let tokens = tokenize_with_spans(&source).unwrap();
+            assert!(parse(&tokens).is_ok(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn parse_indexing() {
+        let tokens = tokenize_with_spans("[1, 2][0]").unwrap();
+        let ast = parse(&tokens).unwrap().expr.unwrap();
+        assert!(matches!(
+            ast,
+            Expr::Index { ref target, ref index, .. }
+                if matches!(target.as_ref(), Expr::Array { .. })
+                    && matches!(index.as_ref(), Expr::NumericLiteral { .. })
+        ));
+        assert_eq!(ast.span(), (0, 9));
+
+        let tokens = tokenize_with_spans("try [1][5] catch e { e }").unwrap();
+        let ast = parse(&tokens).unwrap().expr.unwrap();
+        assert!(matches!(
+            ast,
+            Expr::Try { ref body, .. } if matches!(body.as_ref(), Expr::Index { .. })
+        ));
+
+        let tokens = tokenize_with_spans("x[1").unwrap();
+        assert_eq!(
+            parse(&tokens).unwrap_err().to_string(),
+            "Expected CloseBracket, but found end of file"
+        );
+    }
+
+    #[test]
+    fn parse_records_and_field_access() {
+        let number = |value: f64| Expr::NumericLiteral {
+            value: value.to_string(),
+            number: value,
+            span: (0, 0),
+        };
+        let tokens = tokenize_with_spans("{ x: 1, y: 2, }.x").unwrap();
+        let ast = parse(&tokens).unwrap().expr.unwrap();
+        assert_eq!(
+            ast,
+            Expr::Field {
+                record: Box::new(Expr::Record {
+                    fields: vec![("x".into(), number(1.0)), ("y".into(), number(2.0))],
+                    span: (0, 0),
+                }),
+                name: "x".into(),
+                span: (0, 0),
+            }
+        );
+        assert_eq!(ast.span(), (0, 17));
+
+        let tokens = tokenize_with_spans("f(p).q(1)").unwrap();
+        assert!(matches!(
+            parse(&tokens).unwrap().expr,
+            Some(Expr::Call { ref callee, .. }) if matches!(callee.as_ref(), Expr::Field { .. })
+        ));
+
+        for (source, error) in [
+            ("{ x: 1, x: 2 }", "Duplicate field in record: x"),
+            (
+                "{ x: 1 y: 2 }",
+                "Expected CloseBrace, but found: Identifier { name: \"y\" }",
+            ),
+            (
+                "p.1",
+                "Expected a field name after '.', but found: NumericLiteral { value: \"1\" }",
+            ),
+            (
+                "p.",
+                "Expected a field name after '.', but found end of file",
+            ),
+            ("{ x }", "Unexpected token: OpenBrace"),
+            (".5", "Unexpected token: Dot"),
+        ] {
+            let tokens = tokenize_with_spans(source).unwrap();
+            assert_eq!(parse(&tokens).unwrap_err().to_string(), error, "{}", source);
+        }
+    }
+
+    #[test]
+    fn grammar_has_a_production_per_precedence_level() {
+        let text = grammar();
+        let names: Vec<&str> = text
+            .lines()
+            .filter_map(|line| line.split_once(" -> "))
+            .map(|(name, _)| name.trim_end())
+            .collect();
+        for name in ["expression", "term", "factor", "power", "unary", "primary"] {
+            assert!(names.contains(&name), "{}", name);
+        }
+        for (operator, _) in OPERATORS {
+            let quoted = format!("\"{}\"", operator_text(operator));
+            assert!(text.contains(&quoted), "{}", quoted);
+        }
+        assert!(text.contains("term               -> factor ( ( \"+\" | \"-\" ) factor )* ;"));
+        assert!(text.contains("power              -> unary ( \"^\" power )? ;"));
+    }
+
+    #[test]
+    fn docs_show_the_current_grammar() {
+        let docs = include_str!("../docs/grammar.md");
+        assert!(docs.contains(&format!("```\n{}```\n", grammar())));
+    }
+
+    #[test]
+    fn a_sample_of_each_production_parses() {
+        let samples = [
+            ("program", "def x = 1; print x; x"),
+            ("statement", "print 1;"),
+            ("named_value", "def f x y = x + y;"),
+            ("print", "print 1;"),
+            ("expression_stmt", "1; 2"),
+            ("expression", "1"),
+            ("pipe", "x |> f |> g"),
+            ("or", "a || b || c"),
+            ("and", "a && b && c"),
+            ("comparison", "1 + 1 == 2 && 1 < 2"),
+            ("range", "0..n + 1"),
+            ("term", "1 + 2 - 3"),
+            ("factor", "1 * 2 / 3 // 4 % 5"),
+            ("power", "2 ^ -3 ^ 2"),
+            ("unary", "-x"),
+            ("postfix", "50%%"),
+            ("call", "f(1)(2).x.y()"),
+            ("arguments", "f(1, 2, 3)"),
+            ("primary", "(x)"),
+            ("literal", "\"text\""),
+            ("function", "fn { 1 }"),
+            ("parameters", "fn a, b { a }"),
+            ("while", "while false { 1 }"),
+            ("if", "if x < 0 { -1 } else if x > 0 { 1 } else { 0 }"),
+            ("cond", "cond { x => 1, else => 2, }"),
+            ("arm", "cond { x => 1 }"),
+            ("let", "let x = 1 in x"),
+            ("try", "try 1 / 0 catch e { e }"),
+            ("array", "[1, [2]]"),
+            ("record", "{ x: 1, y: 2, }"),
+        ];
+        let text = grammar();
+        for (name, _) in text.lines().filter_map(|line| line.split_once(" -> ")) {
+            let name = name.trim_end();
+            let sample = samples.iter().find(|(production, _)| *production == name);
+            let (_, source) = sample.unwrap_or_else(|| panic!("No sample for {}", name));
+            let tokens = tokenize_with_spans(source).unwrap();
+            assert!(parse(&tokens).is_ok(), "{}: {}", name, source);
+        }
+        for source in ["1 < 2 == true", "a == b != c", "1 < 2 < 3"] {
+            let tokens = tokenize_with_spans(source).unwrap();
+            assert!(parse(&tokens).is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn parse_errors_render_their_span() {
+        let render = |source: &str| {
+            let tokens = tokenize_with_spans(source).unwrap();
+            let err = parse(&tokens).unwrap_err();
+            err.downcast_ref::<ParseError>().unwrap().render(source)
+        };
+        assert_eq!(
+            render("def x = 1;\nx + * 2"),
+            "Unexpected token: Astrix\n2 | x + * 2\n  |     ^"
+        );
+        assert_eq!(
+            render("1 < 2 < 3"),
+            "Chained comparisons are not supported; use && instead\n1 | 1 < 2 < 3\n  | ^^^^^^^^^"
+        );
+        assert_eq!(
+            render("{ a: 1,\n  a: 2 }"),
+            "Duplicate field in record: a\n2 |   a: 2 }\n  |   ^"
+        );
+        assert_eq!(
+            render("5_u8 + 1"),
+            "Unknown number suffix: _u8\n1 | 5_u8 + 1\n  | ^^^^"
+        );
+        assert_eq!(render("(1 + 2"), "Unclosed '('\n1 | (1 + 2\n  | ^");
+        assert_eq!(
+            render("\"é\" +\n  2 * (1 + 2"),
+            "Unclosed '('\n2 |   2 * (1 + 2\n  |       ^"
+        );
+        assert_eq!(
+            render("(1 2)"),
+            "Expected CloseParen, but found: NumericLiteral { value: \"2\" }\n1 | (1 2)\n  |    ^"
+        );
+        assert_eq!(
+            render("f(1,"),
+            "Unexpected end of file\n1 | f(1,\n  |     ^"
+        );
+    }
+
+    #[test]
+    fn number_suffixes_are_checked() {
+        for (source, error) in [
+            ("5_u8", "Unknown number suffix: _u8"),
+            ("1_000", "Unknown number suffix: _000"),
+            ("5.5_i64", "Expected an i64 integer before _i64: 5.5_i64"),
+            ("1e3_i64", "Expected an i64 integer before _i64: 1e3_i64"),
+            (
+                "9223372036854775808_i64",
+                "Expected an i64 integer before _i64: 9223372036854775808_i64",
+            ),
+            (
+                "0x8000000000000000_i64",
+                "Expected an i64 integer before _i64: 0x8000000000000000_i64",
+            ),
+            ("0xFF_u8", "Unknown number suffix: _u8"),
+        ] {
+            let tokens = tokenize_with_spans(source).unwrap();
+            assert_eq!(parse(&tokens).unwrap_err().to_string(), error, "{}", source);
+        }
+    }
+
+    #[test]
+    fn parse_array_literals() {
+        let number = |value: f64| Expr::NumericLiteral {
+            value: value.to_string(),
+            number: value,
+            span: (0, 0),
+        };
+        let tokens = tokenize_with_spans("[1, [2], []]").unwrap();
+        let ast = parse(&tokens).unwrap().expr.unwrap();
+        assert_eq!(
+            ast,
+            Expr::Array {
+                elements: vec![
+                    number(1.0),
+                    Expr::Array {
+                        elements: vec![number(2.0)],
+                        span: (0, 0),
+                    },
+                    Expr::Array {
+                        elements: vec![],
+                        span: (0, 0),
+                    },
+                ],
+                span: (0, 0),
+            }
+        );
+        assert_eq!(ast.span(), (0, 12));
+
+        let tokens = tokenize_with_spans("[1, 2").unwrap();
+        assert_eq!(
+            parse(&tokens).unwrap_err().to_string(),
+            "Expected CloseBracket, but found end of file"
+        );
+    }
+
+    #[test]
+    fn strip_groupings_gives_the_canonical_tree() {
+        let parse_expr = |source: &str| {
+            let tokens = tokenize_with_spans(source).unwrap();
+            parse(&tokens).unwrap().expr.unwrap()
+        };
+        struct Test {
+            source: &'static str,
+            expected: &'static str,
+        }
+        let tests = [
+            Test {
+                source: "(1 + 2)",
+                expected: "1 + 2",
+            },
+            Test {
+                source: "((x))",
+                expected: "x",
+            },
+            Test {
+                source: "f((1), [(2)], fn a { (a) })",
+                expected: "f(1, [2], fn a { a })",
+            },
+            Test {
+                source: "-(x)% * (while (c) { (1) })",
+                expected: "-x% * while c { 1 }",
+            },
+        ];
+        for test in tests {
+            let stripped = strip_groupings(parse_expr(test.source));
+            assert_eq!(stripped, parse_expr(test.expected), "{}", test.source);
+        }
+        // The shape of the tree keeps the grouping's meaning.
+        let stripped = strip_groupings(parse_expr("(1 + 2) * 3"));
+        assert_ne!(stripped, parse_expr("1 + 2 * 3"));
+        assert_eq!(stripped.to_string(), "(1 + 2) * 3");
+    }
 }
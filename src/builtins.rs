@@ -0,0 +1,1257 @@
+use crate::interpreter::{
+    apply_function, compare_numbers, expect_number, Context, Function, RuntimeError, Value,
+    MAX_EXACT_INTEGER,
+};
+#[cfg(feature = "random")]
+use rand::Rng;
+use rpds::Vector;
+use std::cmp::Ordering;
+use std::error::Error;
+use std::ops::Range;
+use std::rc::Rc;
+
+pub(crate) type BuiltinFn = fn(&[Value], &Context) -> Result<Value, Box<dyn Error>>;
+
+/// A function available to every program without being defined. Its arguments
+/// are evaluated before it is called, and there are always `arity` of them.
+pub(crate) struct Builtin {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: BuiltinFn,
+}
+
+const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "abs",
+        arity: 1,
+        function: |args, _| match args[0] {
+            // Only `abs(i64::MIN)` is too large for an integer.
+            Value::Int(int) => Ok(int
+                .checked_abs()
+                .map_or(Value::Number((int as f64).abs()), Value::Int)),
+            _ => numeric("abs", &args[0], f64::abs),
+        },
+    },
+    Builtin {
+        name: "sqrt",
+        arity: 1,
+        function: |args, _| numeric("sqrt", &args[0], f64::sqrt),
+    },
+    Builtin {
+        name: "round_to",
+        arity: 2,
+        function: |args, _| {
+            let x = expect_number(&args[0], "round_to")?;
+            let digits = expect_number(&args[1], "round_to")?;
+            if digits < 0.0 || digits.fract() != 0.0 {
+                return Err(RuntimeError::new(format!(
+                    "round_to expects a non-negative whole number of digits, got {}",
+                    Value::Number(digits)
+                ))
+                .into());
+            }
+            Ok(Value::Number(round_to(x, digits)))
+        },
+    },
+    Builtin {
+        name: "gcd",
+        arity: 2,
+        function: |args, _| {
+            let a = expect_integer(&args[0], "gcd")?;
+            let b = expect_integer(&args[1], "gcd")?;
+            Ok(Value::Int(gcd(a, b)))
+        },
+    },
+    Builtin {
+        name: "lcm",
+        arity: 2,
+        function: |args, _| {
+            let a = expect_integer(&args[0], "lcm")?;
+            let b = expect_integer(&args[1], "lcm")?;
+            if a == 0 || b == 0 {
+                return Ok(Value::Int(0));
+            }
+            // Both operands are below 2^53, so their product fits in an i128.
+            let lcm = ((a / gcd(a, b)) as i128 * b as i128).abs();
+            if lcm as f64 > MAX_EXACT_INTEGER {
+                return Err(RuntimeError::new("Integer overflow").into());
+            }
+            Ok(Value::Int(lcm as i64))
+        },
+    },
+    Builtin {
+        name: "modpow",
+        arity: 3,
+        function: |args, _| {
+            let base = expect_integer(&args[0], "modpow")?;
+            let exponent = expect_integer(&args[1], "modpow")?;
+            let modulus = expect_integer(&args[2], "modpow")?;
+            if exponent < 0 {
+                return Err(RuntimeError::new(format!(
+                    "modpow expects a non-negative exponent, got {}",
+                    exponent
+                ))
+                .into());
+            }
+            if modulus == 0 {
+                return Err(RuntimeError::new("Modulo by zero").into());
+            }
+            Ok(Value::Int(modpow(base, exponent, modulus)))
+        },
+    },
+    Builtin {
+        name: "format",
+        arity: 2,
+        function: |args, _| {
+            let value = expect_integer(&args[0], "format")?;
+            let base = expect_integer(&args[1], "format")?;
+            let (sign, magnitude) = if value < 0 {
+                ("-", -value)
+            } else {
+                ("", value)
+            };
+            let digits = match base {
+                2 => format!("{:#b}", magnitude),
+                8 => format!("{:#o}", magnitude),
+                10 => magnitude.to_string(),
+                16 => format!("{:#x}", magnitude),
+                _ => {
+                    return Err(RuntimeError::new(format!(
+                        "format expects a base of 2, 8, 10 or 16, got {}",
+                        base
+                    ))
+                    .into())
+                }
+            };
+            Ok(Value::String(format!("{}{}", sign, digits)))
+        },
+    },
+    Builtin {
+        name: "defined",
+        arity: 1,
+        function: |args, ctx| {
+            Ok(Value::Bool(
+                ctx.is_defined(expect_string(&args[0], "defined")?),
+            ))
+        },
+    },
+    Builtin {
+        name: "env",
+        arity: 1,
+        function: |args, ctx| {
+            let name = expect_string(&args[0], "env")?;
+            Ok(ctx.env_var(name).map_or(Value::Nil, Value::String))
+        },
+    },
+    Builtin {
+        name: "now",
+        arity: 0,
+        function: |_, ctx| Ok(Value::Number(ctx.now())),
+    },
+    #[cfg(feature = "random")]
+    Builtin {
+        name: "random",
+        arity: 0,
+        function: |_, ctx| Ok(Value::Number(ctx.rng().gen())),
+    },
+    #[cfg(feature = "random")]
+    Builtin {
+        name: "random_range",
+        arity: 2,
+        function: |args, ctx| {
+            let low = expect_integer(&args[0], "random_range")?;
+            let high = expect_integer(&args[1], "random_range")?;
+            if low >= high {
+                return Err(RuntimeError::new(format!(
+                    "random_range expects low < high, got {} and {}",
+                    low, high
+                ))
+                .into());
+            }
+            Ok(Value::Int(ctx.rng().gen_range(low..high)))
+        },
+    },
+    Builtin {
+        name: "sum",
+        arity: 1,
+        function: |args, _| match args[0] {
+            Value::Range { start, end } => Ok(range_sum(start, end)),
+            _ => Ok(Value::Number(numbers("sum", &args[0])?.iter().sum())),
+        },
+    },
+    Builtin {
+        name: "average",
+        arity: 1,
+        function: |args, _| {
+            let numbers = numbers("average", &args[0])?;
+            if numbers.is_empty() {
+                return Err(RuntimeError::new("average of an empty array").into());
+            }
+            Ok(Value::Number(
+                numbers.iter().sum::<f64>() / numbers.len() as f64,
+            ))
+        },
+    },
+    Builtin {
+        name: "min",
+        arity: 1,
+        function: |args, _| extremum("min", &args[0], Ordering::Less),
+    },
+    Builtin {
+        name: "max",
+        arity: 1,
+        function: |args, _| extremum("max", &args[0], Ordering::Greater),
+    },
+    Builtin {
+        name: "clamp",
+        arity: 3,
+        function: |args, _| {
+            let x = expect_number(&args[0], "clamp")?;
+            let low = expect_number(&args[1], "clamp")?;
+            let high = expect_number(&args[2], "clamp")?;
+            // `f64::clamp` panics on a NaN bound.
+            if low.is_nan() || high.is_nan() {
+                return Err(RuntimeError::new(format!(
+                    "clamp expects bounds that are not NaN, got {} and {}",
+                    Value::Number(low),
+                    Value::Number(high)
+                ))
+                .into());
+            }
+            if low > high {
+                return Err(RuntimeError::new(format!(
+                    "clamp expects low <= high, got {} and {}",
+                    Value::Number(low),
+                    Value::Number(high)
+                ))
+                .into());
+            }
+            Ok(Value::Number(x.clamp(low, high)))
+        },
+    },
+    Builtin {
+        name: "len",
+        arity: 1,
+        function: |args, _| match &args[0] {
+            Value::Array(elements) => Ok(Value::Int(elements.len() as i64)),
+            Value::String(string) => Ok(Value::Int(string.chars().count() as i64)),
+            Value::Range { start, end } => Ok(Value::Int(end.saturating_sub(*start).max(0))),
+            other => Err(RuntimeError::new(format!(
+                "len expects an array, a string or a range, got {}",
+                other.type_name()
+            ))
+            .into()),
+        },
+    },
+    Builtin {
+        name: "contains",
+        arity: 2,
+        function: |args, _| {
+            Ok(Value::Bool(
+                position("contains", &args[0], &args[1])?.is_some(),
+            ))
+        },
+    },
+    Builtin {
+        name: "index_of",
+        arity: 2,
+        function: |args, _| {
+            let index = position("index_of", &args[0], &args[1])?;
+            Ok(Value::Int(index.map_or(-1, |index| index as i64)))
+        },
+    },
+    Builtin {
+        name: "upper",
+        arity: 1,
+        function: |args, _| {
+            Ok(Value::String(
+                expect_string(&args[0], "upper")?.to_uppercase(),
+            ))
+        },
+    },
+    Builtin {
+        name: "lower",
+        arity: 1,
+        function: |args, _| {
+            Ok(Value::String(
+                expect_string(&args[0], "lower")?.to_lowercase(),
+            ))
+        },
+    },
+    Builtin {
+        name: "trim",
+        arity: 1,
+        function: |args, _| {
+            Ok(Value::String(
+                expect_string(&args[0], "trim")?.trim().into(),
+            ))
+        },
+    },
+    Builtin {
+        name: "substring",
+        arity: 3,
+        function: |args, _| {
+            let string = expect_string(&args[0], "substring")?;
+            let length = string.chars().count();
+            let range = index_range(
+                "substring",
+                &args[1..],
+                length,
+                &format!("a string of {} characters", length),
+            )?;
+            Ok(Value::String(
+                string.chars().skip(range.start).take(range.len()).collect(),
+            ))
+        },
+    },
+    Builtin {
+        name: "split",
+        arity: 2,
+        function: |args, _| {
+            let string = expect_string(&args[0], "split")?;
+            let separator = expect_string(&args[1], "split")?;
+            // An empty separator splits into characters rather than putting an
+            // empty string at each end.
+            let parts: Vector<Value> = if separator.is_empty() {
+                string
+                    .chars()
+                    .map(|c| Value::String(c.to_string()))
+                    .collect()
+            } else {
+                string
+                    .split(separator)
+                    .map(|part| Value::String(part.into()))
+                    .collect()
+            };
+            Ok(Value::Array(parts))
+        },
+    },
+    Builtin {
+        name: "join",
+        arity: 2,
+        function: |args, _| {
+            let separator = expect_string(&args[1], "join")?;
+            let parts = expect_array(&args[0], "join")?
+                .iter()
+                .map(|element| match element {
+                    Value::String(part) => Ok(part.as_str()),
+                    other => Err(RuntimeError::new(format!(
+                        "join expects an array of strings, found {}",
+                        other.type_name()
+                    ))),
+                })
+                .collect::<Result<Vec<&str>, _>>()?;
+            Ok(Value::String(parts.join(separator)))
+        },
+    },
+    Builtin {
+        name: "type_of",
+        arity: 1,
+        function: |args, _| Ok(Value::String(args[0].type_name().to_lowercase())),
+    },
+    Builtin {
+        name: "to_number",
+        arity: 1,
+        function: |args, _| match &args[0] {
+            number @ (Value::Number(_) | Value::Int(_)) => Ok(number.clone()),
+            value => {
+                let string = expect_string(value, "to_number")?;
+                if let Ok(int) = string.trim().parse::<i64>() {
+                    return Ok(Value::Int(int));
+                }
+                // Rust also parses "inf" and "NaN", which are not numbers a
+                // program could have written.
+                match string.trim().parse::<f64>() {
+                    Ok(number) if number.is_finite() => Ok(Value::Number(number)),
+                    _ => Err(RuntimeError::new(format!(
+                        "to_number expects a numeric string, got \"{}\"",
+                        string
+                    ))
+                    .into()),
+                }
+            }
+        },
+    },
+    Builtin {
+        name: "to_string",
+        arity: 1,
+        function: |args, _| Ok(Value::String(args[0].to_string())),
+    },
+    Builtin {
+        name: "push",
+        arity: 2,
+        function: |args, _| {
+            let elements = expect_array(&args[0], "push")?;
+            Ok(Value::Array(elements.push_back(args[1].clone())))
+        },
+    },
+    Builtin {
+        name: "concat",
+        arity: 2,
+        function: |args, _| {
+            let mut elements = expect_array(&args[0], "concat")?.clone();
+            for element in expect_array(&args[1], "concat")? {
+                elements.push_back_mut(element.clone());
+            }
+            Ok(Value::Array(elements))
+        },
+    },
+    Builtin {
+        name: "reverse",
+        arity: 1,
+        function: |args, _| {
+            let elements = expect_array(&args[0], "reverse")?;
+            Ok(Value::Array(elements.iter().rev().cloned().collect()))
+        },
+    },
+    Builtin {
+        name: "first",
+        arity: 1,
+        function: |args, _| match expect_array(&args[0], "first")?.first() {
+            Some(element) => Ok(element.clone()),
+            None => Err(RuntimeError::new("first of an empty array").into()),
+        },
+    },
+    Builtin {
+        name: "last",
+        arity: 1,
+        function: |args, _| match expect_array(&args[0], "last")?.last() {
+            Some(element) => Ok(element.clone()),
+            None => Err(RuntimeError::new("last of an empty array").into()),
+        },
+    },
+    Builtin {
+        name: "slice",
+        arity: 3,
+        function: |args, _| {
+            let elements = expect_array(&args[0], "slice")?;
+            let range = index_range(
+                "slice",
+                &args[1..],
+                elements.len(),
+                &format!("an array of {} elements", elements.len()),
+            )?;
+            Ok(Value::Array(
+                elements
+                    .iter()
+                    .skip(range.start)
+                    .take(range.len())
+                    .cloned()
+                    .collect(),
+            ))
+        },
+    },
+    Builtin {
+        name: "sort",
+        arity: 1,
+        function: |args, _| sort(expect_array(&args[0], "sort")?),
+    },
+    Builtin {
+        name: "map",
+        arity: 2,
+        function: |args, ctx| {
+            let function = expect_function(&args[0], "map")?;
+            let mut mapped = Vector::new();
+            for element in expect_array(&args[1], "map")? {
+                mapped.push_back_mut(apply_function(function, vec![element.clone()], ctx)?);
+            }
+            Ok(Value::Array(mapped))
+        },
+    },
+    Builtin {
+        name: "filter",
+        arity: 2,
+        function: |args, ctx| {
+            let predicate = expect_function(&args[0], "filter")?;
+            let mut kept = Vector::new();
+            for element in expect_array(&args[1], "filter")? {
+                match apply_function(predicate, vec![element.clone()], ctx)? {
+                    Value::Bool(true) => kept.push_back_mut(element.clone()),
+                    Value::Bool(false) => {}
+                    other => {
+                        return Err(RuntimeError::new(format!(
+                            "filter expects the predicate to return a Bool, got {}",
+                            other.type_name()
+                        ))
+                        .into())
+                    }
+                }
+            }
+            Ok(Value::Array(kept))
+        },
+    },
+    Builtin {
+        name: "fold",
+        arity: 3,
+        function: |args, ctx| {
+            let function = expect_function(&args[0], "fold")?;
+            let mut accumulator = args[1].clone();
+            for element in expect_array(&args[2], "fold")? {
+                accumulator = apply_function(function, vec![accumulator, element.clone()], ctx)?;
+            }
+            Ok(accumulator)
+        },
+    },
+];
+
+pub(crate) fn lookup(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+fn numeric(name: &str, value: &Value, function: fn(f64) -> f64) -> Result<Value, Box<dyn Error>> {
+    Ok(Value::Number(function(expect_number(value, name)?)))
+}
+
+// The smallest or largest element of a non-empty array of numbers or range,
+// depending on whether `wanted` is `Less` or `Greater`. Like `f64::min`, NaN
+// is only picked when every element is NaN.
+fn extremum(name: &str, value: &Value, wanted: Ordering) -> Result<Value, Box<dyn Error>> {
+    if let Value::Range { start, end } = *value {
+        if start >= end {
+            return Err(RuntimeError::new(format!("{} of an empty range", name)).into());
+        }
+        let last = end - 1;
+        return Ok(Value::Int(if wanted == Ordering::Less {
+            start
+        } else {
+            last
+        }));
+    }
+    // Checks that every element is a number.
+    numbers(name, value)?;
+    let mut best: Option<&Value> = None;
+    for element in expect_array(value, name)? {
+        match best {
+            Some(current)
+                if !is_nan(current) && compare_numbers(element, current) != Some(wanted) => {}
+            _ => best = Some(element),
+        }
+    }
+    best.cloned()
+        .ok_or_else(|| RuntimeError::new(format!("{} of an empty array", name)).into())
+}
+
+// The sum of the whole numbers in `start..end`, without visiting each one.
+fn range_sum(start: i64, end: i64) -> Value {
+    if start >= end {
+        return Value::Int(0);
+    }
+    let (start, end) = (start as i128, end as i128);
+    let sum = (start + end - 1) * (end - start) / 2;
+    i64::try_from(sum).map_or(Value::Number(sum as f64), Value::Int)
+}
+
+// Always non-negative; `gcd(0, 0)` is 0.
+fn gcd(a: i64, b: i64) -> i64 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+// `base` to the power `exponent`, modulo `modulus`, by repeated squaring. The
+// result is between 0 and `|modulus|`, whatever the signs of `base` and
+// `modulus`.
+fn modpow(base: i64, mut exponent: i64, modulus: i64) -> i64 {
+    let modulus = modulus.abs() as i128;
+    let mut base = (base as i128).rem_euclid(modulus);
+    let mut result = 1 % modulus;
+    while exponent > 0 {
+        if exponent % 2 == 1 {
+            result = result * base % modulus;
+        }
+        base = base * base % modulus;
+        exponent /= 2;
+    }
+    result as i64
+}
+
+// Rounds half away from zero on the decimal digits of the scaled value, so the
+// result can differ from the written decimal when it has no exact binary form:
+// 1.005 is stored as 1.00499999..., and rounds to 1. When scaling would
+// overflow, `x` already has no more digits than asked for and is returned as is.
+fn round_to(x: f64, digits: f64) -> f64 {
+    let factor = 10f64.powf(digits);
+    let scaled = x * factor;
+    if !scaled.is_finite() {
+        return x;
+    }
+    scaled.round() / factor
+}
+
+// Where `needle` first occurs in `haystack`: the index of the first equal
+// element of an array, or the character index of the first occurrence of a
+// substring.
+fn position(
+    fn_name: &str,
+    haystack: &Value,
+    needle: &Value,
+) -> Result<Option<usize>, Box<dyn Error>> {
+    match (haystack, needle) {
+        (Value::Array(elements), needle) => {
+            Ok(elements.iter().position(|element| element == needle))
+        }
+        (Value::String(string), Value::String(substring)) => Ok(string
+            .find(substring.as_str())
+            .map(|byte_index| string[..byte_index].chars().count())),
+        (Value::String(_), other) => Err(RuntimeError::new(format!(
+            "{} expects a string to search for in a string, got {}",
+            fn_name,
+            other.type_name()
+        ))
+        .into()),
+        (other, _) => Err(RuntimeError::new(format!(
+            "{} expects an array or a string, got {}",
+            fn_name,
+            other.type_name()
+        ))
+        .into()),
+    }
+}
+
+// Sorts stably in ascending order. Elements must all be numbers or all be
+// strings; strings are ordered by their characters' code points.
+fn sort(elements: &Vector<Value>) -> Result<Value, Box<dyn Error>> {
+    let mut sorted: Vec<Value> = elements.iter().cloned().collect();
+    let first = sorted.first().map(Value::type_name);
+    if let Some(other) = sorted
+        .iter()
+        .find(|element| Some(element.type_name()) != first)
+    {
+        return Err(RuntimeError::new(format!(
+            "sort expects elements of one type, found {} and {}",
+            first.unwrap_or_default(),
+            other.type_name()
+        ))
+        .into());
+    }
+    match sorted.first() {
+        None | Some(Value::Number(_) | Value::Int(_) | Value::String(_)) => {}
+        Some(other) => {
+            return Err(RuntimeError::new(format!(
+                "sort expects numbers or strings, got {}",
+                other.type_name()
+            ))
+            .into())
+        }
+    }
+    // NaN sorts after every other number.
+    sorted.sort_by(|left, right| match (left, right) {
+        (Value::String(left), Value::String(right)) => left.cmp(right),
+        _ => compare_numbers(left, right).unwrap_or_else(|| is_nan(left).cmp(&is_nan(right))),
+    });
+    Ok(Value::Array(sorted.into_iter().collect()))
+}
+
+fn is_nan(value: &Value) -> bool {
+    matches!(value, Value::Number(number) if number.is_nan())
+}
+
+// Unwraps a whole number small enough to be exact, i.e. an integer.
+fn expect_integer(value: &Value, fn_name: &str) -> Result<i64, Box<dyn Error>> {
+    let number = expect_number(value, fn_name)?;
+    if number.fract() != 0.0 || number.abs() > MAX_EXACT_INTEGER {
+        return Err(RuntimeError::new(format!(
+            "{} expects an integer, got {}",
+            fn_name,
+            Value::Number(number)
+        ))
+        .into());
+    }
+    Ok(number as i64)
+}
+
+fn expect_string<'a>(value: &'a Value, fn_name: &str) -> Result<&'a str, Box<dyn Error>> {
+    match value {
+        Value::String(string) => Ok(string),
+        other => Err(RuntimeError::new(format!(
+            "{} expects a string, got {}",
+            fn_name,
+            other.type_name()
+        ))
+        .into()),
+    }
+}
+
+fn expect_array<'a>(value: &'a Value, fn_name: &str) -> Result<&'a Vector<Value>, Box<dyn Error>> {
+    match value {
+        Value::Array(elements) => Ok(elements),
+        other => Err(RuntimeError::new(format!(
+            "{} expects an array, got {}",
+            fn_name,
+            other.type_name()
+        ))
+        .into()),
+    }
+}
+
+fn expect_function<'a>(
+    value: &'a Value,
+    fn_name: &str,
+) -> Result<&'a Rc<Function>, Box<dyn Error>> {
+    match value {
+        Value::Function(function) => Ok(function),
+        other => Err(RuntimeError::new(format!(
+            "{} expects a function, got {}",
+            fn_name,
+            other.type_name()
+        ))
+        .into()),
+    }
+}
+
+// The range given by the `start` and `end` arguments of a function taking part
+// of a sequence of `length` items, described as `sequence` in errors. Both
+// must be whole numbers, with `start <= end <= length`.
+fn index_range(
+    fn_name: &str,
+    bounds: &[Value],
+    length: usize,
+    sequence: &str,
+) -> Result<Range<usize>, Box<dyn Error>> {
+    let start = expect_number(&bounds[0], fn_name)?;
+    let end = expect_number(&bounds[1], fn_name)?;
+    if start.fract() != 0.0 || end.fract() != 0.0 {
+        return Err(RuntimeError::new(format!(
+            "{} expects whole number indices, got {} and {}",
+            fn_name,
+            Value::Number(start),
+            Value::Number(end)
+        ))
+        .into());
+    }
+    if start < 0.0 || start > end || end > length as f64 {
+        return Err(RuntimeError::new(format!(
+            "{} range {}..{} is out of bounds for {}",
+            fn_name,
+            Value::Number(start),
+            Value::Number(end),
+            sequence
+        ))
+        .into());
+    }
+    Ok(start as usize..end as usize)
+}
+
+// The elements of an array that must contain only numbers.
+fn numbers(fn_name: &str, value: &Value) -> Result<Vec<f64>, Box<dyn Error>> {
+    expect_array(value, fn_name)?
+        .iter()
+        .map(|element| {
+            element.as_f64().ok_or_else(|| {
+                RuntimeError::new(format!(
+                    "{} expects an array of numbers, found {}",
+                    fn_name,
+                    element.type_name()
+                ))
+                .into()
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::Engine;
+    use crate::interpreter::Value;
+
+    fn eval(source: &str) -> Result<Value, String> {
+        Engine::new().eval(source).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn to_number_and_to_string_convert() {
+        assert_eq!(eval("to_number(\"3.5\")"), Ok(Value::Number(3.5)));
+        assert_eq!(eval("to_number(\" -2e3 \")"), Ok(Value::Number(-2000.0)));
+        assert_eq!(eval("to_number(7)"), Ok(Value::Number(7.0)));
+        assert_eq!(eval("to_string(42)"), Ok(Value::String("42".into())));
+        assert_eq!(eval("to_string(0.5)"), Ok(Value::String("0.5".into())));
+        assert_eq!(eval("to_string(\"hi\")"), Ok(Value::String("hi".into())));
+        assert_eq!(
+            eval("to_string([1, true])"),
+            Ok(Value::String("[1, true]".into()))
+        );
+        assert_eq!(eval("to_number(to_string(1.25))"), Ok(Value::Number(1.25)));
+    }
+
+    #[test]
+    fn to_number_rejects_non_numeric_strings() {
+        for source in ["\"abc\"", "\"\"", "\"NaN\"", "\"inf\"", "\"1e400\""] {
+            let string = &source[1..source.len() - 1];
+            assert_eq!(
+                eval(&format!("to_number({})", source)),
+                Err(format!(
+                    "to_number expects a numeric string, got \"{}\"",
+                    string
+                ))
+            );
+        }
+        assert_eq!(
+            eval("to_number(true)"),
+            Err("to_number expects a string, got Bool".into())
+        );
+    }
+
+    #[test]
+    fn env_expects_a_name() {
+        assert_eq!(
+            eval("env(1)"),
+            Err("env expects a string, got Number".into())
+        );
+    }
+
+    #[test]
+    fn defined_checks_for_a_binding() {
+        assert_eq!(eval("defined(\"x\")"), Ok(Value::Bool(false)));
+        assert_eq!(eval("def x = 1; defined(\"x\")"), Ok(Value::Bool(true)));
+        assert_eq!(
+            eval("def f n = defined(\"n\"); [f(1), defined(\"n\")]"),
+            eval("[true, false]")
+        );
+        assert_eq!(eval("let y = 2 in defined(\"y\")"), Ok(Value::Bool(true)));
+        assert_eq!(eval("defined(\"sqrt\")"), Ok(Value::Bool(false)));
+        assert_eq!(eval("defined(x)"), Err("Undefined variable: x".into()));
+        assert_eq!(
+            eval("defined(1)"),
+            Err("defined expects a string, got Number".into())
+        );
+    }
+
+    #[test]
+    fn round_to_digits() {
+        assert_eq!(eval("round_to(1.23456, 2)"), Ok(Value::Number(1.23)));
+        assert_eq!(eval("round_to(1.23456, 3)"), Ok(Value::Number(1.235)));
+        assert_eq!(eval("round_to(3.5, 0)"), Ok(Value::Number(4.0)));
+        assert_eq!(eval("round_to(-3.5, 0)"), Ok(Value::Number(-4.0)));
+        assert_eq!(eval("round_to(1.005, 2)"), Ok(Value::Number(1.0)));
+        assert_eq!(eval("round_to(1.5, 400)"), Ok(Value::Number(1.5)));
+        assert_eq!(
+            eval("round_to(1, -1)"),
+            Err("round_to expects a non-negative whole number of digits, got -1".into())
+        );
+        assert_eq!(
+            eval("round_to(1, 1.5)"),
+            Err("round_to expects a non-negative whole number of digits, got 1.5".into())
+        );
+    }
+
+    #[test]
+    fn gcd_and_lcm() {
+        assert_eq!(eval("gcd(12, 8)"), Ok(Value::Number(4.0)));
+        assert_eq!(eval("gcd(-12, 8)"), Ok(Value::Number(4.0)));
+        assert_eq!(eval("gcd(0, 5)"), Ok(Value::Number(5.0)));
+        assert_eq!(eval("lcm(4, 6)"), Ok(Value::Number(12.0)));
+        assert_eq!(
+            eval("lcm(9007199254740991, 9007199254740990)"),
+            Err("Integer overflow".into())
+        );
+        assert_eq!(
+            eval("lcm(-4503599627370496, 3)"),
+            Err("Integer overflow".into())
+        );
+        assert_eq!(
+            eval("lcm(3002399751580330, 3)"),
+            Ok(Value::Number(9007199254740990.0))
+        );
+        assert_eq!(eval("lcm(-4, 6)"), Ok(Value::Number(12.0)));
+        assert_eq!(eval("lcm(0, 6)"), Ok(Value::Number(0.0)));
+        assert_eq!(
+            eval("lcm(9007199254740991, 2)"),
+            Err("Integer overflow".into())
+        );
+        assert_eq!(
+            eval("gcd(1.5, 3)"),
+            Err("gcd expects an integer, got 1.5".into())
+        );
+        assert_eq!(
+            eval("gcd(1e20, 3)"),
+            Err("gcd expects an integer, got 100000000000000000000".into())
+        );
+    }
+
+    #[test]
+    fn modpow_of_integers() {
+        assert_eq!(eval("modpow(2, 10, 1000)"), Ok(Value::Number(24.0)));
+        assert_eq!(eval("modpow(3, 0, 7)"), Ok(Value::Number(1.0)));
+        assert_eq!(eval("modpow(3, 0, 1)"), Ok(Value::Number(0.0)));
+        assert_eq!(eval("modpow(-2, 3, 5)"), Ok(Value::Number(2.0)));
+        assert_eq!(
+            eval("modpow(123456789, 987654321, 1000000007)"),
+            Ok(Value::Number(652541198.0))
+        );
+        assert_eq!(eval("modpow(2, 10, 0)"), Err("Modulo by zero".into()));
+        assert_eq!(
+            eval("modpow(2, -1, 5)"),
+            Err("modpow expects a non-negative exponent, got -1".into())
+        );
+    }
+
+    #[test]
+    fn format_integers_in_a_base() {
+        let string = |s: &str| Ok(Value::String(s.into()));
+        assert_eq!(eval("format(255, 16)"), string("0xff"));
+        assert_eq!(eval("format(8, 2)"), string("0b1000"));
+        assert_eq!(eval("format(8, 8)"), string("0o10"));
+        assert_eq!(eval("format(255, 10)"), string("255"));
+        assert_eq!(eval("format(-255, 16)"), string("-0xff"));
+        assert_eq!(eval("format(0, 2)"), string("0b0"));
+        assert_eq!(
+            eval("format(255, 3)"),
+            Err("format expects a base of 2, 8, 10 or 16, got 3".into())
+        );
+        assert_eq!(
+            eval("format(2.5, 16)"),
+            Err("format expects an integer, got 2.5".into())
+        );
+        assert_eq!(
+            eval("format(255, 16.5)"),
+            Err("format expects an integer, got 16.5".into())
+        );
+    }
+
+    #[cfg(feature = "random")]
+    #[test]
+    fn random_numbers_stay_in_range() {
+        let mut engine = Engine::new();
+        for _ in 0..100 {
+            match engine.eval("[random(), random_range(1, 7)]").unwrap() {
+                Value::Array(values) => match (&values[0], &values[1]) {
+                    (Value::Number(x), Value::Int(roll)) => {
+                        assert!((0.0..1.0).contains(x), "{}", x);
+                        assert!((1..7).contains(roll), "{}", roll);
+                    }
+                    other => panic!("Expected numbers, found {:?}", other),
+                },
+                other => panic!("Expected an array, found {:?}", other),
+            }
+        }
+        assert_eq!(
+            engine
+                .eval("random_range(3, 3)")
+                .map_err(|err| err.to_string()),
+            Err("random_range expects low < high, got 3 and 3".into())
+        );
+    }
+
+    #[test]
+    fn sum_and_average() {
+        assert_eq!(eval("sum([1, 2, 3])"), Ok(Value::Number(6.0)));
+        assert_eq!(eval("sum([])"), Ok(Value::Number(0.0)));
+        assert_eq!(eval("average([1, 2, 3])"), Ok(Value::Number(2.0)));
+        assert_eq!(eval("average([0.5])"), Ok(Value::Number(0.5)));
+    }
+
+    #[test]
+    fn sum_and_average_errors() {
+        assert_eq!(eval("average([])"), Err("average of an empty array".into()));
+        assert_eq!(
+            eval("sum([1, true])"),
+            Err("sum expects an array of numbers, found Bool".into())
+        );
+        assert_eq!(
+            eval("average(3)"),
+            Err("average expects an array, got Number".into())
+        );
+        assert_eq!(
+            eval("sum([1], [2])"),
+            Err("sum expects 1 argument, got 2".into())
+        );
+    }
+
+    #[test]
+    fn min_max_and_clamp() {
+        assert_eq!(eval("min([3, 1, 2])"), Ok(Value::Number(1.0)));
+        assert_eq!(eval("max([3, 1, 2])"), Ok(Value::Number(3.0)));
+        assert_eq!(eval("max([-1])"), Ok(Value::Number(-1.0)));
+        assert_eq!(eval("min([])"), Err("min of an empty array".into()));
+        assert_eq!(
+            eval("max([1, \"a\"])"),
+            Err("max expects an array of numbers, found String".into())
+        );
+        assert_eq!(eval("clamp(5, 0, 3)"), Ok(Value::Number(3.0)));
+        assert_eq!(eval("clamp(-5, 0, 3)"), Ok(Value::Number(0.0)));
+        assert_eq!(eval("clamp(2, 0, 3)"), Ok(Value::Number(2.0)));
+        assert_eq!(
+            eval("clamp(1, 3, 0)"),
+            Err("clamp expects low <= high, got 3 and 0".into())
+        );
+        assert_eq!(
+            eval("clamp(1, sqrt(-1), 3)"),
+            Err("clamp expects bounds that are not NaN, got NaN and 3".into())
+        );
+        assert_eq!(
+            eval("clamp(sqrt(-1), 0, 3)").map(|x| x.to_string()),
+            Ok("NaN".into())
+        );
+    }
+
+    #[test]
+    fn ranges_in_place_of_arrays() {
+        assert_eq!(eval("sum(1..5)"), Ok(Value::Number(10.0)));
+        assert_eq!(eval("max(1..5)"), Ok(Value::Number(4.0)));
+        assert_eq!(eval("min(1..5)"), Ok(Value::Number(1.0)));
+        assert_eq!(eval("len(1..5)"), Ok(Value::Number(4.0)));
+        assert_eq!(eval("sum(-3..3)"), Ok(Value::Number(-3.0)));
+        assert_eq!(
+            eval("sum(0..1000000000)"),
+            Ok(Value::Number(499999999500000000.0))
+        );
+        assert_eq!(eval("[sum(5..5), len(5..1)]"), eval("[0, 0]"));
+        assert_eq!(eval("max(5..5)"), Err("max of an empty range".into()));
+    }
+
+    #[test]
+    fn map_applies_a_function_to_each_element() {
+        assert_eq!(eval("map(fn x { x * 2 }, [1, 2, 3])"), eval("[2, 4, 6]"));
+        assert_eq!(eval("def inc n = n + 1; map(inc, [])"), eval("[]"));
+        assert_eq!(eval("map(fn s { len(s) }, [\"ab\", \"\"])"), eval("[2, 0]"));
+    }
+
+    #[test]
+    fn map_errors() {
+        assert_eq!(
+            eval("map(1, [1, 2])"),
+            Err("map expects a function, got Number".into())
+        );
+        assert_eq!(
+            eval("map(fn x { x }, 1)"),
+            Err("map expects an array, got Number".into())
+        );
+        assert_eq!(
+            eval("map(fn x { 1 / x }, [1, 0])"),
+            Err("Division by zero".into())
+        );
+        assert_eq!(
+            eval("map(fn x, y { x }, [1])"),
+            Err("Function '<anonymous>' expected 2 arguments, got 1".into())
+        );
+    }
+
+    #[test]
+    fn filter_keeps_matching_elements_in_order() {
+        assert_eq!(eval("filter(fn x { x > 2 }, [1, 4, 2, 3])"), eval("[4, 3]"));
+        assert_eq!(eval("filter(fn x { x > 9 }, [1, 2])"), eval("[]"));
+        assert_eq!(
+            eval("filter(fn x { x }, [1])"),
+            Err("filter expects the predicate to return a Bool, got Number".into())
+        );
+    }
+
+    #[test]
+    fn fold_threads_the_accumulator_left_to_right() {
+        assert_eq!(
+            eval("fold(fn acc, x { acc + x }, 0, [1, 2, 3])"),
+            Ok(Value::Number(6.0))
+        );
+        assert_eq!(
+            eval("fold(fn acc, x { acc - x }, 10, [1, 2])"),
+            Ok(Value::Number(7.0))
+        );
+        assert_eq!(
+            eval("fold(fn acc, x { acc + x }, 5, [])"),
+            Ok(Value::Number(5.0))
+        );
+        assert_eq!(
+            eval("def add x = x; fold(add, 0, [1])"),
+            Err("Function 'add' expected 1 argument, got 2".into())
+        );
+    }
+
+    #[test]
+    fn type_of_names_each_type() {
+        for (source, expected) in [
+            ("type_of(1)", "number"),
+            ("type_of(true)", "bool"),
+            ("type_of(\"a\")", "string"),
+            ("type_of(fn x { x })", "function"),
+            ("type_of([1])", "array"),
+            ("type_of(try(1 / 0))", "nil"),
+            ("type_of(while false { 1 })", "unit"),
+            #[cfg(feature = "complex")]
+            ("type_of(2i)", "complex"),
+        ] {
+            assert_eq!(
+                eval(source),
+                Ok(Value::String(expected.into())),
+                "{}",
+                source
+            );
+        }
+        assert_eq!(eval("type_of(1) == \"number\""), Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn push_and_concat_make_new_arrays() {
+        assert_eq!(eval("push([1, 2], 3)"), eval("[1, 2, 3]"));
+        assert_eq!(eval("push([], [1])"), eval("[[1]]"));
+        assert_eq!(eval("def xs = [1]; push(xs, 2); xs"), eval("[1]"));
+        assert_eq!(eval("concat([1], [2, 3])"), eval("[1, 2, 3]"));
+        assert_eq!(eval("concat([], [])"), eval("[]"));
+        assert_eq!(
+            eval("concat([1], 2)"),
+            Err("concat expects an array, got Number".into())
+        );
+        assert_eq!(
+            eval("push(1, 2)"),
+            Err("push expects an array, got Number".into())
+        );
+    }
+
+    #[test]
+    fn reverse_and_sort() {
+        assert_eq!(eval("reverse([1, 2, 3])"), eval("[3, 2, 1]"));
+        assert_eq!(eval("reverse([])"), eval("[]"));
+        assert_eq!(eval("sort([3, 1, 2, -1.5])"), eval("[-1.5, 1, 2, 3]"));
+        assert_eq!(
+            eval("sort([\"b\", \"C\", \"a\"])"),
+            eval("[\"C\", \"a\", \"b\"]")
+        );
+        assert_eq!(eval("sort([])"), eval("[]"));
+        assert_eq!(
+            eval("sort([1, \"a\"])"),
+            Err("sort expects elements of one type, found Number and String".into())
+        );
+        assert_eq!(
+            eval("sort([true, false])"),
+            Err("sort expects numbers or strings, got Bool".into())
+        );
+        assert_eq!(
+            eval("reverse(\"ab\")"),
+            Err("reverse expects an array, got String".into())
+        );
+    }
+
+    #[test]
+    fn contains_and_index_of() {
+        assert_eq!(eval("contains([1, 2, 3], 2)"), Ok(Value::Bool(true)));
+        assert_eq!(eval("contains([1, [2]], [2])"), Ok(Value::Bool(true)));
+        assert_eq!(eval("contains([1, 2, 3], 4)"), Ok(Value::Bool(false)));
+        assert_eq!(eval("contains(\"hello\", \"ell\")"), Ok(Value::Bool(true)));
+        assert_eq!(eval("contains(\"hello\", \"z\")"), Ok(Value::Bool(false)));
+        assert_eq!(eval("index_of([1, 2, 2], 2)"), Ok(Value::Number(1.0)));
+        assert_eq!(eval("index_of([1, 2], \"2\")"), Ok(Value::Number(-1.0)));
+        assert_eq!(eval("index_of(\"hello\", \"l\")"), Ok(Value::Number(2.0)));
+        assert_eq!(eval("index_of(\"héllo\", \"l\")"), Ok(Value::Number(2.0)));
+        assert_eq!(eval("index_of(\"hello\", \"z\")"), Ok(Value::Number(-1.0)));
+        assert_eq!(
+            eval("contains(\"hello\", 1)"),
+            Err("contains expects a string to search for in a string, got Number".into())
+        );
+        assert_eq!(
+            eval("index_of(1, 1)"),
+            Err("index_of expects an array or a string, got Number".into())
+        );
+    }
+
+    #[test]
+    fn first_last_and_slice_of_arrays() {
+        assert_eq!(eval("first([1, 2, 3])"), Ok(Value::Number(1.0)));
+        assert_eq!(eval("last([1, 2, 3])"), Ok(Value::Number(3.0)));
+        assert_eq!(eval("slice([1, 2, 3, 4], 1, 3)"), eval("[2, 3]"));
+        assert_eq!(eval("slice([1, 2], 2, 2)"), eval("[]"));
+        assert_eq!(eval("slice([1, 2], 0, 2)"), eval("[1, 2]"));
+    }
+
+    #[test]
+    fn first_last_and_slice_errors() {
+        assert_eq!(eval("first([])"), Err("first of an empty array".into()));
+        assert_eq!(eval("last([])"), Err("last of an empty array".into()));
+        assert_eq!(
+            eval("first(\"ab\")"),
+            Err("first expects an array, got String".into())
+        );
+        assert_eq!(
+            eval("slice([1, 2, 3, 4], 3, 5)"),
+            Err("slice range 3..5 is out of bounds for an array of 4 elements".into())
+        );
+        assert_eq!(
+            eval("slice([1, 2, 3, 4], 2, 1)"),
+            Err("slice range 2..1 is out of bounds for an array of 4 elements".into())
+        );
+        assert_eq!(
+            eval("slice([1, 2, 3, 4], -1, 2)"),
+            Err("slice range -1..2 is out of bounds for an array of 4 elements".into())
+        );
+        assert_eq!(
+            eval("slice([1, 2], 0, 1.5)"),
+            Err("slice expects whole number indices, got 0 and 1.5".into())
+        );
+    }
+
+    #[test]
+    fn string_case_and_trimming() {
+        let string = |value: &str| Ok(Value::String(value.into()));
+        assert_eq!(eval("upper(\"ab\")"), string("AB"));
+        assert_eq!(eval("upper(\"straße\")"), string("STRASSE"));
+        assert_eq!(eval("lower(\"AbC\")"), string("abc"));
+        assert_eq!(eval("trim(\"  a b  \")"), string("a b"));
+        assert_eq!(
+            eval("trim(1)"),
+            Err("trim expects a string, got Number".into())
+        );
+    }
+
+    #[test]
+    fn substring_by_characters() {
+        let string = |value: &str| Ok(Value::String(value.into()));
+        assert_eq!(eval("substring(\"hello\", 1, 3)"), string("el"));
+        assert_eq!(eval("substring(\"héllo\", 1, 2)"), string("é"));
+        assert_eq!(eval("substring(\"hello\", 5, 5)"), string(""));
+        assert_eq!(
+            eval("substring(\"hello\", 2, 6)"),
+            Err("substring range 2..6 is out of bounds for a string of 5 characters".into())
+        );
+        assert_eq!(
+            eval("substring(\"hello\", 3, 2)"),
+            Err("substring range 3..2 is out of bounds for a string of 5 characters".into())
+        );
+        assert_eq!(
+            eval("substring(\"hello\", 0.5, 2)"),
+            Err("substring expects whole number indices, got 0.5 and 2".into())
+        );
+    }
+
+    #[test]
+    fn split_and_join() {
+        assert_eq!(
+            eval("split(\"a,b,c\", \",\")"),
+            eval("[\"a\", \"b\", \"c\"]")
+        );
+        assert_eq!(
+            eval("split(\"a,,b,\", \",\")"),
+            eval("[\"a\", \"\", \"b\", \"\"]")
+        );
+        assert_eq!(eval("split(\"\", \",\")"), eval("[\"\"]"));
+        assert_eq!(eval("split(\"hé\", \"\")"), eval("[\"h\", \"é\"]"));
+        assert_eq!(
+            eval("join([\"a\", \"b\"], \"-\")"),
+            Ok(Value::String("a-b".into()))
+        );
+        assert_eq!(eval("join([], \"-\")"), Ok(Value::String("".into())));
+        assert_eq!(
+            eval("join(split(\"a b\", \" \"), \"\")"),
+            Ok(Value::String("ab".into()))
+        );
+        assert_eq!(
+            eval("join([\"a\", 1], \"-\")"),
+            Err("join expects an array of strings, found Number".into())
+        );
+        assert_eq!(
+            eval("split(\"a\", 1)"),
+            Err("split expects a string, got Number".into())
+        );
+    }
+
+    #[test]
+    fn len_of_arrays_and_strings() {
+        assert_eq!(eval("len([1, 2, 3])"), Ok(Value::Number(3.0)));
+        assert_eq!(eval("len([])"), Ok(Value::Number(0.0)));
+        assert_eq!(eval("len(\"abc\")"), Ok(Value::Number(3.0)));
+        assert_eq!(eval("len(\"héllo\")"), Ok(Value::Number(5.0)));
+        assert_eq!(
+            eval("len(true)"),
+            Err("len expects an array, a string or a range, got Bool".into())
+        );
+    }
+}
@@ -0,0 +1,530 @@
+use crate::ast::parse;
+use crate::ast::Program;
+use crate::interpreter::{
+    evaluate_each, evaluate_traced, evaluate_with, BinaryOp, Clock, Environment, EvalOptions,
+    IntOverflow, Interpreter, Profile, Value,
+};
+use crate::scanner::tokenize_with_spans;
+use std::error::Error;
+use std::io::{self, Write};
+
+/// Evaluates source text against an environment that persists between calls,
+/// so definitions made by one `eval` are visible to the next. It parses the
+/// source and hands the tree to an `Interpreter`, which holds the definitions,
+/// options and host hooks.
+#[derive(Debug, Default)]
+pub struct Engine {
+    interpreter: Interpreter,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an engine that evaluates with `options`, e.g. to let `1 / 0`
+    /// produce infinity by turning off `finite_checks.div_by_zero`.
+    pub fn with_options(options: EvalOptions) -> Self {
+        Self {
+            interpreter: Interpreter::with_output(io::stdout(), options),
+        }
+    }
+
+    pub fn env(&self) -> &Environment {
+        self.interpreter.env()
+    }
+
+    /// Binds `name` to `value` as a `def` would, so programs can use values
+    /// made by the host, such as custom values.
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.interpreter.define(name, value);
+    }
+
+    /// Evaluates `source` as a transaction: if it fails, any definitions it
+    /// made before the error are rolled back. `print` writes to stdout.
+    pub fn eval(&mut self, source: &str) -> Result<Value, Box<dyn Error>> {
+        self.eval_to(source, &mut io::stdout())
+    }
+
+    /// Evaluates `source` like `eval`, with `print` writing to `out`.
+    pub fn eval_to(&mut self, source: &str, out: &mut dyn Write) -> Result<Value, Box<dyn Error>> {
+        self.transaction(source, out, evaluate_with)
+    }
+
+    /// Evaluates `source` like `eval`, returning the value of each expression
+    /// statement and of the trailing expression in order.
+    pub fn eval_each(&mut self, source: &str) -> Result<Vec<Value>, Box<dyn Error>> {
+        self.eval_each_to(source, &mut io::stdout())
+    }
+
+    /// Evaluates `source` like `eval_each`, with `print` writing to `out`.
+    pub fn eval_each_to(
+        &mut self,
+        source: &str,
+        out: &mut dyn Write,
+    ) -> Result<Vec<Value>, Box<dyn Error>> {
+        self.transaction(source, out, evaluate_each)
+    }
+
+    /// Evaluates `source` like `eval`, but returns each reduction step of its
+    /// final expression, e.g. `2 + 3 * 4`, `2 + 12`, `14`. Intended for
+    /// teaching with small expressions.
+    pub fn eval_traced(&mut self, source: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        self.transaction(source, &mut io::stdout(), evaluate_traced)
+    }
+
+    /// How many times a single loop may iterate before it is stopped.
+    pub fn max_iterations(&self) -> usize {
+        self.interpreter.options().max_iterations
+    }
+
+    pub fn set_max_iterations(&mut self, max_iterations: usize) {
+        self.update_options(|options| options.max_iterations = max_iterations);
+    }
+
+    /// How many function calls may be nested before evaluation is stopped.
+    pub fn max_depth(&self) -> usize {
+        self.interpreter.options().max_depth
+    }
+
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.update_options(|options| options.max_depth = max_depth);
+    }
+
+    /// Whether `/` truncates when both operands are whole numbers.
+    pub fn integer_division(&self) -> bool {
+        self.interpreter.options().integer_division
+    }
+
+    pub fn set_integer_division(&mut self, integer_division: bool) {
+        self.update_options(|options| options.integer_division = integer_division);
+    }
+
+    /// What happens when integer arithmetic overflows.
+    pub fn int_overflow(&self) -> IntOverflow {
+        self.interpreter.options().int_overflow
+    }
+
+    pub fn set_int_overflow(&mut self, int_overflow: IntOverflow) {
+        self.update_options(|options| options.int_overflow = int_overflow);
+    }
+
+    /// Whether conditions must be booleans rather than judged by truthiness.
+    pub fn strict_bool(&self) -> bool {
+        self.interpreter.options().strict_bool
+    }
+
+    pub fn set_strict_bool(&mut self, strict_bool: bool) {
+        self.update_options(|options| options.strict_bool = strict_bool);
+    }
+
+    /// Restarts the random number sequence from `seed`, so evaluations that
+    /// use `random` give the same results every run.
+    #[cfg(feature = "random")]
+    pub fn seed(&mut self, seed: u64) {
+        self.interpreter.seed(seed);
+    }
+
+    /// Makes the `now` built-in read the time from `clock` instead of the
+    /// system clock.
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) {
+        self.interpreter.set_clock(clock);
+    }
+
+    /// Makes the `env` built-in read variables with `lookup` instead of from
+    /// the process environment.
+    pub fn set_env_lookup(&mut self, lookup: impl Fn(&str) -> Option<String> + 'static) {
+        self.interpreter.set_env_lookup(lookup);
+    }
+
+    /// Lets programs apply binary operators to operands the built-in operators
+    /// don't accept, such as custom values, as `operator` defines. Operators
+    /// are tried in the order they were registered.
+    pub fn register_operator(&mut self, operator: impl BinaryOp + 'static) {
+        self.interpreter.register_operator(operator);
+    }
+
+    /// Starts counting the expressions later evaluations evaluate, by kind.
+    pub fn start_profile(&mut self) {
+        self.interpreter.start_profile();
+    }
+
+    /// Stops counting and returns the counts since `start_profile`.
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.interpreter.take_profile()
+    }
+
+    fn update_options(&mut self, update: impl FnOnce(&mut EvalOptions)) {
+        let mut options = self.interpreter.options();
+        update(&mut options);
+        self.interpreter.set_options(options);
+    }
+
+    fn transaction<T, F>(
+        &mut self,
+        source: &str,
+        out: &mut dyn Write,
+        evaluate: F,
+    ) -> Result<T, Box<dyn Error>>
+    where
+        F: FnOnce(
+            &Program,
+            &mut Environment,
+            EvalOptions,
+            &mut dyn Write,
+        ) -> Result<T, Box<dyn Error>>,
+    {
+        let snapshot = self.interpreter.env().snapshot();
+        let result = tokenize_with_spans(source)
+            .and_then(|tokens| parse(&tokens))
+            .and_then(|program| self.interpreter.eval_program_with(&program, out, evaluate));
+        if result.is_err() {
+            self.interpreter.env_mut().restore(snapshot);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::collections::HashMap;
+
+    #[test]
+    fn definitions_persist_between_evaluations() {
+        let mut engine = Engine::new();
+        engine.eval("def x = 20;").unwrap();
+        let value = engine.eval("x + 1").unwrap();
+        assert_eq!(value, Value::Number(21.0));
+    }
+
+    #[test]
+    fn failed_evaluation_leaves_no_partial_definitions() {
+        let mut engine = Engine::new();
+        engine.eval("def x = 1;").unwrap();
+        let err = engine.eval("def x = 2; def y = 3; 1 / 0").unwrap_err();
+        assert_eq!(format!("{}", err), "Division by zero");
+        assert_eq!(engine.env().get("x"), Some(&Value::Number(1.0)));
+        assert_eq!(engine.env().get("y"), None);
+    }
+
+    #[test]
+    fn eval_each_returns_every_expression_value() {
+        let mut engine = Engine::new();
+        let values = engine.eval_each("1 + 1; def x = 3; 2 + 2; x").unwrap();
+        assert_eq!(
+            values,
+            vec![Value::Number(2.0), Value::Number(4.0), Value::Number(3.0)]
+        );
+        assert_eq!(engine.eval("1; 2").unwrap(), Value::Number(2.0));
+    }
+
+    #[test]
+    fn print_output_is_captured() {
+        let mut engine = Engine::new();
+        let mut out = Vec::new();
+        let values = engine
+            .eval_each_to("print 1; 2; print [3, 4];", &mut out)
+            .unwrap();
+        assert_eq!(values, vec![Value::Number(2.0)]);
+        assert_eq!(String::from_utf8(out).unwrap(), "1\n[3, 4]\n");
+    }
+
+    #[test]
+    fn profile_counts_evaluated_expressions() {
+        let mut engine = Engine::new();
+        engine.eval("def f x = x * 2;").unwrap();
+        assert_eq!(engine.take_profile(), None);
+
+        engine.start_profile();
+        engine.eval("f(1 + 2) + f(3)").unwrap();
+        let profile = engine.take_profile().unwrap();
+        assert_eq!(
+            profile.counts(),
+            vec![
+                ("NumericLiteral", 5),
+                ("Binary", 4),
+                ("Variable", 4),
+                ("Call", 2)
+            ]
+        );
+        assert_eq!(profile.count("Function"), 0);
+        assert_eq!(engine.take_profile(), None);
+    }
+
+    #[test]
+    fn eval_traced_lists_each_step() {
+        let mut engine = Engine::new();
+        let steps = engine.eval_traced("2 + 3 * 4").unwrap();
+        assert_eq!(steps, vec!["2 + 3 * 4", "2 + 12", "14"]);
+    }
+
+    #[test]
+    fn options_apply_to_every_evaluation() {
+        let mut options = EvalOptions::default();
+        options.finite_checks.div_by_zero = false;
+        let mut engine = Engine::with_options(options);
+        engine.eval("def x = 1 / 0;").unwrap();
+        assert_eq!(engine.eval("x").unwrap(), Value::Number(f64::INFINITY));
+        assert!(Engine::new().eval("1 / 0").is_err());
+    }
+
+    #[test]
+    fn runaway_loop_is_stopped() {
+        let mut engine = Engine::new();
+        assert_eq!(engine.max_iterations(), 1_000_000);
+        engine.set_max_iterations(10);
+        let err = engine.eval("while true { 1 }").unwrap_err();
+        assert_eq!(err.to_string(), "Maximum loop iterations exceeded");
+    }
+
+    #[test]
+    fn runaway_recursion_is_stopped() {
+        let mut engine = Engine::new();
+        assert_eq!(engine.max_depth(), 10_000);
+        engine
+            .eval("def down n = cond { n == 0 => 0, else => down(n - 1) };")
+            .unwrap();
+        engine.set_max_depth(10);
+        let err = engine.eval("down(10)").unwrap_err();
+        assert_eq!(err.to_string(), "Maximum recursion depth exceeded");
+        assert_eq!(engine.eval("down(9)").unwrap(), Value::Number(0.0));
+        engine.set_max_depth(11);
+        assert_eq!(engine.eval("down(10)").unwrap(), Value::Number(0.0));
+    }
+
+    #[test]
+    fn deep_recursion_grows_the_stack() {
+        let mut engine = Engine::new();
+        engine
+            .eval("def down n = cond { n == 0 => 0, else => down(n - 1) };")
+            .unwrap();
+        assert_eq!(engine.eval("down(5000)").unwrap(), Value::Number(0.0));
+        engine.set_max_depth(100_000);
+        assert_eq!(engine.eval("down(50000)").unwrap(), Value::Number(0.0));
+    }
+
+    #[test]
+    fn int_overflow_modes() {
+        let mut engine = Engine::new();
+        assert_eq!(engine.int_overflow(), IntOverflow::Checked);
+        let max_plus_one = "9223372036854775807 + 1";
+        let err = engine.eval(max_plus_one).unwrap_err();
+        assert_eq!(err.to_string(), "Integer overflow");
+        // Only integers are checked, so ordinary arithmetic is unaffected.
+        assert_eq!(engine.eval("1e300 * 10").unwrap(), Value::Number(1e301));
+        assert_eq!(engine.eval("2^60").unwrap(), Value::Int(1 << 60));
+        assert_eq!(engine.eval("1e20 + 1").unwrap(), Value::Number(1e20));
+
+        engine.set_int_overflow(IntOverflow::Rounding);
+        assert_eq!(
+            engine.eval(max_plus_one).unwrap(),
+            Value::Number(9223372036854775808.0)
+        );
+
+        engine.set_int_overflow(IntOverflow::Wrapping);
+        assert_eq!(engine.eval(max_plus_one).unwrap(), Value::Int(i64::MIN));
+        assert_eq!(
+            engine.eval("-9223372036854775807 - 2").unwrap(),
+            Value::Int(i64::MAX)
+        );
+        assert_eq!(engine.eval("2 ^ 64").unwrap(), Value::Int(0));
+        assert_eq!(engine.eval("2 ^ -1").unwrap(), Value::Number(0.5));
+
+        engine.set_int_overflow(IntOverflow::Saturating);
+        assert_eq!(engine.eval(max_plus_one).unwrap(), Value::Int(i64::MAX));
+        assert_eq!(
+            engine.eval("(-9223372036854775807 - 1) * 2").unwrap(),
+            Value::Int(i64::MIN)
+        );
+        assert_eq!(engine.eval("0.5 + 1").unwrap(), Value::Number(1.5));
+        assert_eq!(engine.eval("7 - 10").unwrap(), Value::Number(-3.0));
+    }
+
+    #[test]
+    fn integer_division_mode() {
+        let mut engine = Engine::new();
+        assert!(!engine.integer_division());
+        assert_eq!(engine.eval("7 / 2").unwrap(), Value::Number(3.5));
+        engine.set_integer_division(true);
+        assert_eq!(engine.eval("7 / 2").unwrap(), Value::Number(3.0));
+        assert_eq!(engine.eval("-7 / 2").unwrap(), Value::Number(-3.0));
+        assert_eq!(engine.eval("7.5 / 2").unwrap(), Value::Number(3.75));
+        assert_eq!(engine.eval("7 / 0.5").unwrap(), Value::Number(14.0));
+        assert_eq!(engine.eval("7.0 / 2").unwrap(), Value::Number(3.5));
+        assert_eq!(engine.eval("7 / -(2.0)").unwrap(), Value::Number(-3.5));
+        assert_eq!(engine.eval("7_f64 / 2").unwrap(), Value::Number(3.5));
+        assert_eq!(engine.eval("0xE / 4").unwrap(), Value::Number(3.0));
+        // Float-ness is carried by the value, not read from the source.
+        engine.eval("def x = 7.0; def n = 7").unwrap();
+        assert_eq!(engine.eval("x / 2").unwrap(), Value::Number(3.5));
+        assert_eq!(engine.eval("n / 2").unwrap(), Value::Number(3.0));
+        assert_eq!(engine.eval("(n + 0.0) / 2").unwrap(), Value::Number(3.5));
+        assert_eq!(
+            engine.eval("1 / 0").unwrap_err().to_string(),
+            "Division by zero"
+        );
+    }
+
+    #[test]
+    fn truthiness_when_not_strict() {
+        let mut engine = Engine::new();
+        assert!(engine.strict_bool());
+        assert_eq!(
+            engine.eval("0 || true").unwrap_err().to_string(),
+            "Expected a boolean operand for ||, got 0"
+        );
+        assert_eq!(
+            engine
+                .eval("if 0 { 1 } else { 2 }")
+                .unwrap_err()
+                .to_string(),
+            "Expected a boolean condition for if, got 0"
+        );
+        assert_eq!(
+            engine.eval("if 1 > 0 { 1 } else { 2 }").unwrap(),
+            Value::Number(1.0)
+        );
+        engine.set_strict_bool(false);
+        assert_eq!(
+            engine.eval("if 0 { 1 } else { 2 }").unwrap(),
+            Value::Number(2.0)
+        );
+        assert_eq!(
+            engine.eval("if [0] { 1 } else { 2 }").unwrap(),
+            Value::Number(1.0)
+        );
+        assert_eq!(
+            engine
+                .eval("if \"\" { 1 } else if 3 { 2 } else { 3 }")
+                .unwrap(),
+            Value::Number(2.0)
+        );
+        assert_eq!(engine.eval("0 || 2").unwrap(), Value::Bool(true));
+        assert_eq!(engine.eval("1 && \"\"").unwrap(), Value::Bool(false));
+        assert_eq!(
+            engine.eval("[0] && try(1 / 0)").unwrap(),
+            Value::Bool(false)
+        );
+        assert_eq!(engine.eval("while 0 { 1 / 0 }").unwrap(), Value::Unit);
+        assert_eq!(engine.eval("while [] { 1 / 0 }").unwrap(), Value::Unit);
+    }
+
+    #[cfg(feature = "random")]
+    #[test]
+    fn seeding_reproduces_random_sequences() {
+        let source = "[random(), random_range(1, 7), random_range(1, 7), random()]";
+        let mut engine = Engine::new();
+        engine.seed(42);
+        let first = engine.eval(source).unwrap();
+        engine.seed(42);
+        assert_eq!(engine.eval(source).unwrap(), first);
+        let mut other = Engine::new();
+        other.seed(42);
+        assert_eq!(other.eval(source).unwrap(), first);
+        assert_ne!(engine.eval(source).unwrap(), first);
+    }
+
+    #[test]
+    fn bench_averages_the_clock_over_each_run() {
+        // Advances a second every time it is read.
+        struct SteppingClock(Cell<f64>);
+        impl Clock for SteppingClock {
+            fn now(&self) -> f64 {
+                let now = self.0.get();
+                self.0.set(now + 1.0);
+                now
+            }
+        }
+        let mut engine = Engine::new();
+        engine.set_clock(SteppingClock(Cell::new(0.0)));
+        assert_eq!(
+            engine.eval("bench(1 + 1, 4)").unwrap(),
+            Value::Number(250.0)
+        );
+        // The clock is read before and after the runs, and by each of them.
+        assert_eq!(
+            engine.eval("bench(now(), 4)").unwrap(),
+            Value::Number(1250.0)
+        );
+        assert_eq!(
+            engine.eval("bench(1, 0)").unwrap_err().to_string(),
+            "bench expects a positive whole number of runs, got 0"
+        );
+        assert_eq!(
+            engine.eval("bench(1 / 0, 2)").unwrap_err().to_string(),
+            "Division by zero"
+        );
+        assert_eq!(
+            engine.eval("bench(1, 1e300)").unwrap_err().to_string(),
+            "Maximum loop iterations exceeded"
+        );
+        engine.set_max_iterations(4);
+        assert_eq!(
+            engine.eval("bench(1 + 1, 4)").unwrap(),
+            Value::Number(250.0)
+        );
+        assert_eq!(
+            engine.eval("bench(1, 5)").unwrap_err().to_string(),
+            "Maximum loop iterations exceeded"
+        );
+    }
+
+    #[test]
+    fn env_reads_the_injected_lookup() {
+        let mut engine = Engine::new();
+        let variables = HashMap::from([("HOME".to_string(), "/home/sal".to_string())]);
+        engine.set_env_lookup(move |name| variables.get(name).cloned());
+        assert_eq!(
+            engine.eval("env(\"HOME\")").unwrap(),
+            Value::String("/home/sal".into())
+        );
+        assert_eq!(engine.eval("env(\"UNSET\")").unwrap(), Value::Nil);
+    }
+
+    #[test]
+    fn now_reads_the_injected_clock() {
+        struct FixedClock(f64);
+        impl Clock for FixedClock {
+            fn now(&self) -> f64 {
+                self.0
+            }
+        }
+        let mut engine = Engine::new();
+        engine.eval("def stamp = fn { now() };").unwrap();
+        engine.set_clock(FixedClock(1_700_000_000.5));
+        assert_eq!(
+            engine.eval("now()").unwrap(),
+            Value::Number(1_700_000_000.5)
+        );
+        assert_eq!(
+            engine.eval("stamp()").unwrap(),
+            Value::Number(1_700_000_000.5)
+        );
+        assert!(matches!(
+            Engine::new().eval("now()").unwrap(),
+            Value::Number(seconds) if seconds > 1_600_000_000.0
+        ));
+    }
+
+    #[test]
+    fn failed_evaluation_does_not_define_new_names() {
+        let mut engine = Engine::new();
+        assert!(engine.eval("def x = 1; undefined").is_err());
+        let err = engine.eval("x").unwrap_err();
+        assert_eq!(format!("{}", err), "Undefined variable: x");
+    }
+
+    #[test]
+    fn unicode_names() {
+        let mut engine = Engine::new();
+        engine.eval("def π = 3; def número = 5;").unwrap();
+        assert_eq!(engine.eval("número * 2").unwrap(), Value::Number(10.0));
+        assert_eq!(engine.eval("2*π").unwrap(), Value::Number(6.0));
+        assert_eq!(
+            engine.eval("def área r = π * r^2; área(2)").unwrap(),
+            Value::Number(12.0)
+        );
+    }
+}
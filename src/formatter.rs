@@ -0,0 +1,335 @@
+use crate::ast::{
+    binary_operator, is_comparison, Associativity, Expr, Program, Stmt, POWER_PRECEDENCE,
+};
+use crate::scanner::Token;
+use std::fmt;
+
+// How tightly each kind of expression holds together as an operand, on the
+// scale of the operators' precedences shifted up by one so that `let`, whose
+// body extends as far as it can, is below them all. Prefix minus binds like
+// `^`, since its operand is parsed at that level.
+const LET_BINDING: u8 = 0;
+const UNARY_BINDING: u8 = POWER_PRECEDENCE + 1;
+const POSTFIX_BINDING: u8 = UNARY_BINDING + 1;
+const CALL_BINDING: u8 = POSTFIX_BINDING + 1;
+const PRIMARY_BINDING: u8 = CALL_BINDING + 1;
+
+fn binding(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Let { .. } => LET_BINDING,
+        Expr::Binary { operator, .. } => {
+            binary_operator(operator).map_or(LET_BINDING, |info| info.precedence + 1)
+        }
+        Expr::Unary { .. } => UNARY_BINDING,
+        Expr::Postfix { .. } => POSTFIX_BINDING,
+        Expr::Call { .. } | Expr::Field { .. } | Expr::Index { .. } => CALL_BINDING,
+        _ => PRIMARY_BINDING,
+    }
+}
+
+// Formats `expr` where an operand binding at least `min_binding` is expected,
+// in parentheses if it binds more loosely.
+fn format_operand(expr: &Expr, min_binding: u8) -> String {
+    if binding(expr) < min_binding {
+        format!("({})", format_expr(expr))
+    } else {
+        format_expr(expr)
+    }
+}
+
+/// Renders `expr` back into source text. Literals are written exactly as they
+/// appeared in the source, so `0xFF` stays `0xFF` rather than becoming `255`.
+/// Parentheses are written where the tree has a grouping, and around any
+/// operand that would otherwise be read back as part of a different tree, so
+/// `(1 + 2) * 3` keeps its parentheses even without a grouping.
+pub fn format_expr(expr: &Expr) -> String {
+    match expr {
+        Expr::Binary {
+            left,
+            operator,
+            right,
+            ..
+        } => {
+            let own = binding(expr);
+            // Comparisons can't be chained, so neither side may be another
+            // comparison.
+            let (left_min, right_min) = match binary_operator(operator) {
+                _ if is_comparison(operator) => (own + 1, own + 1),
+                Some(info) if info.associativity == Associativity::Right => (own + 1, own),
+                _ => (own, own + 1),
+            };
+            format!(
+                "{} {} {}",
+                format_operand(left, left_min),
+                operator_text(operator),
+                format_operand(right, right_min)
+            )
+        }
+        Expr::Call { callee, args, .. } => {
+            let args: Vec<String> = args.iter().map(format_expr).collect();
+            format!(
+                "{}({})",
+                format_operand(callee, CALL_BINDING),
+                args.join(", ")
+            )
+        }
+        Expr::Grouping { expr, .. } => format!("({})", format_expr(expr)),
+        Expr::BooleanLiteral { value, .. } => value.to_string(),
+        Expr::NumericLiteral { value, .. } => value.clone(),
+        #[cfg(feature = "complex")]
+        Expr::ImaginaryLiteral { value, .. } => format!("{}i", value),
+        Expr::StringLiteral { value, .. } => format!("\"{}\"", value),
+        Expr::Postfix { left, operator, .. } => format!(
+            "{}{}",
+            format_operand(left, POSTFIX_BINDING),
+            operator_text(operator)
+        ),
+        Expr::Unary {
+            operator, right, ..
+        } => format!(
+            "{}{}",
+            operator_text(operator),
+            format_operand(right, UNARY_BINDING)
+        ),
+        Expr::Variable { name, .. } => name.clone(),
+        Expr::Function { params, body, .. } if params.is_empty() => {
+            format!("fn {{ {} }}", format_expr(body))
+        }
+        Expr::Function { params, body, .. } => {
+            format!("fn {} {{ {} }}", params.join(", "), format_expr(body))
+        }
+        Expr::While {
+            condition, body, ..
+        } => format!(
+            "while {} {{ {} }}",
+            format_expr(condition),
+            format_expr(body)
+        ),
+        Expr::Cond { arms, default, .. } => {
+            let mut arms: Vec<String> = arms
+                .iter()
+                .map(|(guard, value)| format!("{} => {}", format_expr(guard), format_expr(value)))
+                .collect();
+            if let Some(default) = default {
+                arms.push(format!("else => {}", format_expr(default)));
+            }
+            format!("cond {{ {} }}", arms.join(", "))
+        }
+        Expr::Let {
+            name, value, body, ..
+        } => format!(
+            "let {} = {} in {}",
+            name,
+            format_expr(value),
+            format_expr(body)
+        ),
+        Expr::Array { elements, .. } => {
+            let elements: Vec<String> = elements.iter().map(format_expr).collect();
+            format!("[{}]", elements.join(", "))
+        }
+        Expr::Record { fields, .. } => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name, format_expr(value)))
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        Expr::Field { record, name, .. } => {
+            format!("{}.{}", format_operand(record, CALL_BINDING), name)
+        }
+        Expr::Index { target, index, .. } => {
+            format!(
+                "{}[{}]",
+                format_operand(target, CALL_BINDING),
+                format_expr(index)
+            )
+        }
+        Expr::Try {
+            body,
+            binding,
+            handler,
+            ..
+        } => {
+            // A body starting with `(` would be read back as the parenthesized
+            // argument of `try(...)`, which ends at the matching `)`.
+            let body = format_expr(body);
+            let body = if body.starts_with('(') {
+                format!("({})", body)
+            } else {
+                body
+            };
+            format!(
+                "try {} catch {} {{ {} }}",
+                body,
+                binding,
+                format_expr(handler)
+            )
+        }
+    }
+}
+
+/// Renders a whole program, one statement per line.
+pub fn format_program(program: &Program) -> String {
+    program.to_string()
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format_expr(self))
+    }
+}
+
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stmt::NamedValue { name, expr } => write!(f, "def {} = {};", name, expr),
+            Stmt::Print { expr } => write!(f, "print {};", expr),
+            Stmt::Expr { expr } => write!(f, "{};", expr),
+        }
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut lines: Vec<String> = self.stmts.iter().map(Stmt::to_string).collect();
+        if let Some(expr) = &self.expr {
+            lines.push(expr.to_string());
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+pub(crate) fn operator_text(operator: &Token) -> &'static str {
+    match operator {
+        Token::Plus => "+",
+        Token::Minus => "-",
+        Token::Astrix => "*",
+        Token::Slash => "/",
+        Token::SlashSlash => "//",
+        Token::Percent => "%",
+        Token::Caret => "^",
+        Token::EqualEqual => "==",
+        Token::BangEqual => "!=",
+        Token::Less => "<",
+        Token::LessEqual => "<=",
+        Token::Greater => ">",
+        Token::GreaterEqual => ">=",
+        Token::AndAnd => "&&",
+        Token::PipePipe => "||",
+        Token::PipeGreater => "|>",
+        Token::DotDot => "..",
+        _ => unreachable!("{:?} is not an operator", operator),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{parse, strip_groupings};
+    use crate::scanner::tokenize_with_spans;
+
+    fn parse_source(source: &str) -> Program {
+        parse(&tokenize_with_spans(source).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn format_keeps_literal_forms() {
+        struct Test {
+            source: &'static str,
+            expected: &'static str,
+        }
+        let tests = [
+            Test {
+                source: "0xFF+1e3",
+                expected: "0xFF + 1e3",
+            },
+            Test {
+                source: "(1.50 +2.5E-2)*-x",
+                expected: "(1.50 + 2.5E-2) * -x",
+            },
+            Test {
+                source: "f( \"a\",true ) % 3 + 5%",
+                expected: "f(\"a\", true) % 3 + 5%",
+            },
+            Test {
+                source: "def x=1;print x;x",
+                expected: "def x = 1;\nprint x;\nx",
+            },
+        ];
+        for test in tests {
+            let formatted = format_program(&parse_source(test.source));
+            assert_eq!(formatted, test.expected, "{}", test.source);
+        }
+    }
+
+    #[test]
+    fn formatted_source_round_trips() {
+        for source in [
+            "0xFF + 1e3",
+            "-(2 - -3) // 0x10 * 4%",
+            "a && (b || 1 < 2)",
+            "fn a, b { a * b }(2, fn { 3 }())",
+            "while x > 0 { f(x) }",
+            "let x = 1 + 2 in (let y = x in y) * x",
+            "cond { x < 0 => -1, x > 0 => 1, else => 0 }",
+            "cond { a => b }",
+            "try 1 / 0 catch e { e } + 1",
+            "try ((a + 1) * 2) catch _ { 0 }",
+            "try(x) + 1",
+            "[1, [2, 3], []]",
+            "{x: 1, y: {z: [2]}}.y.z",
+            "[1, [2, 3]][1][a + 1]",
+            "{}",
+            "sum(0..n + 1) == 1..2",
+        ] {
+            let program = parse_source(source);
+            let reparsed = parse_source(&format_program(&program));
+            assert_eq!(reparsed, program, "{}", source);
+        }
+    }
+
+    #[test]
+    fn displayed_program_round_trips() {
+        let program = parse_source("def x=0x10;print x%;x*2;def f a=a+1;f(x)");
+        let displayed = format!("{}", program);
+        assert_eq!(
+            displayed,
+            "def x = 0x10;\nprint x%;\nx * 2;\ndef f = fn a { a + 1 };\nf(x)"
+        );
+        assert_eq!(parse_source(&displayed), program);
+        assert_eq!(program.stmts[0].to_string(), "def x = 0x10;");
+    }
+
+    #[test]
+    fn trees_without_groupings_get_the_parentheses_they_need() {
+        for (source, expected) in [
+            ("(1 + 2) * 3", "(1 + 2) * 3"),
+            ("1 + (2 * 3)", "1 + 2 * 3"),
+            ("1 - (2 - 3)", "1 - (2 - 3)"),
+            ("(1 - 2) - 3", "1 - 2 - 3"),
+            ("(2 ^ 3) ^ 2", "(2 ^ 3) ^ 2"),
+            ("2 ^ (3 ^ 2)", "2 ^ 3 ^ 2"),
+            ("(-2) ^ 2", "(-2) ^ 2"),
+            ("2 ^ -(1 + 1)", "2 ^ -(1 + 1)"),
+            ("(1 < 2) == (a && b)", "(1 < 2) == (a && b)"),
+            ("(x + 1)%", "(x + 1)%"),
+            ("(-x)%", "(-x)%"),
+            ("(f || g)(1).y", "(f || g)(1).y"),
+            ("(a + b)[(0)]", "(a + b)[0]"),
+            ("(let x = 1 in x) + 1", "(let x = 1 in x) + 1"),
+            ("(a |> f) + 1", "f(a) + 1"),
+            ("(0..n) == (1..2)", "0 .. n == 1 .. 2"),
+        ] {
+            let program = parse_source(source);
+            let stripped = strip_groupings(program.expr.clone().unwrap());
+            let formatted = format_expr(&stripped);
+            assert_eq!(formatted, expected, "{}", source);
+            assert_eq!(
+                strip_groupings(parse_source(&formatted).expr.unwrap()),
+                stripped,
+                "{}",
+                source
+            );
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How many entries are kept in the history file.
+pub const HISTORY_LIMIT: usize = 1000;
+
+/// The lines entered at the REPL, oldest first.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct History {
+    entries: Vec<String>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads one entry per line from `path`. A missing file is an empty
+    /// history, since there is none before the first session.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(Self {
+                entries: text.lines().map(str::to_string).collect(),
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Writes the most recent `HISTORY_LIMIT` entries to `path`.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let start = self.entries.len().saturating_sub(HISTORY_LIMIT);
+        let mut text = String::new();
+        for entry in &self.entries[start..] {
+            text.push_str(entry);
+            text.push('\n');
+        }
+        fs::write(path, text)
+    }
+
+    /// Records `line`, skipping it if it repeats the previous entry.
+    pub fn push(&mut self, line: &str) {
+        if self.entries.last().map(String::as_str) != Some(line) {
+            self.entries.push(line.to_string());
+        }
+    }
+
+    /// Up to `count` of the latest entries, oldest first, each with its
+    /// position in the whole history counting from 1.
+    pub fn recent(&self, count: usize) -> impl Iterator<Item = (usize, &str)> {
+        let start = self.entries.len().saturating_sub(count);
+        self.entries[start..]
+            .iter()
+            .enumerate()
+            .map(move |(i, entry)| (start + i + 1, entry.as_str()))
+    }
+}
+
+/// `~/.sal_history`, or `None` when there is no home directory.
+pub fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(|home| PathBuf::from(home).join(".sal_history"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("sal-history-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn history_round_trips_through_a_file() {
+        let path = temp_path("round-trip");
+        let mut history = History::new();
+        history.push("def x = 1;");
+        history.push("x + 1");
+        history.push("x + 1");
+        history.save(&path).unwrap();
+        let loaded = History::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, history);
+        let entries: Vec<(usize, &str)> = loaded.recent(10).collect();
+        assert_eq!(entries, vec![(1, "def x = 1;"), (2, "x + 1")]);
+    }
+
+    #[test]
+    fn missing_history_file_is_empty() {
+        let history = History::load(&temp_path("missing")).unwrap();
+        assert_eq!(history.recent(10).count(), 0);
+    }
+
+    #[test]
+    fn save_keeps_only_the_latest_entries() {
+        let path = temp_path("limit");
+        let mut history = History::new();
+        for i in 0..HISTORY_LIMIT + 5 {
+            history.push(&i.to_string());
+        }
+        history.save(&path).unwrap();
+        let loaded = History::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let entries: Vec<(usize, &str)> = loaded.recent(HISTORY_LIMIT + 5).collect();
+        assert_eq!(entries.len(), HISTORY_LIMIT);
+        assert_eq!(entries[0], (1, "5"));
+        assert_eq!(loaded.recent(1).next(), Some((HISTORY_LIMIT, "1004")));
+    }
+}
@@ -1,307 +1,3658 @@
-use crate::ast::Expr;
-use crate::scanner::Token;
+use crate::ast::{literal_integer, literal_is_float, Expr, Program, Stmt, DISCARD};
+use crate::builtins::{self, Builtin};
+use crate::formatter::format_expr;
+use crate::scanner::{Span, Token};
+use float_cmp::approx_eq;
+#[cfg(feature = "random")]
+use rand::{rngs::StdRng, SeedableRng};
+use rpds::{HashTrieMap, Vector};
+use std::any::Any;
+use std::cell::RefCell;
+#[cfg(feature = "random")]
+use std::cell::RefMut;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BTreeMap;
 use std::error::Error;
-use std::str::FromStr;
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Value {
     Number(f64),
+    // A number known to be an integer: an integer literal, or the result of
+    // integer arithmetic on them. Programs see it as a Number; only the
+    // arithmetic differs, which is exact and can overflow.
+    Int(i64),
+    Bool(bool),
+    String(String),
+    Function(Rc<Function>),
+    Array(Vector<Value>),
+    // Fields by name. They have no order; they are printed sorted by name.
+    Record(HashTrieMap<String, Value>),
+    // The whole numbers from `start` up to but not including `end`, as written
+    // `start..end`.
+    Range {
+        start: i64,
+        end: i64,
+    },
+    Nil,
+    Unit,
+    #[cfg(feature = "complex")]
+    Complex {
+        re: f64,
+        im: f64,
+    },
+    // A value of a type the host defines. Like functions, two custom values
+    // are only equal if they are the same value.
+    Custom(Rc<dyn CustomValue>),
+}
+
+/// A value of a type defined by the host rather than the language, such as a
+/// vector. Programs can only pass it around and apply the operators the host
+/// registers for it with `Engine::register_operator`.
+pub trait CustomValue: fmt::Debug + fmt::Display {
+    /// The name of the type, as reported in errors and, lowercased, by
+    /// `type_of`.
+    fn type_name(&self) -> &'static str;
+
+    /// The value as `Any`, so an operator can downcast it to its type.
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// Evaluates binary operators on operands the built-in operators don't
+/// accept, such as custom values.
+pub trait BinaryOp {
+    /// The result of `left operator right`, or None if these operands are not
+    /// ones this handles.
+    fn apply(
+        &self,
+        operator: &Token,
+        left: &Value,
+        right: &Value,
+    ) -> Option<Result<Value, Box<dyn Error>>>;
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Number(left), Value::Number(right)) => {
+                approx_eq!(f64, *left, *right, ulps = 2)
+            }
+            (Value::Int(left), Value::Int(right)) => left == right,
+            (Value::Int(int), Value::Number(number)) | (Value::Number(number), Value::Int(int)) => {
+                approx_eq!(f64, *int as f64, *number, ulps = 2)
+            }
+            (Value::Bool(left), Value::Bool(right)) => left == right,
+            (Value::String(left), Value::String(right)) => left == right,
+            (Value::Function(left), Value::Function(right)) => Rc::ptr_eq(left, right),
+            (Value::Custom(left), Value::Custom(right)) => Rc::ptr_eq(left, right),
+            (Value::Array(left), Value::Array(right)) => left == right,
+            (Value::Record(left), Value::Record(right)) => left == right,
+            (
+                Value::Range { start, end },
+                Value::Range {
+                    start: other_start,
+                    end: other_end,
+                },
+            ) => start == other_start && end == other_end,
+            (Value::Nil, Value::Nil) => true,
+            (Value::Unit, Value::Unit) => true,
+            #[cfg(feature = "complex")]
+            (Value::Complex { .. }, _) | (_, Value::Complex { .. }) => {
+                match (as_complex(self), as_complex(other)) {
+                    (Some((lre, lim)), Some((rre, rim))) => {
+                        approx_eq!(f64, lre, rre, ulps = 2) && approx_eq!(f64, lim, rim, ulps = 2)
+                    }
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A value that can be hashed and compared exactly, for use as a map key or
+/// a `match` pattern. `Value` itself can't implement `Eq` or `Hash`: its
+/// numbers compare with a tolerance, and NaN is not equal to itself. Keys
+/// therefore have no floats at all; only whole numbers become keys, as
+/// integers, so `2` and `2.0` are the same key while `0.5` is not a key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Key {
+    Integer(i64),
+    Bool(bool),
+    String(String),
+    Array(Vec<Key>),
+    Nil,
+    Unit,
+}
+
+impl From<Key> for Value {
+    fn from(key: Key) -> Self {
+        match key {
+            Key::Integer(integer) => Value::Int(integer),
+            Key::Bool(value) => Value::Bool(value),
+            Key::String(string) => Value::String(string),
+            Key::Array(keys) => Value::Array(keys.into_iter().map(Value::from).collect()),
+            Key::Nil => Value::Nil,
+            Key::Unit => Value::Unit,
+        }
+    }
+}
+
+// Ordering agrees with equality: numbers within the equality tolerance compare
+// as Equal even though their raw f64 values differ, so `a <= b` holds whenever
+// `a == b` does. Incomparable pairs (e.g. NaN) return None, which the
+// interpreter reports as a runtime error.
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self == other {
+            return Some(Ordering::Equal);
+        }
+        compare_numbers(self, other)
+    }
+}
+
+/// Orders two numbers by their values, comparing integers with floats exactly
+/// rather than rounding the integer to an f64 first. None if either is not a
+/// number, or is NaN.
+pub(crate) fn compare_numbers(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Int(left), Value::Int(right)) => Some(left.cmp(right)),
+        (Value::Number(left), Value::Number(right)) => left.partial_cmp(right),
+        (Value::Int(left), Value::Number(right)) => compare_int_float(*left, *right),
+        (Value::Number(left), Value::Int(right)) => {
+            compare_int_float(*right, *left).map(Ordering::reverse)
+        }
+        _ => None,
+    }
+}
+
+fn compare_int_float(int: i64, float: f64) -> Option<Ordering> {
+    // 2^63, the first f64 past `i64::MAX`. Every f64 between it and its
+    // negation has an integer part that fits in an i64.
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+    if float.is_nan() {
+        None
+    } else if float >= LIMIT {
+        Some(Ordering::Less)
+    } else if float < -LIMIT {
+        Some(Ordering::Greater)
+    } else {
+        let whole = float.trunc();
+        Some(
+            int.cmp(&(whole as i64))
+                .then(0.0.partial_cmp(&(float - whole))?),
+        )
+    }
+}
+
+/// A function value: its parameters, its body and the environment it was
+/// created in. Functions see the bindings that existed when they were made,
+/// plus their own name once a `def` gives them one, so they can recurse.
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: Option<String>,
+    pub params: Vec<String>,
+    pub body: Expr,
+    env: Environment,
+}
+
+impl Function {
+    /// The number of arguments the function must be called with.
+    pub fn arity(&self) -> usize {
+        self.params.len()
+    }
+
+    /// The name used for the function in error messages.
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or("<anonymous>")
+    }
+}
+
+/// An error raised while evaluating a well-formed program, such as dividing by
+/// zero or using an undefined variable. Unlike other errors, these can be
+/// caught by `try`.
+#[derive(Debug)]
+pub struct RuntimeError {
+    message: String,
+}
+
+impl RuntimeError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for RuntimeError {}
+
+/// Presentation settings for printing values. These never affect evaluation.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayOptions {
+    /// Insert thousands separators into integral numbers, e.g. `1,000,000`.
+    pub group_digits: bool,
+    /// Write numbers too large or too small to read at a glance in scientific
+    /// notation, e.g. `1.23e6`. Takes precedence over `group_digits`.
+    pub scientific: bool,
+    /// Write whole numbers in hexadecimal, e.g. `0x100`. Takes precedence over
+    /// the other options; fractions are written as usual.
+    pub hex: bool,
+}
+
+// The magnitudes written normally even in scientific mode.
+const PLAIN_RANGE: std::ops::Range<f64> = 1e-3..1e6;
+
+pub struct ValueDisplay<'a> {
+    value: &'a Value,
+    options: DisplayOptions,
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) | Value::Int(_) => "Number",
+            Value::Bool(_) => "Bool",
+            Value::String(_) => "String",
+            Value::Function(_) => "Function",
+            Value::Array(_) => "Array",
+            Value::Record(_) => "Record",
+            Value::Range { .. } => "Range",
+            Value::Nil => "Nil",
+            Value::Unit => "Unit",
+            #[cfg(feature = "complex")]
+            Value::Complex { .. } => "Complex",
+            Value::Custom(custom) => custom.type_name(),
+        }
+    }
+
+    /// Whether the value counts as true as the condition of `while` or `if`, a
+    /// `cond` guard or an operand of `&&` and `||`, when `strict_bool` is off:
+    /// `false`, `0`, `""`, `[]`, empty ranges, nil and unit are false, and
+    /// every other value is true.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Number(number) => *number != 0.0,
+            Value::Int(int) => *int != 0,
+            Value::Bool(value) => *value,
+            Value::String(string) => !string.is_empty(),
+            Value::Function(_) | Value::Custom(_) => true,
+            Value::Array(elements) => !elements.is_empty(),
+            Value::Record(fields) => !fields.is_empty(),
+            Value::Range { start, end } => start < end,
+            Value::Nil | Value::Unit => false,
+            #[cfg(feature = "complex")]
+            Value::Complex { re, im } => *re != 0.0 || *im != 0.0,
+        }
+    }
+
+    /// The key for this value, or an error for values that can't be hashed:
+    /// fractions, infinities and NaN, numbers beyond 2^53 - 1, functions,
+    /// custom values, complex numbers, and arrays containing any of them.
+    pub fn to_key(&self) -> Result<Key, RuntimeError> {
+        match self {
+            Value::Number(number) if number.fract() == 0.0 && number.abs() <= MAX_EXACT_INTEGER => {
+                Ok(Key::Integer(*number as i64))
+            }
+            Value::Int(int) => Ok(Key::Integer(*int)),
+            Value::Bool(value) => Ok(Key::Bool(*value)),
+            Value::String(string) => Ok(Key::String(string.clone())),
+            Value::Array(elements) => elements
+                .iter()
+                .map(Value::to_key)
+                .collect::<Result<_, _>>()
+                .map(Key::Array),
+            Value::Nil => Ok(Key::Nil),
+            Value::Unit => Ok(Key::Unit),
+            other => Err(RuntimeError::new(format!(
+                "{} can't be used as a key; only whole numbers, strings, booleans and arrays of them can",
+                other
+            ))),
+        }
+    }
+
+    /// Source text that evaluates back to this value, e.g. `5`, `"a"` or
+    /// `[1, true]`. Values that no source can produce are None: functions,
+    /// nil, unit, infinities and NaN, and strings containing `"`, since
+    /// strings have no escape sequences. Record fields are written sorted by
+    /// name.
+    pub fn to_source_literal(&self) -> Option<String> {
+        match self {
+            Value::Number(number) if number.is_finite() => Some(number.to_string()),
+            Value::Int(int) => Some(int.to_string()),
+            Value::Bool(value) => Some(value.to_string()),
+            Value::String(string) if !string.contains('"') => Some(format!("\"{}\"", string)),
+            Value::Array(elements) => {
+                let elements: Option<Vec<String>> =
+                    elements.iter().map(Value::to_source_literal).collect();
+                Some(format!("[{}]", elements?.join(", ")))
+            }
+            Value::Record(fields) => {
+                let fields: Option<Vec<String>> = sorted_fields(fields)
+                    .into_iter()
+                    .map(|(name, value)| Some(format!("{}: {}", name, value.to_source_literal()?)))
+                    .collect();
+                Some(format!("{{{}}}", fields?.join(", ")))
+            }
+            Value::Range { .. } => Some(self.to_string()),
+            #[cfg(feature = "complex")]
+            Value::Complex { re, im } if re.is_finite() && im.is_finite() => {
+                let sign = if im.is_sign_negative() { '-' } else { '+' };
+                Some(format!("{} {} {}i", re, sign, im.abs()))
+            }
+            _ => None,
+        }
+    }
+
+    /// The value of a number as an f64, rounded if it is an integer too large
+    /// for one to hold exactly, or None if the value is not a number.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            Value::Int(int) => Some(*int as f64),
+            _ => None,
+        }
+    }
+
+    pub fn display(&self, options: DisplayOptions) -> ValueDisplay<'_> {
+        ValueDisplay {
+            value: self,
+            options,
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Number(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Int(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = RuntimeError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(number) => Ok(number),
+            Value::Int(int) => Ok(int as f64),
+            other => Err(RuntimeError::new(format!(
+                "Expected a Number, got {}",
+                other.type_name()
+            ))),
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = RuntimeError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Bool(value) => Ok(value),
+            other => Err(RuntimeError::new(format!(
+                "Expected a Bool, got {}",
+                other.type_name()
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.display(DisplayOptions::default()).fmt(f)
+    }
+}
+
+impl fmt::Display for ValueDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.value {
+            // Negative zero prints as `0`, matching `-0 == 0`.
+            Value::Number(value) if *value == 0.0 => write!(f, "0"),
+            Value::Number(value) => {
+                if self.options.hex && value.fract() == 0.0 && value.abs() < u64::MAX as f64 {
+                    let sign = if *value < 0.0 { "-" } else { "" };
+                    write!(f, "{}{:#X}", sign, value.abs() as u64)
+                } else if self.options.scientific
+                    && value.is_finite()
+                    && !PLAIN_RANGE.contains(&value.abs())
+                {
+                    write!(f, "{:e}", value)
+                } else if self.options.group_digits && value.is_finite() && value.fract() == 0.0 {
+                    write!(f, "{}", group_digits(&value.to_string()))
+                } else {
+                    write!(f, "{}", value)
+                }
+            }
+            Value::Int(value) if self.options.hex => {
+                let sign = if *value < 0 { "-" } else { "" };
+                write!(f, "{}{:#X}", sign, value.unsigned_abs())
+            }
+            Value::Int(value) if self.options.scientific => {
+                write!(f, "{}", Value::Number(*value as f64).display(self.options))
+            }
+            Value::Int(value) if self.options.group_digits => {
+                write!(f, "{}", group_digits(&value.to_string()))
+            }
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Function(function) => match &function.name {
+                Some(name) => write!(f, "<fn {}>", name),
+                None => write!(f, "<fn>"),
+            },
+            Value::Array(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element.display(self.options))?;
+                }
+                write!(f, "]")
+            }
+            Value::Record(fields) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in sorted_fields(fields).into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", name, value.display(self.options))?;
+                }
+                write!(f, "}}")
+            }
+            Value::Range { start, end } => write!(f, "{}..{}", start, end),
+            Value::Nil => write!(f, "nil"),
+            Value::Unit => write!(f, "()"),
+            #[cfg(feature = "complex")]
+            Value::Complex { re, im } => {
+                let sign = if im.is_sign_negative() { '-' } else { '+' };
+                write!(f, "{}{}{}i", re, sign, im.abs())
+            }
+            Value::Custom(custom) => write!(f, "{}", custom),
+        }
+    }
+}
+
+fn sorted_fields(fields: &HashTrieMap<String, Value>) -> Vec<(&String, &Value)> {
+    let mut fields: Vec<_> = fields.iter().collect();
+    fields.sort_by_key(|(name, _)| *name);
+    fields
+}
+
+fn group_digits(digits: &str) -> String {
+    let (sign, digits) = match digits.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", digits),
+    };
+    let mut grouped = String::from(sign);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// Named values visible to an evaluation. Defining a name that is already
+/// bound replaces the earlier binding, so the latest `def` wins.
+///
+/// Bindings live in a persistent map, so a snapshot shares structure with the
+/// environment instead of copying every binding.
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+    values: HashTrieMap<String, Value>,
+    // Shared by every copy of the environment, including those captured by
+    // functions, so the whole program sees one clock, one random sequence and
+    // one profile.
+    host: Rc<Host>,
+}
+
+/// The source of the current time for the `now` built-in, replaceable so
+/// tests can fix the time.
+pub trait Clock {
+    /// Seconds since the Unix epoch.
+    fn now(&self) -> f64;
+}
+
+/// Looks up an environment variable for the `env` built-in, giving None when
+/// it is not set.
+pub type EnvLookup = dyn Fn(&str) -> Option<String>;
+
+/// The system's real-time clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> f64 {
+        // A clock set before 1970 reads as negative seconds.
+        match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(elapsed) => elapsed.as_secs_f64(),
+            Err(err) => -err.duration().as_secs_f64(),
+        }
+    }
+}
+
+/// How many expressions of each kind were evaluated, keyed by the name of
+/// their `Expr` variant.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    counts: BTreeMap<&'static str, usize>,
+}
+
+impl Profile {
+    /// How many `kind` expressions were evaluated, e.g. `count("Binary")`.
+    pub fn count(&self, kind: &str) -> usize {
+        self.counts.get(kind).copied().unwrap_or(0)
+    }
+
+    /// The kinds that were evaluated at least once and their counts, most
+    /// evaluated first.
+    pub fn counts(&self) -> Vec<(&'static str, usize)> {
+        let mut counts: Vec<_> = self
+            .counts
+            .iter()
+            .map(|(kind, count)| (*kind, *count))
+            .collect();
+        counts.sort_by_key(|(_, count)| Reverse(*count));
+        counts
+    }
+
+    fn record(&mut self, expr: &Expr) {
+        *self.counts.entry(expr.kind()).or_default() += 1;
+    }
+}
+
+// What built-ins use from outside the program, and what the evaluator
+// reports back to the host.
+struct Host {
+    clock: RefCell<Box<dyn Clock>>,
+    env_lookup: RefCell<Box<EnvLookup>>,
+    #[cfg(feature = "random")]
+    rng: RefCell<StdRng>,
+    // Counting only happens while this is set.
+    profile: RefCell<Option<Profile>>,
+    // Consulted in order of registration.
+    operators: RefCell<Vec<Box<dyn BinaryOp>>>,
+}
+
+impl Default for Host {
+    fn default() -> Self {
+        Self {
+            clock: RefCell::new(Box::new(SystemClock)),
+            env_lookup: RefCell::new(Box::new(|name| std::env::var(name).ok())),
+            #[cfg(feature = "random")]
+            rng: RefCell::new(StdRng::from_entropy()),
+            profile: RefCell::new(None),
+            operators: RefCell::new(vec![]),
+        }
+    }
+}
+
+impl fmt::Debug for Host {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Host").finish_non_exhaustive()
+    }
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restarts the sequence of random numbers from `seed`, so a program's
+    /// calls to `random` and `random_range` can be reproduced.
+    #[cfg(feature = "random")]
+    pub fn seed(&self, seed: u64) {
+        *self.host.rng.borrow_mut() = StdRng::seed_from_u64(seed);
+    }
+
+    /// Makes `now` read the time from `clock`.
+    pub fn set_clock(&self, clock: Box<dyn Clock>) {
+        *self.host.clock.borrow_mut() = clock;
+    }
+
+    /// Makes `env` read variables with `lookup`.
+    pub fn set_env_lookup(&self, lookup: Box<EnvLookup>) {
+        *self.host.env_lookup.borrow_mut() = lookup;
+    }
+
+    /// Adds `operator` to those consulted when a binary operator is applied to
+    /// operands the built-in operators don't accept.
+    pub fn register_operator(&self, operator: Box<dyn BinaryOp>) {
+        self.host.operators.borrow_mut().push(operator);
+    }
+
+    /// Starts counting the expressions evaluated against this environment,
+    /// discarding any earlier counts.
+    pub fn start_profile(&self) {
+        *self.host.profile.borrow_mut() = Some(Profile::default());
+    }
+
+    /// Stops counting and returns the counts since `start_profile`, or None
+    /// if profiling was not started.
+    pub fn take_profile(&self) -> Option<Profile> {
+        self.host.profile.borrow_mut().take()
+    }
+
+    /// Binds `name` to `value`, unless `name` is the discard name `_`.
+    pub fn define(&mut self, name: &str, value: Value) {
+        if name == DISCARD {
+            return;
+        }
+        self.values.insert_mut(name.to_string(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.values.get(name)
+    }
+
+    /// Every binding, sorted by name.
+    pub fn bindings(&self) -> Vec<(&str, &Value)> {
+        let mut bindings: Vec<(&str, &Value)> = self
+            .values
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+            .collect();
+        bindings.sort_by_key(|(name, _)| *name);
+        bindings
+    }
+
+    /// Captures the current bindings so they can be put back with `restore`.
+    pub fn snapshot(&self) -> EnvSnapshot {
+        EnvSnapshot {
+            values: self.values.clone(),
+        }
+    }
+
+    /// Discards every binding made since `snapshot` was taken.
+    pub fn restore(&mut self, snapshot: EnvSnapshot) {
+        self.values = snapshot.values;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EnvSnapshot {
+    values: HashTrieMap<String, Value>,
+}
+
+/// Which non-finite results are reported as runtime errors. A check that is
+/// off lets the IEEE 754 result through, e.g. `1 / 0` is `inf`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FiniteChecks {
+    /// Dividing by zero with `/`, `//` or `%`.
+    pub div_by_zero: bool,
+    /// Arithmetic on finite numbers producing an infinite result.
+    pub overflow: bool,
+}
+
+impl Default for FiniteChecks {
+    fn default() -> Self {
+        Self {
+            div_by_zero: true,
+            overflow: true,
+        }
+    }
+}
+
+/// What happens when arithmetic on two integers, or negating one, gives a
+/// result outside the i64 range. Only integers, `Value::Int`, can overflow:
+/// with a float operand, as in `1e300 * 10` or `2.0 ^ 64`, the arithmetic is
+/// f64 arithmetic and never an integer overflow.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IntOverflow {
+    /// The result is computed with f64s instead, so it is the nearest f64.
+    Rounding,
+    /// The overflow is reported as an error.
+    #[default]
+    Checked,
+    /// The result wraps around the i64 range.
+    Wrapping,
+    /// The result is the nearest end of the i64 range.
+    Saturating,
+}
+
+/// Settings that change how programs are evaluated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvalOptions {
+    pub finite_checks: FiniteChecks,
+    /// Report integer literals outside the i64 range as errors instead of
+    /// using the nearest f64.
+    pub reject_large_integers: bool,
+    /// How many times a single loop may run its body before evaluation is
+    /// stopped, so a runaway loop can't hang the REPL.
+    pub max_iterations: usize,
+    /// How many function calls may be nested before evaluation is stopped,
+    /// so runaway recursion is reported instead of using up memory. The stack
+    /// grows as needed, so any depth that fits in memory can be allowed.
+    pub max_depth: usize,
+    /// Make `/` truncate toward zero when both operands are integers, so
+    /// `7 / 2` is `3` rather than `3.5`. With a float operand, as in `7.0 / 2`
+    /// or `x / 2` where `x` is `7.0`, `/` is still true division.
+    pub integer_division: bool,
+    /// How arithmetic on integers handles results outside the i64 range.
+    pub int_overflow: IntOverflow,
+    /// Require the conditions of `while` and `if`, the guards of `cond` and
+    /// the operands of `&&` and `||` to be booleans. When off, any value is
+    /// accepted and judged by `Value::is_truthy`.
+    pub strict_bool: bool,
+}
+
+pub const DEFAULT_MAX_ITERATIONS: usize = 1_000_000;
+pub const DEFAULT_MAX_DEPTH: usize = 10_000;
+
+impl Default for EvalOptions {
+    fn default() -> Self {
+        Self {
+            finite_checks: FiniteChecks::default(),
+            reject_large_integers: false,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            max_depth: DEFAULT_MAX_DEPTH,
+            integer_division: false,
+            int_overflow: IntOverflow::default(),
+            strict_bool: true,
+        }
+    }
+}
+
+// What an expression is evaluated against.
+pub(crate) struct Context<'a> {
+    env: &'a Environment,
+    options: EvalOptions,
+    // How many function calls deep the evaluation is.
+    depth: usize,
+}
+
+impl Context<'_> {
+    pub(crate) fn is_defined(&self, name: &str) -> bool {
+        self.env.get(name).is_some()
+    }
+
+    pub(crate) fn now(&self) -> f64 {
+        self.env.host.clock.borrow().now()
+    }
+
+    pub(crate) fn env_var(&self, name: &str) -> Option<String> {
+        (self.env.host.env_lookup.borrow())(name)
+    }
+
+    #[cfg(feature = "random")]
+    pub(crate) fn rng(&self) -> RefMut<'_, StdRng> {
+        self.env.host.rng.borrow_mut()
+    }
+}
+
+pub fn evaluate_program(program: &Program) -> Result<Value, Box<dyn Error>> {
+    evaluate_with(
+        program,
+        &mut Environment::new(),
+        EvalOptions::default(),
+        &mut io::stdout(),
+    )
+}
+
+/// Evaluates `program` against a caller-supplied environment, so a host can
+/// pre-populate bindings before evaluation and inspect definitions afterwards.
+/// `print` statements write to `out`.
+pub fn evaluate_with(
+    program: &Program,
+    env: &mut Environment,
+    options: EvalOptions,
+    out: &mut dyn Write,
+) -> Result<Value, Box<dyn Error>> {
+    execute_statements(program, env, options, out, &mut |_| {})?;
+    match &program.expr {
+        Some(expr) => evaluate_expr(
+            expr,
+            &Context {
+                env,
+                options,
+                depth: 0,
+            },
+        ),
+        None => Ok(Value::Unit),
+    }
+}
+
+/// Like `evaluate_with`, but returns the value of every expression statement
+/// followed by the value of the trailing expression, if there is one.
+pub fn evaluate_each(
+    program: &Program,
+    env: &mut Environment,
+    options: EvalOptions,
+    out: &mut dyn Write,
+) -> Result<Vec<Value>, Box<dyn Error>> {
+    let mut values = vec![];
+    execute_statements(program, env, options, out, &mut |value| values.push(value))?;
+    if let Some(expr) = &program.expr {
+        values.push(evaluate_expr(
+            expr,
+            &Context {
+                env,
+                options,
+                depth: 0,
+            },
+        )?);
+    }
+    Ok(values)
+}
+
+/// Runs the statements of `program`, then reduces its trailing expression one
+/// step at a time, returning the formatted expression before each step and the
+/// final value. This is meant for showing how small expressions evaluate, not
+/// for running programs: `2 + 3 * 4` gives `2 + 3 * 4`, `2 + 12`, `14`.
+pub fn evaluate_traced(
+    program: &Program,
+    env: &mut Environment,
+    options: EvalOptions,
+    out: &mut dyn Write,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    execute_statements(program, env, options, out, &mut |_| {})?;
+    let ctx = Context {
+        env,
+        options,
+        depth: 0,
+    };
+    let mut expr = match &program.expr {
+        Some(expr) => expr.clone(),
+        None => return Ok(vec![]),
+    };
+    let mut steps = vec![format_expr(&expr)];
+    while !is_reduced(&expr) {
+        match reduce_step(&expr, &ctx)? {
+            Some(next) => {
+                expr = next;
+                // Negating a literal gives a literal that reads the same.
+                let step = format_expr(&expr);
+                if steps.last() != Some(&step) {
+                    steps.push(step);
+                }
+            }
+            // The next step yields a value with no literal form, such as nil,
+            // so finish the evaluation in one go.
+            None => {
+                steps.push(evaluate_expr(&expr, &ctx)?.to_string());
+                break;
+            }
+        }
+    }
+    Ok(steps)
+}
+
+fn is_reduced(expr: &Expr) -> bool {
+    match expr {
+        Expr::NumericLiteral { .. } | Expr::BooleanLiteral { .. } | Expr::StringLiteral { .. } => {
+            true
+        }
+        #[cfg(feature = "complex")]
+        Expr::ImaginaryLiteral { .. } => true,
+        _ => false,
+    }
+}
+
+// Rewrites the leftmost innermost reducible subexpression of `expr` with its
+// value. Calls, variables and logical operators with a known left operand are
+// reduced whole; groupings disappear as soon as their contents are a value.
+fn reduce_step(expr: &Expr, ctx: &Context) -> Result<Option<Expr>, Box<dyn Error>> {
+    let reduce_child = |child: &Expr| -> Result<Option<Box<Expr>>, Box<dyn Error>> {
+        Ok(reduce_step(child, ctx)?.map(Box::new))
+    };
+    let next = match expr {
+        Expr::Grouping { expr: inner, span } if !is_reduced(inner) => {
+            match reduce_step(inner, ctx)? {
+                Some(inner) if is_reduced(&inner) => Some(inner),
+                Some(inner) => Some(Expr::Grouping {
+                    expr: Box::new(inner),
+                    span: *span,
+                }),
+                None => None,
+            }
+        }
+        Expr::Binary {
+            left,
+            operator,
+            right,
+            span,
+        } if !is_reduced(left) => reduce_child(left)?.map(|left| Expr::Binary {
+            left,
+            operator: operator.clone(),
+            right: right.clone(),
+            span: *span,
+        }),
+        Expr::Binary {
+            left,
+            operator,
+            right,
+            span,
+        } if !is_reduced(right) && !matches!(operator, Token::AndAnd | Token::PipePipe) => {
+            reduce_child(right)?.map(|right| Expr::Binary {
+                left: left.clone(),
+                operator: operator.clone(),
+                right,
+                span: *span,
+            })
+        }
+        Expr::Unary {
+            operator,
+            right,
+            span,
+        } if !is_reduced(right) => reduce_child(right)?.map(|right| Expr::Unary {
+            operator: operator.clone(),
+            right,
+            span: *span,
+        }),
+        Expr::Postfix {
+            left,
+            operator,
+            span,
+        } if !is_reduced(left) => reduce_child(left)?.map(|left| Expr::Postfix {
+            left,
+            operator: operator.clone(),
+            span: *span,
+        }),
+        _ => value_literal(evaluate_expr(expr, ctx)?, expr.span()),
+    };
+    Ok(next)
+}
+
+fn value_literal(value: Value, span: Span) -> Option<Expr> {
+    match value {
+        Value::Number(number) => Some(Expr::NumericLiteral {
+            value: number.to_string(),
+            number,
+            span,
+        }),
+        Value::Int(int) => Some(Expr::NumericLiteral {
+            value: int.to_string(),
+            number: int as f64,
+            span,
+        }),
+        Value::Bool(value) => Some(Expr::BooleanLiteral { value, span }),
+        Value::String(value) => Some(Expr::StringLiteral { value, span }),
+        _ => None,
+    }
+}
+
+fn execute_statements(
+    program: &Program,
+    env: &mut Environment,
+    options: EvalOptions,
+    out: &mut dyn Write,
+    on_value: &mut dyn FnMut(Value),
+) -> Result<(), Box<dyn Error>> {
+    for stmt in &program.stmts {
+        match stmt {
+            Stmt::NamedValue { name, expr } => {
+                let value = evaluate_expr(
+                    expr,
+                    &Context {
+                        env,
+                        options,
+                        depth: 0,
+                    },
+                )?;
+                env.define(name, name_function(value, name));
+            }
+            Stmt::Print { expr } => {
+                let value = evaluate_expr(
+                    expr,
+                    &Context {
+                        env,
+                        options,
+                        depth: 0,
+                    },
+                )?;
+                writeln!(out, "{}", value)?;
+            }
+            Stmt::Expr { expr } => on_value(evaluate_expr(
+                expr,
+                &Context {
+                    env,
+                    options,
+                    depth: 0,
+                },
+            )?),
+        }
+    }
+    Ok(())
 }
 
-pub fn evaluate(expr: &Expr) -> Result<Value, Box<dyn Error>> {
-    match expr {
-        Expr::NumericLiteral { value } => {
-            let value = f64::from_str(value)?;
-            Ok(Value::Number(value))
+/// Evaluates parsed programs and expressions against an environment that
+/// persists between calls, writing `print` output to `out`. Unlike `Engine`,
+/// which is built on it, it takes trees rather than source text, and a failed
+/// evaluation keeps any definitions it made before the error.
+#[derive(Debug)]
+pub struct Interpreter<W: Write = io::Stdout> {
+    env: Environment,
+    out: W,
+    options: EvalOptions,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::with_output(io::stdout(), EvalOptions::default())
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<W: Write> Interpreter<W> {
+    pub fn with_output(out: W, options: EvalOptions) -> Self {
+        Self {
+            env: Environment::new(),
+            out,
+            options,
+        }
+    }
+
+    /// Evaluates a single expression against the current definitions.
+    pub fn eval(&mut self, expr: &Expr) -> Result<Value, Box<dyn Error>> {
+        evaluate_expr(
+            expr,
+            &Context {
+                env: &self.env,
+                options: self.options,
+                depth: 0,
+            },
+        )
+    }
+
+    /// Runs the statements of `program`, keeping its definitions, and returns
+    /// the value of its trailing expression.
+    pub fn eval_program(&mut self, program: &Program) -> Result<Value, Box<dyn Error>> {
+        evaluate_with(program, &mut self.env, self.options, &mut self.out)
+    }
+
+    // Runs `evaluate` on `program` with this interpreter's definitions and
+    // options, with `print` writing to `out` instead of the interpreter's
+    // own sink.
+    pub(crate) fn eval_program_with<T>(
+        &mut self,
+        program: &Program,
+        out: &mut dyn Write,
+        evaluate: impl FnOnce(
+            &Program,
+            &mut Environment,
+            EvalOptions,
+            &mut dyn Write,
+        ) -> Result<T, Box<dyn Error>>,
+    ) -> Result<T, Box<dyn Error>> {
+        evaluate(program, &mut self.env, self.options, out)
+    }
+
+    pub fn env(&self) -> &Environment {
+        &self.env
+    }
+
+    pub(crate) fn env_mut(&mut self) -> &mut Environment {
+        &mut self.env
+    }
+
+    /// Binds `name` to `value` as a `def` would, so programs can use values
+    /// made by the host, such as custom values.
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.env.define(name, value);
+    }
+
+    /// Restarts the random number sequence from `seed`, so evaluations that
+    /// use `random` give the same results every run.
+    #[cfg(feature = "random")]
+    pub fn seed(&mut self, seed: u64) {
+        self.env.seed(seed);
+    }
+
+    /// Makes the `now` built-in read the time from `clock` instead of the
+    /// system clock.
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) {
+        self.env.set_clock(Box::new(clock));
+    }
+
+    /// Makes the `env` built-in read variables with `lookup` instead of from
+    /// the process environment.
+    pub fn set_env_lookup(&mut self, lookup: impl Fn(&str) -> Option<String> + 'static) {
+        self.env.set_env_lookup(Box::new(lookup));
+    }
+
+    /// Lets programs apply binary operators to operands the built-in operators
+    /// don't accept, such as custom values, as `operator` defines. Operators
+    /// are tried in the order they were registered.
+    pub fn register_operator(&mut self, operator: impl BinaryOp + 'static) {
+        self.env.register_operator(Box::new(operator));
+    }
+
+    pub fn options(&self) -> EvalOptions {
+        self.options
+    }
+
+    pub fn set_options(&mut self, options: EvalOptions) {
+        self.options = options;
+    }
+
+    /// Starts counting evaluated expressions by kind; see `take_profile`.
+    pub fn start_profile(&self) {
+        self.env.start_profile();
+    }
+
+    /// The counts since `start_profile`, which also stops counting.
+    pub fn take_profile(&self) -> Option<Profile> {
+        self.env.take_profile()
+    }
+
+    /// The sink `print` writes to, e.g. to read back captured output.
+    pub fn output(&self) -> &W {
+        &self.out
+    }
+
+    /// The sink `print` writes to, e.g. to write results between its output.
+    pub fn output_mut(&mut self) -> &mut W {
+        &mut self.out
+    }
+}
+
+/// Evaluates `expr` on its own, with no definitions and default options.
+pub fn evaluate(expr: &Expr) -> Result<Value, Box<dyn Error>> {
+    Interpreter::new().eval(expr)
+}
+
+// How much of the stack must be left before evaluating an expression, and how
+// much to add when less is. A program's recursion recurses just as deep here,
+// so rather than overflowing the thread's stack, evaluation carries on in a
+// new stack segment whenever the current one runs low; `max_depth` is then
+// the only limit on recursion. The parser grows its stack the same way.
+pub(crate) const STACK_RED_ZONE: usize = 256 * 1024;
+pub(crate) const STACK_SEGMENT: usize = 8 * 1024 * 1024;
+
+fn evaluate_expr(expr: &Expr, ctx: &Context) -> Result<Value, Box<dyn Error>> {
+    stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || evaluate_node(expr, ctx))
+}
+
+fn evaluate_node(expr: &Expr, ctx: &Context) -> Result<Value, Box<dyn Error>> {
+    if let Some(profile) = ctx.env.host.profile.borrow_mut().as_mut() {
+        profile.record(expr);
+    }
+    match expr {
+        Expr::NumericLiteral { value, .. }
+            if ctx.options.reject_large_integers && !integer_literal_fits(value) =>
+        {
+            Err(RuntimeError::new(format!("Integer literal too large: {}", value)).into())
+        }
+        // Integer literals are read from their text, since the f64 of one
+        // past 2^53 may not be the integer written.
+        Expr::NumericLiteral { value, number, .. } => {
+            Ok(literal_integer(value).map_or(Value::Number(*number), Value::Int))
+        }
+        #[cfg(feature = "complex")]
+        Expr::ImaginaryLiteral { number, .. } => Ok(Value::Complex {
+            re: 0.0,
+            im: *number,
+        }),
+        Expr::BooleanLiteral { value, .. } => Ok(Value::Bool(*value)),
+        Expr::StringLiteral { value, .. } => Ok(Value::String(value.clone())),
+        Expr::Grouping { expr, .. } => {
+            let value = evaluate_expr(expr, ctx)?;
+            Ok(value)
+        }
+        Expr::Postfix { left, operator, .. } => {
+            match (operator, evaluate_expr(left, ctx)?.as_f64()) {
+                (Token::Percent, Some(left)) => Ok(Value::Number(left / 100.0)),
+                _ => Err(RuntimeError::new("Not supported").into()),
+            }
+        }
+        Expr::Unary {
+            operator, right, ..
+        } => match (operator, evaluate_expr(right, ctx)?) {
+            (Token::Minus, Value::Number(right)) => Ok(Value::Number(-right)),
+            (Token::Minus, Value::Int(right)) => integer_result(
+                right
+                    .checked_neg()
+                    .ok_or((right.wrapping_neg(), right.saturating_neg())),
+                ctx.options.int_overflow,
+                || Ok(Value::Number(-(right as f64))),
+            ),
+            #[cfg(feature = "complex")]
+            (Token::Minus, Value::Complex { re, im }) => Ok(Value::Complex { re: -re, im: -im }),
+            _ => Err(RuntimeError::new("Not supported").into()),
+        },
+        Expr::Variable { name, .. } => match ctx.env.get(name) {
+            Some(value) => Ok(value.clone()),
+            None => Err(RuntimeError::new(format!("Undefined variable: {}", name)).into()),
+        },
+        Expr::Function { params, body, .. } => Ok(Value::Function(Rc::new(Function {
+            name: None,
+            params: params.clone(),
+            body: body.as_ref().clone(),
+            env: ctx.env.clone(),
+        }))),
+        Expr::While {
+            condition, body, ..
+        } => evaluate_while(condition, body, ctx),
+        Expr::Cond {
+            arms,
+            default,
+            keyword,
+            ..
+        } => evaluate_cond(arms, default.as_deref(), keyword, ctx),
+        Expr::Let {
+            name, value, body, ..
+        } => {
+            let value = evaluate_expr(value, ctx)?;
+            let mut env = ctx.env.clone();
+            env.define(name, name_function(value, name));
+            evaluate_expr(
+                body,
+                &Context {
+                    env: &env,
+                    options: ctx.options,
+                    depth: ctx.depth,
+                },
+            )
+        }
+        Expr::Array { elements, .. } => {
+            let mut values = Vector::new();
+            for element in elements {
+                values.push_back_mut(evaluate_expr(element, ctx)?);
+            }
+            Ok(Value::Array(values))
+        }
+        Expr::Record { fields, .. } => {
+            let mut values = HashTrieMap::new();
+            for (name, value) in fields {
+                values.insert_mut(name.clone(), evaluate_expr(value, ctx)?);
+            }
+            Ok(Value::Record(values))
+        }
+        Expr::Try {
+            body,
+            binding,
+            handler,
+            ..
+        } => match evaluate_expr(body, ctx) {
+            Err(err) if err.is::<RuntimeError>() => {
+                let mut env = ctx.env.clone();
+                env.define(binding, Value::String(err.to_string()));
+                evaluate_expr(
+                    handler,
+                    &Context {
+                        env: &env,
+                        options: ctx.options,
+                        depth: ctx.depth,
+                    },
+                )
+            }
+            result => result,
+        },
+        Expr::Field { record, name, .. } => match evaluate_expr(record, ctx)? {
+            Value::Record(fields) => match fields.get(name) {
+                Some(value) => Ok(value.clone()),
+                None => Err(RuntimeError::new(format!("No such field: {}", name)).into()),
+            },
+            other => Err(RuntimeError::new(format!(
+                "Expected a record before .{}, got {}",
+                name,
+                other.type_name()
+            ))
+            .into()),
+        },
+        Expr::Index { target, index, .. } => {
+            let target = evaluate_expr(target, ctx)?;
+            let index = evaluate_expr(index, ctx)?;
+            element_at(&target, &index)
+        }
+        // Built-ins are only used when no definition shadows their name.
+        Expr::Call { callee, args, .. } => match callee.as_ref() {
+            Expr::Variable { name, .. } if name == "try" && ctx.env.get(name).is_none() => {
+                evaluate_try(args, ctx)
+            }
+            Expr::Variable { name, .. } if name == "bench" && ctx.env.get(name).is_none() => {
+                evaluate_bench(args, ctx)
+            }
+            Expr::Variable { name, .. } if ctx.env.get(name).is_none() => {
+                match builtins::lookup(name) {
+                    Some(builtin) => evaluate_builtin(builtin, args, ctx),
+                    None => Err(RuntimeError::new(format!("Undefined variable: {}", name)).into()),
+                }
+            }
+            _ => match evaluate_expr(callee, ctx)? {
+                Value::Function(function) => call_function(&function, args, ctx),
+                _ => Err(RuntimeError::new("Can only call functions").into()),
+            },
+        },
+        Expr::Binary {
+            left,
+            operator,
+            right,
+            ..
+        } if matches!(operator, Token::AndAnd | Token::PipePipe) => {
+            evaluate_logical(left, operator, right, ctx)
+        }
+        Expr::Binary {
+            left,
+            operator,
+            right,
+            ..
+        } => {
+            let left = evaluate_expr(left, ctx)?;
+            let right = evaluate_expr(right, ctx)?;
+            match (operator, left, right) {
+                (
+                    Token::Plus
+                    | Token::Minus
+                    | Token::Astrix
+                    | Token::Slash
+                    | Token::SlashSlash
+                    | Token::Percent
+                    | Token::Caret,
+                    left @ (Value::Number(_) | Value::Int(_)),
+                    right @ (Value::Number(_) | Value::Int(_)),
+                ) => numeric_arithmetic(operator, &left, &right, ctx.options),
+                #[cfg(feature = "complex")]
+                (
+                    Token::Plus | Token::Minus | Token::Astrix | Token::Slash,
+                    left @ (Value::Number(_) | Value::Int(_) | Value::Complex { .. }),
+                    right @ (Value::Number(_) | Value::Int(_) | Value::Complex { .. }),
+                ) => complex_arithmetic(operator, &left, &right, ctx.options.finite_checks),
+                (Token::DotDot, left, right) => range(&left, &right),
+                (Token::EqualEqual, left, right) => Ok(Value::Bool(left == right)),
+                (Token::BangEqual, left, right) => Ok(Value::Bool(left != right)),
+                (
+                    Token::Less | Token::LessEqual | Token::Greater | Token::GreaterEqual,
+                    left,
+                    right,
+                ) => compare(operator, &left, &right),
+                (operator, left, right) => ctx
+                    .env
+                    .host
+                    .operators
+                    .borrow()
+                    .iter()
+                    .find_map(|registered| registered.apply(operator, &left, &right))
+                    .unwrap_or_else(|| Err(RuntimeError::new("Not supported").into())),
+            }
+        }
+    }
+}
+
+// Whether `literal` is within the i64 range, judged from its text since the
+// parsed f64 can't tell `2^63 - 1` from `2^63`. Literals with a fraction or an
+// exponent aren't integers and always fit.
+fn integer_literal_fits(literal: &str) -> bool {
+    literal_is_float(literal) || literal_integer(literal).is_some()
+}
+
+// The largest integer below which every integer has an exact f64, 2^53 - 1.
+pub(crate) const MAX_EXACT_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+// Arithmetic on two integers stays exact; with any float operand, both are
+// converted to f64s.
+fn numeric_arithmetic(
+    operator: &Token,
+    left: &Value,
+    right: &Value,
+    options: EvalOptions,
+) -> Result<Value, Box<dyn Error>> {
+    match (left, right) {
+        (Value::Int(left), Value::Int(right)) => {
+            integer_arithmetic(operator, *left, *right, options)
+        }
+        _ => match (left.as_f64(), right.as_f64()) {
+            (Some(left), Some(right)) => arithmetic(operator, left, right, options),
+            _ => Err(RuntimeError::new("Not supported").into()),
+        },
+    }
+}
+
+// f64 arithmetic, on floats and on integers mixed with floats. `/` is always
+// true division here; only two integers divide as integers.
+fn arithmetic(
+    operator: &Token,
+    left: f64,
+    right: f64,
+    options: EvalOptions,
+) -> Result<Value, Box<dyn Error>> {
+    let checks = options.finite_checks;
+    if checks.div_by_zero && right == 0.0 {
+        match operator {
+            Token::Slash | Token::SlashSlash => {
+                return Err(RuntimeError::new("Division by zero").into())
+            }
+            Token::Percent => return Err(RuntimeError::new("Modulo by zero").into()),
+            _ => {}
+        }
+    }
+    // `0 ^ -n` is `1 / 0 ^ n`.
+    if checks.div_by_zero && *operator == Token::Caret && left == 0.0 && right < 0.0 {
+        return Err(RuntimeError::new("Division by zero").into());
+    }
+    let result = match operator {
+        Token::Plus => left + right,
+        Token::Minus => left - right,
+        Token::Astrix => left * right,
+        Token::Slash => left / right,
+        Token::SlashSlash => (left / right).floor(),
+        Token::Caret => left.powf(right),
+        _ => left % right,
+    };
+    if checks.overflow
+        && result.is_infinite()
+        && left.is_finite()
+        && right.is_finite()
+        && right != 0.0
+    {
+        return Err(RuntimeError::new("Numeric overflow").into());
+    }
+    Ok(Value::Number(result))
+}
+
+// Exact arithmetic on two integers. A result outside the i64 range is handled
+// as `int_overflow` says. A division that may not be whole, a negative
+// exponent and a zero divisor are left to f64 arithmetic, which also reports
+// division by zero.
+fn integer_arithmetic(
+    operator: &Token,
+    left: i64,
+    right: i64,
+    options: EvalOptions,
+) -> Result<Value, Box<dyn Error>> {
+    let float = || arithmetic(operator, left as f64, right as f64, options);
+    // Each result is the exact one, or else the wrapped and saturated ones.
+    let result = match operator {
+        Token::Plus => left
+            .checked_add(right)
+            .ok_or((left.wrapping_add(right), left.saturating_add(right))),
+        Token::Minus => left
+            .checked_sub(right)
+            .ok_or((left.wrapping_sub(right), left.saturating_sub(right))),
+        Token::Astrix => left
+            .checked_mul(right)
+            .ok_or((left.wrapping_mul(right), left.saturating_mul(right))),
+        Token::Caret if right >= 0 => {
+            // Past `u32::MAX`, the largest exponent with the same parity gives
+            // the same result, except when wrapping a base other than -1, 0,
+            // 1 or a power of 2.
+            let exponent = u32::try_from(right).unwrap_or(u32::MAX - (right % 2 == 0) as u32);
+            left.checked_pow(exponent)
+                .ok_or((left.wrapping_pow(exponent), left.saturating_pow(exponent)))
+        }
+        Token::Slash if options.integer_division && right != 0 => left
+            .checked_div(right)
+            .ok_or((left.wrapping_div(right), left.saturating_div(right))),
+        Token::SlashSlash if right != 0 => match left.checked_div(right) {
+            // Division truncates toward zero, so a negative quotient with a
+            // remainder is one above the floor.
+            Some(quotient) if left % right != 0 && (left < 0) != (right < 0) => Ok(quotient - 1),
+            Some(quotient) => Ok(quotient),
+            None => Err((left.wrapping_div(right), left.saturating_div(right))),
+        },
+        // `i64::MIN % -1` is the only remainder that overflows, and it is 0.
+        Token::Percent if right != 0 => Ok(left.wrapping_rem(right)),
+        _ => return float(),
+    };
+    integer_result(result, options.int_overflow, float)
+}
+
+// The value of an integer operation, given its exact result or, when that
+// overflows, its wrapped and saturated results, and how to compute it with
+// f64s for `Rounding`.
+fn integer_result(
+    result: Result<i64, (i64, i64)>,
+    overflow: IntOverflow,
+    float: impl FnOnce() -> Result<Value, Box<dyn Error>>,
+) -> Result<Value, Box<dyn Error>> {
+    match (result, overflow) {
+        (Ok(result), _) => Ok(Value::Int(result)),
+        (Err(_), IntOverflow::Rounding) => float(),
+        (Err(_), IntOverflow::Checked) => Err(RuntimeError::new("Integer overflow").into()),
+        (Err((wrapped, _)), IntOverflow::Wrapping) => Ok(Value::Int(wrapped)),
+        (Err((_, saturated)), IntOverflow::Saturating) => Ok(Value::Int(saturated)),
+    }
+}
+
+// Reals are promoted to complex numbers with a zero imaginary part.
+#[cfg(feature = "complex")]
+fn as_complex(value: &Value) -> Option<(f64, f64)> {
+    match value {
+        Value::Number(re) => Some((*re, 0.0)),
+        Value::Int(re) => Some((*re as f64, 0.0)),
+        Value::Complex { re, im } => Some((*re, *im)),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "complex")]
+fn complex_arithmetic(
+    operator: &Token,
+    left: &Value,
+    right: &Value,
+    checks: FiniteChecks,
+) -> Result<Value, Box<dyn Error>> {
+    let (Some((a, b)), Some((c, d))) = (as_complex(left), as_complex(right)) else {
+        return Err(RuntimeError::new("Not supported").into());
+    };
+    let (re, im) = match operator {
+        Token::Plus => (a + c, b + d),
+        Token::Minus => (a - c, b - d),
+        Token::Astrix => (a * c - b * d, a * d + b * c),
+        _ => {
+            let divisor = c * c + d * d;
+            if divisor == 0.0 && checks.div_by_zero {
+                return Err(RuntimeError::new("Division by zero").into());
+            }
+            ((a * c + b * d) / divisor, (b * c - a * d) / divisor)
+        }
+    };
+    Ok(Value::Complex { re, im })
+}
+
+// `start..end`; both bounds must be integers.
+fn range(start: &Value, end: &Value) -> Result<Value, Box<dyn Error>> {
+    let bound = |value: &Value| match value {
+        Value::Int(int) => Ok(*int),
+        Value::Number(number) if number.fract() == 0.0 && number.abs() <= MAX_EXACT_INTEGER => {
+            Ok(*number as i64)
+        }
+        other => Err(RuntimeError::new(format!(
+            "Range bounds must be integers, got {}",
+            other
+        ))),
+    };
+    Ok(Value::Range {
+        start: bound(start)?,
+        end: bound(end)?,
+    })
+}
+
+fn compare(operator: &Token, left: &Value, right: &Value) -> Result<Value, Box<dyn Error>> {
+    let ordering = match left.partial_cmp(right) {
+        Some(ordering) => ordering,
+        None => {
+            return Err(RuntimeError::new(format!(
+                "Cannot compare {} and {}",
+                left.type_name().to_lowercase(),
+                right.type_name().to_lowercase()
+            ))
+            .into())
+        }
+    };
+    let result = match operator {
+        Token::Less => ordering == Ordering::Less,
+        Token::LessEqual => ordering != Ordering::Greater,
+        Token::Greater => ordering == Ordering::Greater,
+        _ => ordering != Ordering::Less,
+    };
+    Ok(Value::Bool(result))
+}
+
+// `target[index]`: the element of an array, or the character of a string as a
+// string, at a whole-number index counted from 0.
+fn element_at(target: &Value, index: &Value) -> Result<Value, Box<dyn Error>> {
+    let position = match *index {
+        Value::Int(position) => position,
+        Value::Number(position) if position.fract() == 0.0 => position as i64,
+        ref other => {
+            return Err(
+                RuntimeError::new(format!("Expected a whole number index, got {}", other)).into(),
+            )
+        }
+    };
+    let (element, length, sequence, items) = match target {
+        Value::Array(elements) => (
+            usize::try_from(position)
+                .ok()
+                .and_then(|position| elements.get(position).cloned()),
+            elements.len(),
+            "an array",
+            "elements",
+        ),
+        Value::String(string) => (
+            usize::try_from(position)
+                .ok()
+                .and_then(|position| string.chars().nth(position))
+                .map(|character| Value::String(character.to_string())),
+            string.chars().count(),
+            "a string",
+            "characters",
+        ),
+        other => {
+            return Err(RuntimeError::new(format!(
+                "Expected an array or string before [, got {}",
+                other.type_name()
+            ))
+            .into())
+        }
+    };
+    element.ok_or_else(|| {
+        RuntimeError::new(format!(
+            "Index {} is out of bounds for {} of {} {}",
+            position, sequence, length, items
+        ))
+        .into()
+    })
+}
+
+// The truth of a condition, or the value itself back when it must be a boolean
+// and isn't.
+fn condition(value: Value, options: EvalOptions) -> Result<bool, Value> {
+    match value {
+        Value::Bool(value) => Ok(value),
+        other if options.strict_bool => Err(other),
+        other => Ok(other.is_truthy()),
+    }
+}
+
+// `&&` and `||` only evaluate their right operand when the left one does not
+// already decide the result. They always produce a boolean, even when their
+// operands are judged by truthiness.
+fn evaluate_logical(
+    left: &Expr,
+    operator: &Token,
+    right: &Expr,
+    ctx: &Context,
+) -> Result<Value, Box<dyn Error>> {
+    let expect_bool = |value: Value| {
+        condition(value, ctx.options).map_err(|other| {
+            RuntimeError::new(format!(
+                "Expected a boolean operand for {}, got {}",
+                if *operator == Token::AndAnd {
+                    "&&"
+                } else {
+                    "||"
+                },
+                other
+            ))
+        })
+    };
+    let left = expect_bool(evaluate_expr(left, ctx)?)?;
+    match (operator, left) {
+        (Token::AndAnd, false) => Ok(Value::Bool(false)),
+        (Token::PipePipe, true) => Ok(Value::Bool(true)),
+        _ => Ok(Value::Bool(expect_bool(evaluate_expr(right, ctx)?)?)),
+    }
+}
+
+// A loop evaluates to unit; the values of its body are discarded.
+fn evaluate_while(condition: &Expr, body: &Expr, ctx: &Context) -> Result<Value, Box<dyn Error>> {
+    let mut iterations: usize = 0;
+    loop {
+        match self::condition(evaluate_expr(condition, ctx)?, ctx.options) {
+            Ok(false) => return Ok(Value::Unit),
+            Ok(true) => {}
+            Err(other) => {
+                return Err(RuntimeError::new(format!(
+                    "Expected a boolean condition for while, got {}",
+                    other
+                ))
+                .into())
+            }
+        }
+        if iterations == ctx.options.max_iterations {
+            return Err("Maximum loop iterations exceeded".into());
+        }
+        iterations += 1;
+        evaluate_expr(body, ctx)?;
+    }
+}
+
+// Guards are tried in order, and only up to the first true one. `keyword` is
+// the construct they were written in, `if` or `cond`, for errors to name.
+fn evaluate_cond(
+    arms: &[(Expr, Expr)],
+    default: Option<&Expr>,
+    keyword: &Token,
+    ctx: &Context,
+) -> Result<Value, Box<dyn Error>> {
+    for (guard, value) in arms {
+        match condition(evaluate_expr(guard, ctx)?, ctx.options) {
+            Ok(true) => return evaluate_expr(value, ctx),
+            Ok(false) => {}
+            Err(other) => {
+                let guard = match keyword {
+                    Token::If => "condition for if",
+                    _ => "guard for cond",
+                };
+                return Err(RuntimeError::new(format!(
+                    "Expected a boolean {}, got {}",
+                    guard, other
+                ))
+                .into());
+            }
+        }
+    }
+    match default {
+        Some(default) => evaluate_expr(default, ctx),
+        None => Err(RuntimeError::new("No cond arm matched").into()),
+    }
+}
+
+// A function defined with `def` takes the name it was defined with.
+fn name_function(value: Value, name: &str) -> Value {
+    match value {
+        Value::Function(function) if function.name.is_none() => {
+            Value::Function(Rc::new(Function {
+                name: Some(name.to_string()),
+                ..function.as_ref().clone()
+            }))
+        }
+        value => value,
+    }
+}
+
+fn arity_error(name: &str, expected: usize, got: usize) -> Box<dyn Error> {
+    let plural = if expected == 1 { "" } else { "s" };
+    RuntimeError::new(format!(
+        "{} expects {} argument{}, got {}",
+        name, expected, plural, got
+    ))
+    .into()
+}
+
+// Arguments are evaluated in the caller's environment; the body is evaluated in
+// the function's own environment with the parameters bound.
+fn call_function(
+    function: &Rc<Function>,
+    args: &[Expr],
+    ctx: &Context,
+) -> Result<Value, Box<dyn Error>> {
+    check_arity(function, args.len())?;
+    let mut values = Vec::with_capacity(args.len());
+    for arg in args {
+        values.push(evaluate_expr(arg, ctx)?);
+    }
+    apply_function(function, values, ctx)
+}
+
+fn check_arity(function: &Function, got: usize) -> Result<(), Box<dyn Error>> {
+    if got != function.arity() {
+        let plural = if function.arity() == 1 { "" } else { "s" };
+        return Err(RuntimeError::new(format!(
+            "Function '{}' expected {} argument{}, got {}",
+            function.display_name(),
+            function.arity(),
+            plural,
+            got
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Unwraps the number passed to the built-in `fn_name`, or reports the type
+/// that was passed instead. Every numeric built-in checks its arguments with
+/// this, so they all word the error the same way.
+pub(crate) fn expect_number(value: &Value, fn_name: &str) -> Result<f64, Box<dyn Error>> {
+    match value.as_f64() {
+        Some(number) => Ok(number),
+        None => Err(RuntimeError::new(format!(
+            "{} expects a number, got {}",
+            fn_name,
+            value.type_name()
+        ))
+        .into()),
+    }
+}
+
+/// Calls `function` with arguments that have already been evaluated, as the
+/// higher-order built-ins do.
+pub(crate) fn apply_function(
+    function: &Rc<Function>,
+    args: Vec<Value>,
+    ctx: &Context,
+) -> Result<Value, Box<dyn Error>> {
+    check_arity(function, args.len())?;
+    // Not a `RuntimeError`, so `try` can't catch it: a handler would run just
+    // as deep and the recursion would carry on.
+    if ctx.depth == ctx.options.max_depth {
+        return Err("Maximum recursion depth exceeded".into());
+    }
+    let mut env = function.env.clone();
+    if let Some(name) = &function.name {
+        env.define(name, Value::Function(function.clone()));
+    }
+    for (param, value) in function.params.iter().zip(args) {
+        env.define(param, value);
+    }
+    evaluate_expr(
+        &function.body,
+        &Context {
+            env: &env,
+            options: ctx.options,
+            depth: ctx.depth + 1,
+        },
+    )
+}
+
+// `try` receives its argument unevaluated so that a runtime error raised while
+// evaluating it can be turned into `nil` instead of aborting the program.
+fn evaluate_try(args: &[Expr], ctx: &Context) -> Result<Value, Box<dyn Error>> {
+    if args.len() != 1 {
+        return Err(arity_error("try", 1, args.len()));
+    }
+    match evaluate_expr(&args[0], ctx) {
+        Ok(value) => Ok(value),
+        Err(err) if err.is::<RuntimeError>() => Ok(Value::Nil),
+        Err(err) => Err(err),
+    }
+}
+
+// `bench` also receives its first argument unevaluated, so that it can be
+// evaluated `runs` times. The average is in milliseconds, read from the same
+// clock as `now`.
+fn evaluate_bench(args: &[Expr], ctx: &Context) -> Result<Value, Box<dyn Error>> {
+    if args.len() != 2 {
+        return Err(arity_error("bench", 2, args.len()));
+    }
+    let runs = match evaluate_expr(&args[1], ctx)? {
+        Value::Int(runs) if runs >= 1 => runs as f64,
+        Value::Number(runs) if runs >= 1.0 && runs.fract() == 0.0 => runs,
+        other => {
+            return Err(RuntimeError::new(format!(
+                "bench expects a positive whole number of runs, got {}",
+                other
+            ))
+            .into())
+        }
+    };
+    // The runs are a loop, so they are held to the same limit as `while`.
+    if runs > ctx.options.max_iterations as f64 {
+        return Err("Maximum loop iterations exceeded".into());
+    }
+    let started = ctx.now();
+    for _ in 0..runs as u64 {
+        evaluate_expr(&args[0], ctx)?;
+    }
+    Ok(Value::Number((ctx.now() - started) * 1000.0 / runs))
+}
+
+fn evaluate_builtin(
+    builtin: &Builtin,
+    args: &[Expr],
+    ctx: &Context,
+) -> Result<Value, Box<dyn Error>> {
+    if args.len() != builtin.arity {
+        return Err(arity_error(builtin.name, builtin.arity, args.len()));
+    }
+    let mut values = Vec::with_capacity(args.len());
+    for arg in args {
+        values.push(evaluate_expr(arg, ctx)?);
+    }
+    (builtin.function)(&values, ctx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse;
+    use crate::scanner::tokenize_with_spans;
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashSet;
+    use std::hash::{Hash, Hasher};
+
+    fn evaluate_source(source: &str) -> Result<Value, Box<dyn Error>> {
+        let tokens = tokenize_with_spans(source)?;
+        let program = parse(&tokens)?;
+        evaluate_program(&program)
+    }
+
+    #[test]
+    fn suffixed_literals_choose_the_value_type() {
+        assert!(matches!(evaluate_source("5_i64").unwrap(), Value::Int(5)));
+        assert!(
+            matches!(evaluate_source("5_f64").unwrap(), Value::Number(number) if number == 5.0)
+        );
+        assert!(matches!(
+            evaluate_source("0xFF_i64").unwrap(),
+            Value::Int(255)
+        ));
+        assert!(
+            matches!(evaluate_source("0xFF_f64").unwrap(), Value::Number(number) if number == 255.0)
+        );
+        assert!(matches!(
+            evaluate_source("9223372036854775807_i64").unwrap(),
+            Value::Int(i64::MAX)
+        ));
+        assert!(matches!(
+            evaluate_source("2.5_f64 * 2_i64").unwrap(),
+            Value::Number(number) if number == 5.0
+        ));
+        assert_eq!(
+            evaluate_source("type_of(-5_i64)").unwrap(),
+            Value::String("number".into())
+        );
+        let err = evaluate_source("5_u8").unwrap_err();
+        assert_eq!(err.to_string(), "Unknown number suffix: _u8");
+    }
+
+    #[test]
+    fn order_numbers() {
+        assert!(Value::Number(1.0) < Value::Number(2.0));
+        assert!(Value::Number(-3.5) < Value::Number(0.0));
+        assert!(Value::Number(10.0) > Value::Number(9.999));
+        assert!(Value::Number(2.0) >= Value::Number(2.0));
+        assert_eq!(
+            Value::Number(4.0).partial_cmp(&Value::Number(4.0)),
+            Some(Ordering::Equal)
+        );
+    }
+
+    #[test]
+    fn near_equal_numbers_order_as_equal() {
+        let left = Value::Number(0.1 + 0.2);
+        let right = Value::Number(0.3);
+        assert_eq!(left.partial_cmp(&right), Some(Ordering::Equal));
+        assert!(left <= right);
+        assert!(left >= right);
+    }
+
+    #[test]
+    fn incomparable_values_have_no_order() {
+        let nan = Value::Number(f64::NAN);
+        assert_eq!(nan.partial_cmp(&Value::Number(1.0)), None);
+        assert_eq!(Value::Number(1.0).partial_cmp(&nan), None);
+    }
+
+    #[test]
+    fn evaluate_number() {
+        struct Test {
+            expr: Expr,
+            expected: Value,
+        }
+        let tests = vec![
+            Test {
+                expr: Expr::NumericLiteral {
+                    value: String::from("123.345"),
+                    number: 123.345,
+                    span: (0, 0),
+                },
+                expected: Value::Number(123.345),
+            },
+            Test {
+                expr: Expr::NumericLiteral {
+                    value: String::from("0"),
+                    number: 0.0,
+                    span: (0, 0),
+                },
+                expected: Value::Number(0.0),
+            },
+            Test {
+                expr: Expr::NumericLiteral {
+                    value: String::from("0.0"),
+                    number: 0.0,
+                    span: (0, 0),
+                },
+                expected: Value::Number(0.0),
+            },
+        ];
+        for test in tests {
+            let value = evaluate(&test.expr).unwrap();
+            assert_eq!(value, test.expected);
+        }
+    }
+
+    #[test]
+    fn evaluate_addition() {
+        struct Test {
+            expr: Expr,
+            expected: Value,
+        }
+        let tests = vec![
+            Test {
+                expr: Expr::Binary {
+                    left: Box::new(Expr::NumericLiteral {
+                        value: "123.345".into(),
+                        number: 123.345,
+                        span: (0, 0),
+                    }),
+                    right: Box::new(Expr::NumericLiteral {
+                        value: "1.0".into(),
+                        number: 1.0,
+                        span: (0, 0),
+                    }),
+                    operator: Token::Plus,
+                    span: (0, 0),
+                },
+                expected: Value::Number(124.345),
+            },
+            Test {
+                expr: Expr::Binary {
+                    left: Box::new(Expr::NumericLiteral {
+                        value: "8753.0".into(),
+                        number: 8753.0,
+                        span: (0, 0),
+                    }),
+                    right: Box::new(Expr::NumericLiteral {
+                        value: "0.0".into(),
+                        number: 0.0,
+                        span: (0, 0),
+                    }),
+                    operator: Token::Plus,
+                    span: (0, 0),
+                },
+                expected: Value::Number(8753.0),
+            },
+        ];
+        for test in tests {
+            let value = evaluate(&test.expr).unwrap();
+            assert_eq!(value, test.expected);
+        }
+    }
+
+    #[test]
+    fn evaluate_subtraction() {
+        struct Test {
+            expr: Expr,
+            expected: Value,
+        }
+        let tests = vec![
+            Test {
+                expr: Expr::Binary {
+                    left: Box::new(Expr::NumericLiteral {
+                        value: "123.345".into(),
+                        number: 123.345,
+                        span: (0, 0),
+                    }),
+                    right: Box::new(Expr::NumericLiteral {
+                        value: "1.0".into(),
+                        number: 1.0,
+                        span: (0, 0),
+                    }),
+                    operator: Token::Minus,
+                    span: (0, 0),
+                },
+                expected: Value::Number(122.345),
+            },
+            Test {
+                expr: Expr::Binary {
+                    left: Box::new(Expr::NumericLiteral {
+                        value: "8753.0".into(),
+                        number: 8753.0,
+                        span: (0, 0),
+                    }),
+                    right: Box::new(Expr::NumericLiteral {
+                        value: "0.0".into(),
+                        number: 0.0,
+                        span: (0, 0),
+                    }),
+                    operator: Token::Minus,
+                    span: (0, 0),
+                },
+                expected: Value::Number(8753.0),
+            },
+        ];
+        for test in tests {
+            let value = evaluate(&test.expr).unwrap();
+            assert_eq!(value, test.expected);
+        }
+    }
+
+    #[test]
+    fn evaluate_multiplication() {
+        struct Test {
+            expr: Expr,
+            expected: Value,
+        }
+        let tests = vec![
+            Test {
+                expr: Expr::Binary {
+                    left: Box::new(Expr::NumericLiteral {
+                        value: "123.345".into(),
+                        number: 123.345,
+                        span: (0, 0),
+                    }),
+                    right: Box::new(Expr::NumericLiteral {
+                        value: "1.0".into(),
+                        number: 1.0,
+                        span: (0, 0),
+                    }),
+                    operator: Token::Astrix,
+                    span: (0, 0),
+                },
+                expected: Value::Number(123.345),
+            },
+            Test {
+                expr: Expr::Binary {
+                    left: Box::new(Expr::NumericLiteral {
+                        value: "8753.0".into(),
+                        number: 8753.0,
+                        span: (0, 0),
+                    }),
+                    right: Box::new(Expr::NumericLiteral {
+                        value: "0.0".into(),
+                        number: 0.0,
+                        span: (0, 0),
+                    }),
+                    operator: Token::Astrix,
+                    span: (0, 0),
+                },
+                expected: Value::Number(0.0),
+            },
+        ];
+        for test in tests {
+            let value = evaluate(&test.expr).unwrap();
+            assert_eq!(value, test.expected);
+        }
+    }
+
+    #[test]
+    fn evaluate_division() {
+        struct Test {
+            expr: Expr,
+            expected: Value,
+        }
+        let tests = vec![
+            Test {
+                expr: Expr::Binary {
+                    left: Box::new(Expr::NumericLiteral {
+                        value: "123.345".into(),
+                        number: 123.345,
+                        span: (0, 0),
+                    }),
+                    right: Box::new(Expr::NumericLiteral {
+                        value: "1.0".into(),
+                        number: 1.0,
+                        span: (0, 0),
+                    }),
+                    operator: Token::Slash,
+                    span: (0, 0),
+                },
+                expected: Value::Number(123.345),
+            },
+            Test {
+                expr: Expr::Binary {
+                    left: Box::new(Expr::NumericLiteral {
+                        value: "8753.0".into(),
+                        number: 8753.0,
+                        span: (0, 0),
+                    }),
+                    right: Box::new(Expr::NumericLiteral {
+                        value: "2.2".into(),
+                        number: 2.2,
+                        span: (0, 0),
+                    }),
+                    operator: Token::Slash,
+                    span: (0, 0),
+                },
+                expected: Value::Number(3_978.636_363_636_363_5),
+            },
+        ];
+        for test in tests {
+            let value = evaluate(&test.expr).unwrap();
+            assert_eq!(value, test.expected);
+        }
+    }
+
+    #[test]
+    fn evaluate_precedence() {
+        struct Test {
+            expr: Expr,
+            expected: Value,
+        }
+        let tests = vec![
+            Test {
+                expr: Expr::Binary {
+                    left: Box::new(Expr::Binary {
+                        left: Box::new(Expr::NumericLiteral {
+                            value: "123.345".into(),
+                            number: 123.345,
+                            span: (0, 0),
+                        }),
+                        right: Box::new(Expr::Binary {
+                            left: Box::new(Expr::NumericLiteral {
+                                value: "1.0".into(),
+                                number: 1.0,
+                                span: (0, 0),
+                            }),
+                            right: Box::new(Expr::NumericLiteral {
+                                value: "1.345".into(),
+                                number: 1.345,
+                                span: (0, 0),
+                            }),
+                            operator: Token::Slash,
+                            span: (0, 0),
+                        }),
+                        operator: Token::Plus,
+                        span: (0, 0),
+                    }),
+                    right: Box::new(Expr::NumericLiteral {
+                        value: "10.0".into(),
+                        number: 10.0,
+                        span: (0, 0),
+                    }),
+                    operator: Token::Minus,
+                    span: (0, 0),
+                },
+                expected: Value::Number(114.0884944237918),
+            },
+            Test {
+                expr: Expr::Binary {
+                    left: Box::new(Expr::Binary {
+                        left: Box::new(Expr::NumericLiteral {
+                            value: "123.345".into(),
+                            number: 123.345,
+                            span: (0, 0),
+                        }),
+                        right: Box::new(Expr::Binary {
+                            left: Box::new(Expr::NumericLiteral {
+                                value: "1.0".into(),
+                                number: 1.0,
+                                span: (0, 0),
+                            }),
+                            right: Box::new(Expr::NumericLiteral {
+                                value: "1.345".into(),
+                                number: 1.345,
+                                span: (0, 0),
+                            }),
+                            operator: Token::Minus,
+                            span: (0, 0),
+                        }),
+                        operator: Token::Plus,
+                        span: (0, 0),
+                    }),
+                    right: Box::new(Expr::NumericLiteral {
+                        value: "10.0".into(),
+                        number: 10.0,
+                        span: (0, 0),
+                    }),
+                    operator: Token::Astrix,
+                    span: (0, 0),
+                },
+                expected: Value::Number(1230.0),
+            },
+        ];
+        for test in tests {
+            let value = evaluate(&test.expr).unwrap();
+            assert_eq!(value, test.expected);
+        }
+    }
+
+    #[test]
+    fn evaluate_definitions() {
+        let value = evaluate_source("def x = 2; def y = x * 3; y + x").unwrap();
+        assert_eq!(value, Value::Number(8.0));
+    }
+
+    #[test]
+    fn pipe_applies_functions_left_to_right() {
+        assert_eq!(evaluate_source("16 |> sqrt").unwrap(), Value::Number(4.0));
+        assert_eq!(
+            evaluate_source("-256 |> abs |> sqrt |> sqrt").unwrap(),
+            Value::Number(4.0)
+        );
+        assert_eq!(
+            evaluate_source("def half x = x / 2; def inc x = x + 1; 10 |> half |> inc").unwrap(),
+            Value::Number(6.0)
+        );
+        assert_eq!(
+            evaluate_source("9 |> clamp(0, 4) |> sqrt").unwrap(),
+            Value::Number(2.0)
+        );
+        assert_eq!(
+            evaluate_source("[3, 1, 2] |> sort |> reverse")
+                .unwrap()
+                .to_string(),
+            "[3, 2, 1]"
+        );
+    }
+
+    #[test]
+    fn later_definition_replaces_earlier() {
+        let value = evaluate_source("def x = 1; def x = 2; x").unwrap();
+        assert_eq!(value, Value::Number(2.0));
+    }
+
+    #[test]
+    fn redefinition_can_refer_to_previous_binding() {
+        let value = evaluate_source("def x = 1; def x = x + 10; x").unwrap();
+        assert_eq!(value, Value::Number(11.0));
+    }
+
+    #[test]
+    fn undefined_variable_is_an_error() {
+        let err = evaluate_source("def x = 1; y").unwrap_err();
+        assert_eq!(format!("{}", err), "Undefined variable: y");
+    }
+
+    #[test]
+    fn display_numbers() {
+        struct Test {
+            value: Value,
+            expected: &'static str,
+        }
+        let tests = vec![
+            Test {
+                value: Value::Number(1000000.0),
+                expected: "1000000",
+            },
+            Test {
+                value: Value::Number(0.5),
+                expected: "0.5",
+            },
+            Test {
+                value: Value::Number(-42.0),
+                expected: "-42",
+            },
+        ];
+        for test in tests {
+            assert_eq!(format!("{}", test.value), test.expected);
+        }
+    }
+
+    #[test]
+    fn values_round_trip_through_source_literals() {
+        struct Test {
+            value: Value,
+            expected: &'static str,
         }
-        Expr::Grouping { expr } => {
-            let value = evaluate(expr)?;
-            Ok(value)
+        let tests = vec![
+            Test {
+                value: Value::Number(5.0),
+                expected: "5",
+            },
+            Test {
+                value: Value::Number(-0.125),
+                expected: "-0.125",
+            },
+            Test {
+                value: Value::String("a b".into()),
+                expected: "\"a b\"",
+            },
+            Test {
+                value: Value::Bool(true),
+                expected: "true",
+            },
+            Test {
+                value: Value::Array(
+                    Vector::new()
+                        .push_back(Value::Number(1.0))
+                        .push_back(Value::Array(Vector::new())),
+                ),
+                expected: "[1, []]",
+            },
+        ];
+        for test in tests {
+            let literal = test.value.to_source_literal().unwrap();
+            assert_eq!(literal, test.expected);
+            assert_eq!(evaluate_source(&literal).unwrap(), test.value);
         }
-        Expr::Binary {
-            left,
-            operator,
-            right,
-        } => {
-            let left = evaluate(left)?;
-            let right = evaluate(right)?;
-            match (operator, left, right) {
-                (Token::Plus, Value::Number(left), Value::Number(right)) => {
-                    Ok(Value::Number(left + right))
-                }
-                (Token::Minus, Value::Number(left), Value::Number(right)) => {
-                    Ok(Value::Number(left - right))
-                }
-                (Token::Astrix, Value::Number(left), Value::Number(right)) => {
-                    Ok(Value::Number(left * right))
-                }
-                (Token::Slash, Value::Number(left), Value::Number(right)) => {
-                    Ok(Value::Number(left / right))
-                }
-                _ => Err("Not supported".into()),
-            }
+    }
+
+    #[test]
+    fn values_without_source_literals() {
+        let function = evaluate_source("fn x { x }").unwrap();
+        for value in [
+            function.clone(),
+            Value::Array(Vector::new().push_back(function)),
+            Value::Nil,
+            Value::Unit,
+            Value::Number(f64::INFINITY),
+            Value::Number(f64::NAN),
+            Value::String("say \"hi\"".into()),
+        ] {
+            assert_eq!(value.to_source_literal(), None, "{:?}", value);
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use float_cmp::approx_eq;
+    #[test]
+    fn equal_values_have_equal_keys() {
+        fn hash(key: &Key) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            key.hash(&mut hasher);
+            hasher.finish()
+        }
+        let pairs = [
+            (Value::Number(2.0), Value::Number(2.0)),
+            (Value::Number(0.0), Value::Number(-0.0)),
+            (Value::String("a".into()), Value::String("a".into())),
+            (Value::Bool(true), Value::Bool(true)),
+            (
+                evaluate_source("[1, \"b\", [false]]").unwrap(),
+                evaluate_source("[1.0, \"b\", [1 > 2]]").unwrap(),
+            ),
+        ];
+        for (left, right) in pairs {
+            let (left_key, right_key) = (left.to_key().unwrap(), right.to_key().unwrap());
+            assert_eq!(left_key, right_key);
+            assert_eq!(hash(&left_key), hash(&right_key));
+            assert_eq!(Value::from(left_key), left);
+        }
+        assert_ne!(
+            Value::Number(1.0).to_key().unwrap(),
+            Value::String("1".into()).to_key().unwrap()
+        );
+        let keys: HashSet<Key> = ["1", "1.0", "\"1\"", "true", "1"]
+            .iter()
+            .map(|source| evaluate_source(source).unwrap().to_key().unwrap())
+            .collect();
+        assert_eq!(keys.len(), 3);
+    }
 
-    impl PartialEq for Value {
-        fn eq(&self, other: &Self) -> bool {
-            match (self, other) {
-                (Value::Number(left), Value::Number(right)) => {
-                    approx_eq!(f64, *left, *right, ulps = 2)
-                }
-            }
+    #[test]
+    fn floats_are_not_keys() {
+        for source in ["0.5", "[1, 2.5]", "fn { 1 }", "1e300"] {
+            let value = evaluate_source(source).unwrap();
+            assert!(value.to_key().is_err(), "{}", source);
         }
+        assert_eq!(
+            Value::Number(f64::NAN).to_key().unwrap_err().to_string(),
+            "NaN can't be used as a key; only whole numbers, strings, booleans and arrays of them can"
+        );
     }
 
     #[test]
-    fn evaluate_number() {
+    fn display_grouped_digits() {
         struct Test {
-            expr: Expr,
+            value: Value,
+            expected: &'static str,
+        }
+        let tests = vec![
+            Test {
+                value: Value::Number(1000000.0),
+                expected: "1,000,000",
+            },
+            Test {
+                value: Value::Number(999.0),
+                expected: "999",
+            },
+            Test {
+                value: Value::Number(1000.0),
+                expected: "1,000",
+            },
+            Test {
+                value: Value::Number(-1234567.0),
+                expected: "-1,234,567",
+            },
+            Test {
+                value: Value::Number(1234.5),
+                expected: "1234.5",
+            },
+        ];
+        let options = DisplayOptions {
+            group_digits: true,
+            ..DisplayOptions::default()
+        };
+        for test in tests {
+            assert_eq!(format!("{}", test.value.display(options)), test.expected);
+        }
+    }
+
+    #[test]
+    fn division_by_zero_is_an_error() {
+        let err = evaluate_source("1 / 0").unwrap_err();
+        assert_eq!(format!("{}", err), "Division by zero");
+        assert!(err.is::<RuntimeError>());
+    }
+
+    #[test]
+    fn try_returns_value_or_nil() {
+        struct Test {
+            source: &'static str,
             expected: Value,
         }
         let tests = vec![
             Test {
-                expr: Expr::NumericLiteral {
-                    value: String::from("123.345"),
-                },
-                expected: Value::Number(123.345),
+                source: "try(1 / 2)",
+                expected: Value::Number(0.5),
             },
             Test {
-                expr: Expr::NumericLiteral {
-                    value: String::from("0"),
-                },
-                expected: Value::Number(0.0),
+                source: "try(1 / 0)",
+                expected: Value::Nil,
             },
             Test {
-                expr: Expr::NumericLiteral {
-                    value: String::from("0.0"),
-                },
-                expected: Value::Number(0.0),
+                source: "try(undefined + 1)",
+                expected: Value::Nil,
+            },
+            Test {
+                source: "def x = try(4 / 0); x",
+                expected: Value::Nil,
             },
         ];
         for test in tests {
-            let value = evaluate(&test.expr).unwrap();
+            let value = evaluate_source(test.source).unwrap();
             assert_eq!(value, test.expected);
         }
     }
 
     #[test]
-    fn evaluate_addition() {
+    fn try_catch_binds_the_error_message() {
+        assert_eq!(
+            evaluate_source("try 1 / 2 catch e { 0 }").unwrap(),
+            Value::Number(0.5)
+        );
+        assert_eq!(
+            evaluate_source("try 1 / 0 catch e { e }").unwrap(),
+            Value::String("Division by zero".into())
+        );
+        assert_eq!(
+            evaluate_source("def f x = try x.y catch err { err }; f(1)").unwrap(),
+            Value::String("Expected a record before .y, got Number".into())
+        );
+        // The name is bound only in the handler.
+        let err = evaluate_source("try 1 / 0 catch e { 0 } + e").unwrap_err();
+        assert_eq!(err.to_string(), "Undefined variable: e");
+        // An error in the handler is not caught again.
+        let err = evaluate_source("try 1 / 0 catch e { e + 1 }").unwrap_err();
+        assert!(err.is::<RuntimeError>());
+    }
+
+    #[test]
+    fn try_does_not_catch_the_recursion_limit() {
+        let source = "def f n = f(n + 1);";
+        for handler in ["try f(0) catch e { 0 }", "try(f(0))"] {
+            let err = evaluate_source(&format!("{} {}", source, handler)).unwrap_err();
+            assert_eq!(err.to_string(), "Maximum recursion depth exceeded");
+        }
+    }
+
+    #[test]
+    fn try_does_not_catch_the_loop_limit() {
+        for handler in [
+            "try while true { 1 } catch e { 0 }",
+            "try(while true { 1 })",
+            "try bench(1, 1e300) catch e { 0 }",
+        ] {
+            let err = evaluate_source(handler).unwrap_err();
+            assert_eq!(err.to_string(), "Maximum loop iterations exceeded");
+        }
+    }
+
+    #[test]
+    fn try_body_may_start_with_parentheses() {
+        assert_eq!(
+            evaluate_source("def x = 1; try (x).y catch e { e }").unwrap(),
+            Value::String("Expected a record before .y, got Number".into())
+        );
+        assert_eq!(
+            evaluate_source("def f = 2; try (f)(1) catch e { e }").unwrap(),
+            Value::String("Can only call functions".into())
+        );
+        assert_eq!(
+            evaluate_source("try (1) + 2 catch e { e }").unwrap(),
+            Value::Number(3.0)
+        );
+        assert_eq!(evaluate_source("try(1 / 0)").unwrap(), Value::Nil);
+        assert_eq!(
+            evaluate_source("try(1 / 0) + 1").unwrap_err().to_string(),
+            "Not supported"
+        );
+    }
+
+    #[test]
+    fn try_body_may_be_indexed() {
+        assert_eq!(
+            evaluate_source("try [1][5] catch e { e }").unwrap(),
+            Value::String("Index 5 is out of bounds for an array of 1 elements".into())
+        );
+        assert_eq!(
+            evaluate_source("try (1 / 0) + 1 catch e { e }").unwrap(),
+            Value::String("Division by zero".into())
+        );
+    }
+
+    #[test]
+    fn index_arrays_and_strings() {
+        assert_eq!(evaluate_source("[1, 2, 3][1]").unwrap(), Value::Int(2));
+        assert_eq!(
+            evaluate_source("\"héllo\"[1]").unwrap(),
+            Value::String("é".into())
+        );
+        assert_eq!(
+            evaluate_source("[1][-1]").unwrap_err().to_string(),
+            "Index -1 is out of bounds for an array of 1 elements"
+        );
+        assert_eq!(
+            evaluate_source("\"ab\"[2]").unwrap_err().to_string(),
+            "Index 2 is out of bounds for a string of 2 characters"
+        );
+        assert_eq!(
+            evaluate_source("[1][0.5]").unwrap_err().to_string(),
+            "Expected a whole number index, got 0.5"
+        );
+        assert_eq!(
+            evaluate_source("1[0]").unwrap_err().to_string(),
+            "Expected an array or string before [, got Number"
+        );
+    }
+
+    #[test]
+    fn try_does_not_catch_parse_errors() {
+        let err = evaluate_source("try(1 +)").unwrap_err();
+        assert!(!err.is::<RuntimeError>());
+    }
+
+    #[test]
+    fn evaluation_uses_the_cached_number() {
+        // Only an integer literal's text is read again, for its exact value.
+        let expr = Expr::NumericLiteral {
+            value: "not a number".into(),
+            number: 4.0,
+            span: (0, 0),
+        };
+        assert!(matches!(evaluate(&expr).unwrap(), Value::Number(number) if number == 4.0));
+    }
+
+    #[test]
+    fn try_expects_one_argument() {
+        let err = evaluate_source("try(1, 2)").unwrap_err();
+        assert_eq!(format!("{}", err), "try expects 1 argument, got 2");
+    }
+
+    #[test]
+    fn display_nil() {
+        assert_eq!(format!("{}", Value::Nil), "nil");
+    }
+
+    #[test]
+    fn evaluate_comparisons() {
         struct Test {
-            expr: Expr,
+            source: &'static str,
             expected: Value,
         }
         let tests = vec![
             Test {
-                expr: Expr::Binary {
-                    left: Box::new(Expr::NumericLiteral {
-                        value: "123.345".into(),
-                    }),
-                    right: Box::new(Expr::NumericLiteral {
-                        value: "1.0".into(),
-                    }),
-                    operator: Token::Plus,
-                },
-                expected: Value::Number(124.345),
+                source: "1 < 2",
+                expected: Value::Bool(true),
+            },
+            Test {
+                source: "2 <= 2",
+                expected: Value::Bool(true),
+            },
+            Test {
+                source: "1 > 2",
+                expected: Value::Bool(false),
+            },
+            Test {
+                source: "3 >= 4",
+                expected: Value::Bool(false),
+            },
+            Test {
+                source: "0.1 + 0.2 == 0.3",
+                expected: Value::Bool(true),
+            },
+            Test {
+                source: "1 != 1",
+                expected: Value::Bool(false),
+            },
+            Test {
+                source: "true == false",
+                expected: Value::Bool(false),
+            },
+            Test {
+                source: "1 == true",
+                expected: Value::Bool(false),
+            },
+        ];
+        for test in tests {
+            let value = evaluate_source(test.source).unwrap();
+            assert_eq!(value, test.expected, "{}", test.source);
+        }
+    }
+
+    #[test]
+    fn evaluate_logical_operators() {
+        struct Test {
+            source: &'static str,
+            expected: Value,
+        }
+        let tests = vec![
+            Test {
+                source: "1 < 2 && 2 < 3",
+                expected: Value::Bool(true),
+            },
+            Test {
+                source: "1 < 2 && 3 < 2",
+                expected: Value::Bool(false),
+            },
+            Test {
+                source: "1 > 2 || 2 < 3",
+                expected: Value::Bool(true),
+            },
+            Test {
+                source: "false || false",
+                expected: Value::Bool(false),
+            },
+            // The right operand is never evaluated, so the division is fine.
+            Test {
+                source: "false && 1 / 0 == 1",
+                expected: Value::Bool(false),
+            },
+            Test {
+                source: "true || 1 / 0 == 1",
+                expected: Value::Bool(true),
+            },
+        ];
+        for test in tests {
+            let value = evaluate_source(test.source).unwrap();
+            assert_eq!(value, test.expected, "{}", test.source);
+        }
+    }
+
+    #[test]
+    fn logical_operators_require_booleans() {
+        let err = evaluate_source("1 && true").unwrap_err();
+        assert_eq!(
+            format!("{}", err),
+            "Expected a boolean operand for &&, got 1"
+        );
+    }
+
+    #[test]
+    fn comparing_incomparable_values_is_an_error() {
+        let err = evaluate_source("(1 < 2) < 3").unwrap_err();
+        assert_eq!(format!("{}", err), "Cannot compare bool and number");
+        let err = evaluate_source("\"a\" < \"b\"").unwrap_err();
+        assert_eq!(format!("{}", err), "Cannot compare string and string");
+    }
+
+    #[test]
+    fn chained_comparisons_are_an_error() {
+        let err = evaluate_source("1 < 2 < 3").unwrap_err();
+        assert_eq!(
+            format!("{}", err),
+            "Chained comparisons are not supported; use && instead"
+        );
+    }
+
+    #[test]
+    fn type_names() {
+        assert_eq!(Value::Number(1.0).type_name(), "Number");
+        assert_eq!(Value::Bool(true).type_name(), "Bool");
+        assert_eq!(Value::Nil.type_name(), "Nil");
+        assert_eq!(Value::Unit.type_name(), "Unit");
+    }
+
+    #[test]
+    fn evaluate_with_preseeded_environment() {
+        let mut env = Environment::new();
+        env.define("x", Value::Number(10.0));
+        let tokens = tokenize_with_spans("x * 2").unwrap();
+        let program = parse(&tokens).unwrap();
+        let value =
+            evaluate_with(&program, &mut env, EvalOptions::default(), &mut io::sink()).unwrap();
+        assert_eq!(value, Value::Number(20.0));
+    }
+
+    #[test]
+    fn evaluate_with_keeps_definitions() {
+        let mut env = Environment::new();
+        env.define("width", Value::Number(3.0));
+        let tokens = tokenize_with_spans("def area = width * 4; area").unwrap();
+        let program = parse(&tokens).unwrap();
+        evaluate_with(&program, &mut env, EvalOptions::default(), &mut io::sink()).unwrap();
+        assert_eq!(env.get("area"), Some(&Value::Number(12.0)));
+    }
+
+    #[test]
+    fn evaluate_floor_division() {
+        struct Test {
+            source: &'static str,
+            expected: Value,
+        }
+        let tests = vec![
+            Test {
+                source: "7 // 2",
+                expected: Value::Number(3.0),
+            },
+            Test {
+                source: "-7 // 2",
+                expected: Value::Number(-4.0),
+            },
+            Test {
+                source: "7.5 // 0.5",
+                expected: Value::Number(15.0),
+            },
+            Test {
+                source: "7 / 2",
+                expected: Value::Number(3.5),
+            },
+        ];
+        for test in tests {
+            let value = evaluate_source(test.source).unwrap();
+            assert_eq!(value, test.expected, "{}", test.source);
+        }
+        let err = evaluate_source("1 // 0").unwrap_err();
+        assert_eq!(format!("{}", err), "Division by zero");
+    }
+
+    #[test]
+    fn evaluate_negation() {
+        assert_eq!(evaluate_source("-(2 + 3)").unwrap(), Value::Number(-5.0));
+        assert_eq!(evaluate_source("--4").unwrap(), Value::Number(4.0));
+    }
+
+    #[test]
+    fn statement_only_program_evaluates_to_unit() {
+        let value = evaluate_source("def x = 1; print x").unwrap();
+        assert_eq!(value, Value::Unit);
+    }
+
+    #[test]
+    fn evaluate_percentages() {
+        struct Test {
+            source: &'static str,
+            expected: Value,
+        }
+        let tests = vec![
+            Test {
+                source: "5%",
+                expected: Value::Number(0.05),
+            },
+            Test {
+                source: "200 * 5%",
+                expected: Value::Number(10.0),
+            },
+            Test {
+                source: "50% + 1",
+                expected: Value::Number(1.5),
+            },
+            Test {
+                source: "7 % 3",
+                expected: Value::Number(1.0),
+            },
+            Test {
+                source: "-7 % 3",
+                expected: Value::Number(-1.0),
+            },
+            Test {
+                source: "10 % (2 + 2)",
+                expected: Value::Number(2.0),
+            },
+        ];
+        for test in tests {
+            let value = evaluate_source(test.source).unwrap();
+            assert_eq!(value, test.expected, "{}", test.source);
+        }
+        let err = evaluate_source("1 % 0").unwrap_err();
+        assert_eq!(format!("{}", err), "Modulo by zero");
+    }
+
+    #[test]
+    fn restore_discards_later_definitions() {
+        let mut env = Environment::new();
+        env.define("x", Value::Number(1.0));
+        let snapshot = env.snapshot();
+        env.define("x", Value::Number(2.0));
+        env.define("y", Value::Number(3.0));
+        env.restore(snapshot);
+        assert_eq!(env.get("x"), Some(&Value::Number(1.0)));
+        assert_eq!(env.get("y"), None);
+    }
+
+    #[test]
+    fn snapshots_are_independent_of_later_mutation() {
+        let mut env = Environment::new();
+        env.define("x", Value::Number(1.0));
+        let first = env.snapshot();
+        env.define("x", Value::Number(2.0));
+        let second = env.snapshot();
+        env.define("x", Value::Number(3.0));
+        env.define("y", Value::Number(4.0));
+
+        env.restore(second.clone());
+        assert_eq!(env.get("x"), Some(&Value::Number(2.0)));
+        assert_eq!(env.get("y"), None);
+
+        env.define("x", Value::Number(5.0));
+        env.restore(first);
+        assert_eq!(env.get("x"), Some(&Value::Number(1.0)));
+
+        env.restore(second);
+        assert_eq!(env.get("x"), Some(&Value::Number(2.0)));
+    }
+
+    #[cfg(feature = "complex")]
+    #[test]
+    fn evaluate_complex_arithmetic() {
+        struct Test {
+            source: &'static str,
+            expected: &'static str,
+        }
+        let tests = [
+            Test {
+                source: "(1 + 2i) + (3 - 1i)",
+                expected: "4+1i",
             },
             Test {
-                expr: Expr::Binary {
-                    left: Box::new(Expr::NumericLiteral {
-                        value: "8753.0".into(),
-                    }),
-                    right: Box::new(Expr::NumericLiteral {
-                        value: "0.0".into(),
-                    }),
-                    operator: Token::Plus,
-                },
-                expected: Value::Number(8753.0),
+                source: "(1 + 2i) * (3 - 1i)",
+                expected: "5+5i",
+            },
+            Test {
+                source: "2i * 2i",
+                expected: "-4+0i",
+            },
+            Test {
+                source: "(5 + 5i) / (3 - 1i)",
+                expected: "1+2i",
+            },
+            Test {
+                source: "-(1 + 2i)",
+                expected: "-1-2i",
             },
         ];
         for test in tests {
-            let value = evaluate(&test.expr).unwrap();
-            assert_eq!(value, test.expected);
+            let value = evaluate_source(test.source).unwrap();
+            assert_eq!(value.to_string(), test.expected, "{}", test.source);
         }
+        assert_eq!(
+            evaluate_source("(1 + 2i) / 0").unwrap_err().to_string(),
+            "Division by zero"
+        );
+        assert_eq!(evaluate_source("(1 + 0i) == 1").unwrap(), Value::Bool(true));
     }
 
     #[test]
-    fn evaluate_subtraction() {
+    fn evaluate_numeric_builtins() {
+        assert_eq!(evaluate_source("sqrt(16)").unwrap(), Value::Number(4.0));
+        assert_eq!(evaluate_source("abs(-2.5)").unwrap(), Value::Number(2.5));
+        assert_eq!(
+            evaluate_source("sqrt(1, 2)").unwrap_err().to_string(),
+            "sqrt expects 1 argument, got 2"
+        );
+    }
+
+    #[test]
+    fn numeric_builtins_name_the_offending_type() {
         struct Test {
-            expr: Expr,
-            expected: Value,
+            source: &'static str,
+            expected: &'static str,
         }
-        let tests = vec![
+        let tests = [
             Test {
-                expr: Expr::Binary {
-                    left: Box::new(Expr::NumericLiteral {
-                        value: "123.345".into(),
-                    }),
-                    right: Box::new(Expr::NumericLiteral {
-                        value: "1.0".into(),
-                    }),
-                    operator: Token::Minus,
-                },
-                expected: Value::Number(122.345),
+                source: "sqrt(\"x\")",
+                expected: "sqrt expects a number, got String",
             },
             Test {
-                expr: Expr::Binary {
-                    left: Box::new(Expr::NumericLiteral {
-                        value: "8753.0".into(),
-                    }),
-                    right: Box::new(Expr::NumericLiteral {
-                        value: "0.0".into(),
-                    }),
-                    operator: Token::Minus,
-                },
-                expected: Value::Number(8753.0),
+                source: "abs(true)",
+                expected: "abs expects a number, got Bool",
+            },
+            Test {
+                source: "sqrt(try(1 / 0))",
+                expected: "sqrt expects a number, got Nil",
             },
         ];
         for test in tests {
-            let value = evaluate(&test.expr).unwrap();
-            assert_eq!(value, test.expected);
+            let err = evaluate_source(test.source).unwrap_err();
+            assert!(err.is::<RuntimeError>());
+            assert_eq!(err.to_string(), test.expected, "{}", test.source);
         }
     }
 
     #[test]
-    fn evaluate_multiplication() {
+    fn evaluate_hex_and_scientific_literals() {
+        assert_eq!(
+            evaluate_source("0xFF + 1e3").unwrap(),
+            Value::Number(1255.0)
+        );
+        assert_eq!(evaluate_source("2.5E-2").unwrap(), Value::Number(0.025));
+        assert_eq!(
+            evaluate_source("0x").unwrap_err().to_string(),
+            "Invalid number: 0x"
+        );
+    }
+
+    #[test]
+    fn trace_reduction_steps() {
         struct Test {
-            expr: Expr,
-            expected: Value,
+            source: &'static str,
+            expected: Vec<&'static str>,
         }
         let tests = vec![
             Test {
-                expr: Expr::Binary {
-                    left: Box::new(Expr::NumericLiteral {
-                        value: "123.345".into(),
-                    }),
-                    right: Box::new(Expr::NumericLiteral {
-                        value: "1.0".into(),
-                    }),
-                    operator: Token::Astrix,
-                },
-                expected: Value::Number(123.345),
+                source: "2 + 3 * 4",
+                expected: vec!["2 + 3 * 4", "2 + 12", "14"],
             },
             Test {
-                expr: Expr::Binary {
-                    left: Box::new(Expr::NumericLiteral {
-                        value: "8753.0".into(),
-                    }),
-                    right: Box::new(Expr::NumericLiteral {
-                        value: "0.0".into(),
-                    }),
-                    operator: Token::Astrix,
-                },
-                expected: Value::Number(0.0),
+                source: "(1 + 2) * -(3 - 1)",
+                expected: vec!["(1 + 2) * -(3 - 1)", "3 * -(3 - 1)", "3 * -2", "-6"],
+            },
+            Test {
+                source: "def x = 5; x > 2 && false",
+                expected: vec!["x > 2 && false", "5 > 2 && false", "true && false", "false"],
+            },
+            Test {
+                source: "try(1 / 0) == 1",
+                expected: vec!["try(1 / 0) == 1", "false"],
+            },
+            Test {
+                source: "def x = 1;",
+                expected: vec![],
             },
         ];
         for test in tests {
-            let value = evaluate(&test.expr).unwrap();
-            assert_eq!(value, test.expected);
+            let tokens = tokenize_with_spans(test.source).unwrap();
+            let program = parse(&tokens).unwrap();
+            let steps = evaluate_traced(
+                &program,
+                &mut Environment::new(),
+                EvalOptions::default(),
+                &mut io::sink(),
+            )
+            .unwrap();
+            assert_eq!(steps, test.expected, "{}", test.source);
         }
     }
 
+    fn evaluate_with_checks(
+        source: &str,
+        finite_checks: FiniteChecks,
+    ) -> Result<Value, Box<dyn Error>> {
+        let tokens = tokenize_with_spans(source)?;
+        let program = parse(&tokens)?;
+        let options = EvalOptions {
+            finite_checks,
+            ..EvalOptions::default()
+        };
+        evaluate_with(&program, &mut Environment::new(), options, &mut io::sink())
+    }
+
     #[test]
-    fn evaluate_division() {
+    fn finite_checks_are_on_by_default() {
+        let checks = FiniteChecks::default();
+        assert!(checks.div_by_zero && checks.overflow);
+        assert_eq!(
+            evaluate_source("1e308 * 10").unwrap_err().to_string(),
+            "Numeric overflow"
+        );
+    }
+
+    #[test]
+    fn division_by_zero_check_can_be_disabled() {
+        let checks = FiniteChecks {
+            div_by_zero: false,
+            overflow: true,
+        };
+        assert_eq!(
+            evaluate_with_checks("1 / 0", checks).unwrap(),
+            Value::Number(f64::INFINITY)
+        );
+        assert_eq!(
+            evaluate_with_checks("-1 // 0", checks).unwrap(),
+            Value::Number(f64::NEG_INFINITY)
+        );
+        assert!(matches!(
+            evaluate_with_checks("1 % 0", checks).unwrap(),
+            Value::Number(n) if n.is_nan()
+        ));
+        assert_eq!(
+            evaluate_with_checks("1e308 + 1e308", checks)
+                .unwrap_err()
+                .to_string(),
+            "Numeric overflow"
+        );
+    }
+
+    #[test]
+    fn overflow_check_can_be_disabled() {
+        let checks = FiniteChecks {
+            div_by_zero: true,
+            overflow: false,
+        };
+        assert_eq!(
+            evaluate_with_checks("1e308 * 10", checks).unwrap(),
+            Value::Number(f64::INFINITY)
+        );
+        assert_eq!(
+            evaluate_with_checks("-1e308 - 1e308", checks).unwrap(),
+            Value::Number(f64::NEG_INFINITY)
+        );
+        assert_eq!(
+            evaluate_with_checks("1 / 0", checks)
+                .unwrap_err()
+                .to_string(),
+            "Division by zero"
+        );
+    }
+
+    #[test]
+    fn convert_host_values() {
+        let number: Value = 3.0.into();
+        assert_eq!(number, Value::Number(3.0));
+        let boolean: Value = true.into();
+        assert_eq!(boolean, Value::Bool(true));
+        assert_eq!(f64::try_from(number).unwrap(), 3.0);
+        assert!(bool::try_from(boolean).unwrap());
+    }
+
+    #[test]
+    fn failed_conversions_name_both_types() {
+        let err = f64::try_from(Value::Bool(true)).unwrap_err();
+        assert_eq!(err.to_string(), "Expected a Number, got Bool");
+        let err = bool::try_from(Value::Nil).unwrap_err();
+        assert_eq!(err.to_string(), "Expected a Bool, got Nil");
+    }
+
+    #[test]
+    fn call_functions() {
         struct Test {
-            expr: Expr,
+            source: &'static str,
             expected: Value,
         }
         let tests = vec![
             Test {
-                expr: Expr::Binary {
-                    left: Box::new(Expr::NumericLiteral {
-                        value: "123.345".into(),
-                    }),
-                    right: Box::new(Expr::NumericLiteral {
-                        value: "1.0".into(),
-                    }),
-                    operator: Token::Slash,
-                },
-                expected: Value::Number(123.345),
+                source: "def f a b = a + b; f(1, 2)",
+                expected: Value::Number(3.0),
             },
             Test {
-                expr: Expr::Binary {
-                    left: Box::new(Expr::NumericLiteral {
-                        value: "8753.0".into(),
-                    }),
-                    right: Box::new(Expr::NumericLiteral {
-                        value: "2.2".into(),
-                    }),
-                    operator: Token::Slash,
-                },
-                expected: Value::Number(3978.63636363636364),
+                source: "def f = fn a, b { a * b }; f(3, 4)",
+                expected: Value::Number(12.0),
+            },
+            Test {
+                source: "fn { 7 }()",
+                expected: Value::Number(7.0),
+            },
+            Test {
+                source: "def x = 10; def add_x n = n + x; def x = 1; add_x(x)",
+                expected: Value::Number(11.0),
+            },
+            Test {
+                source: "def twice f x = f(f(x)); def inc n = n + 1; twice(inc, 5)",
+                expected: Value::Number(7.0),
+            },
+            Test {
+                source: "def sqrt x = x; sqrt(4 - 5)",
+                expected: Value::Number(-1.0),
             },
         ];
         for test in tests {
-            let value = evaluate(&test.expr).unwrap();
-            assert_eq!(value, test.expected);
+            let value = evaluate_source(test.source).unwrap();
+            assert_eq!(value, test.expected, "{}", test.source);
         }
     }
 
     #[test]
-    fn evaluate_precedence() {
+    fn function_errors() {
         struct Test {
-            expr: Expr,
-            expected: Value,
+            source: &'static str,
+            expected: &'static str,
         }
-        let tests = vec![
+        let tests = [
             Test {
-                expr: Expr::Binary {
-                    left: Box::new(Expr::Binary {
-                        left: Box::new(Expr::NumericLiteral {
-                            value: "123.345".into(),
-                        }),
-                        right: Box::new(Expr::Binary {
-                            left: Box::new(Expr::NumericLiteral {
-                                value: "1.0".into(),
-                            }),
-                            right: Box::new(Expr::NumericLiteral {
-                                value: "1.345".into(),
-                            }),
-                            operator: Token::Slash,
-                        }),
-                        operator: Token::Plus,
-                    }),
-                    right: Box::new(Expr::NumericLiteral {
-                        value: "10.0".into(),
-                    }),
-                    operator: Token::Minus,
-                },
-                expected: Value::Number(114.0884944237918),
+                source: "def add a b = a + b; add(1)",
+                expected: "Function 'add' expected 2 arguments, got 1",
             },
             Test {
-                expr: Expr::Binary {
-                    left: Box::new(Expr::Binary {
-                        left: Box::new(Expr::NumericLiteral {
-                            value: "123.345".into(),
-                        }),
-                        right: Box::new(Expr::Binary {
-                            left: Box::new(Expr::NumericLiteral {
-                                value: "1.0".into(),
-                            }),
-                            right: Box::new(Expr::NumericLiteral {
-                                value: "1.345".into(),
-                            }),
-                            operator: Token::Minus,
-                        }),
-                        operator: Token::Plus,
-                    }),
-                    right: Box::new(Expr::NumericLiteral {
-                        value: "10.0".into(),
-                    }),
-                    operator: Token::Astrix,
-                },
-                expected: Value::Number(1230.0),
+                source: "def add a b = a + b; add(1, 2, 3)",
+                expected: "Function 'add' expected 2 arguments, got 3",
+            },
+            Test {
+                source: "fn a { a }()",
+                expected: "Function '<anonymous>' expected 1 argument, got 0",
+            },
+            Test {
+                source: "def apply f = f(1, 2); apply(fn { 0 })",
+                expected: "Function '<anonymous>' expected 0 arguments, got 2",
+            },
+            Test {
+                source: "def f = 1; f(1)",
+                expected: "Can only call functions",
+            },
+            Test {
+                source: "def f a = b; f(1)",
+                expected: "Undefined variable: b",
             },
         ];
         for test in tests {
-            let value = evaluate(&test.expr).unwrap();
-            assert_eq!(value, test.expected);
+            let err = evaluate_source(test.source).unwrap_err();
+            assert_eq!(err.to_string(), test.expected, "{}", test.source);
+        }
+    }
+
+    #[test]
+    fn functions_display_their_name() {
+        let value = evaluate_source("def f x = x; f").unwrap();
+        assert_eq!(value.type_name(), "Function");
+        assert_eq!(value.to_string(), "<fn f>");
+        assert_eq!(evaluate_source("fn { 1 }").unwrap().to_string(), "<fn>");
+    }
+
+    #[test]
+    fn large_integer_literals_promote_to_float_by_default() {
+        assert_eq!(
+            evaluate_source("99999999999999999999").unwrap(),
+            Value::Number(1e20)
+        );
+        assert_eq!(
+            evaluate_source("0x10000000000000000").unwrap(),
+            Value::Number(18446744073709551616.0)
+        );
+    }
+
+    #[test]
+    fn checked_integer_arithmetic() {
+        let evaluate = |source: &str, checked: bool| {
+            let tokens = tokenize_with_spans(source).unwrap();
+            let program = parse(&tokens).unwrap();
+            let int_overflow = if checked {
+                IntOverflow::Checked
+            } else {
+                IntOverflow::Rounding
+            };
+            let options = EvalOptions {
+                int_overflow,
+                ..EvalOptions::default()
+            };
+            evaluate_with(&program, &mut Environment::new(), options, &mut io::sink())
+        };
+        for source in [
+            "9223372036854775807 + 1",
+            "-9223372036854775807 - 2",
+            "3037000500 * 3037000500",
+            "2 ^ 63",
+            "-(-9223372036854775807 - 1)",
+            "(-9223372036854775807 - 1) // -1",
+        ] {
+            assert_eq!(
+                evaluate(source, true).unwrap_err().to_string(),
+                "Integer overflow",
+                "{}",
+                source
+            );
+            assert!(evaluate(source, false).is_ok(), "{}", source);
+        }
+        // Integers stay exact past 2^53, up to the ends of the i64 range.
+        assert_eq!(
+            evaluate("9007199254740992 + 1", true).unwrap().to_string(),
+            "9007199254740993"
+        );
+        assert_eq!(
+            evaluate("9223372036854775806 + 1", true).unwrap(),
+            Value::Int(i64::MAX)
+        );
+        assert_eq!(
+            evaluate("3037000499 * 3037000499", true).unwrap(),
+            Value::Int(9223372030926249001)
+        );
+    }
+
+    #[test]
+    fn integers_stay_exact() {
+        assert!(matches!(evaluate_source("2 + 3").unwrap(), Value::Int(5)));
+        assert!(matches!(
+            evaluate_source("0x10 * 2").unwrap(),
+            Value::Int(32)
+        ));
+        assert!(matches!(
+            evaluate_source("2.0 + 3").unwrap(),
+            Value::Number(_)
+        ));
+        assert_eq!(
+            evaluate_source("9007199254740993").unwrap().to_string(),
+            "9007199254740993"
+        );
+        for (source, expected) in [
+            ("7 // 2", 3),
+            ("7 // -2", -4),
+            ("-7 // 2", -4),
+            ("-8 // 2", -4),
+            ("7 % (-2)", 1),
+            ("-7 % 2", -1),
+            ("-(3 ^ 3)", -27),
+        ] {
+            assert_eq!(
+                evaluate_source(source).unwrap(),
+                Value::Int(expected),
+                "{}",
+                source
+            );
+        }
+        assert_eq!(evaluate_source("6 / 4").unwrap(), Value::Number(1.5));
+        assert_eq!(
+            evaluate_source("9007199254740993 > 9007199254740992").unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            evaluate_source("7 // 0").unwrap_err().to_string(),
+            "Division by zero"
+        );
+    }
+
+    #[test]
+    fn float_operands_never_overflow_as_integers() {
+        for (source, expected) in [
+            ("1e300 * 10", 1e301),
+            ("1e20 + 1", 1e20),
+            ("2.0 ^ 64", 18446744073709551616.0),
+            ("0.5 * 9007199254740991", 4503599627370495.5),
+            ("9223372036854775807 + 1.0", 9223372036854775808.0),
+        ] {
+            assert_eq!(
+                evaluate_source(source).unwrap(),
+                Value::Number(expected),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn large_integer_literals_can_be_rejected() {
+        let evaluate = |source: &str| {
+            let tokens = tokenize_with_spans(source).unwrap();
+            let program = parse(&tokens).unwrap();
+            let options = EvalOptions {
+                reject_large_integers: true,
+                ..EvalOptions::default()
+            };
+            evaluate_with(&program, &mut Environment::new(), options, &mut io::sink())
+        };
+        assert_eq!(
+            evaluate("99999999999999999999").unwrap_err().to_string(),
+            "Integer literal too large: 99999999999999999999"
+        );
+        assert_eq!(
+            evaluate("1 + 9223372036854775808").unwrap_err().to_string(),
+            "Integer literal too large: 9223372036854775808"
+        );
+        assert_eq!(
+            evaluate("9223372036854775807").unwrap(),
+            Value::Number(i64::MAX as f64)
+        );
+        assert_eq!(evaluate("1e20").unwrap(), Value::Number(1e20));
+        assert_eq!(evaluate("0xFF").unwrap(), Value::Number(255.0));
+    }
+
+    #[test]
+    fn negative_zero_equals_and_displays_as_zero() {
+        assert_eq!(Value::Number(-0.0), Value::Number(0.0));
+        assert_eq!(evaluate_source("-0 == 0").unwrap(), Value::Bool(true));
+        assert_eq!(evaluate_source("0 * -1 < 0").unwrap(), Value::Bool(false));
+        assert_eq!(evaluate_source("-0").unwrap().to_string(), "0");
+        assert_eq!(evaluate_source("0 * -1").unwrap().to_string(), "0");
+        assert_eq!(
+            evaluate_source("1 / -0").unwrap_err().to_string(),
+            "Division by zero"
+        );
+    }
+
+    #[test]
+    fn while_loops() {
+        assert_eq!(
+            evaluate_source("while false { 1 / 0 }").unwrap(),
+            Value::Unit
+        );
+        assert_eq!(
+            evaluate_source("while 1 { 2 }").unwrap_err().to_string(),
+            "Expected a boolean condition for while, got 1"
+        );
+    }
+
+    #[test]
+    fn cond_takes_the_first_true_arm() {
+        let sign = "def sign x = cond { x < 0 => -1, x > 0 => 1, else => 0 };";
+        for (x, expected) in [(-5.0, -1.0), (3.0, 1.0), (0.0, 0.0)] {
+            assert_eq!(
+                evaluate_source(&format!("{} sign({})", sign, x)).unwrap(),
+                Value::Number(expected)
+            );
+        }
+        // Later guards are not evaluated once one is true.
+        assert_eq!(
+            evaluate_source("cond { true => 1, 1 / 0 == 0 => 2 }").unwrap(),
+            Value::Number(1.0)
+        );
+        assert_eq!(
+            evaluate_source("cond { false => 1 }")
+                .unwrap_err()
+                .to_string(),
+            "No cond arm matched"
+        );
+        assert_eq!(
+            evaluate_source("cond { 1 => 2, else => 3 }")
+                .unwrap_err()
+                .to_string(),
+            "Expected a boolean guard for cond, got 1"
+        );
+        assert_eq!(
+            evaluate_source("if false { 1 } else if 2 { 2 } else { 3 }")
+                .unwrap_err()
+                .to_string(),
+            "Expected a boolean condition for if, got 2"
+        );
+    }
+
+    #[test]
+    fn records_hold_fields_by_name() {
+        assert_eq!(
+            evaluate_source("def p = { x: 1, y: 2 }; p.x + p.y").unwrap(),
+            Value::Number(3.0)
+        );
+        assert_eq!(
+            evaluate_source("{ y: \"b\", x: [1] }").unwrap().to_string(),
+            "{x: [1], y: b}"
+        );
+        assert_eq!(
+            evaluate_source("{ inner: { f: fn n { n * 2 } } }.inner.f(4)").unwrap(),
+            Value::Number(8.0)
+        );
+        assert_eq!(
+            evaluate_source("{ a: 1, b: 2 } == { b: 2, a: 1 }").unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            evaluate_source("def p = { x: 1 }; p.z")
+                .unwrap_err()
+                .to_string(),
+            "No such field: z"
+        );
+        assert_eq!(
+            evaluate_source("[1].x").unwrap_err().to_string(),
+            "Expected a record before .x, got Array"
+        );
+        assert_eq!(
+            evaluate_source("{ y: 2, x: \"a\" }")
+                .unwrap()
+                .to_source_literal(),
+            Some("{x: \"a\", y: 2}".into())
+        );
+    }
+
+    #[test]
+    fn ranges_are_half_open() {
+        assert_eq!(
+            evaluate_source("def n = 4; 0..n + 1").unwrap(),
+            Value::Range { start: 0, end: 5 }
+        );
+        assert_eq!(evaluate_source("-2..2").unwrap().to_string(), "-2..2");
+        assert_eq!(evaluate_source("1..5 == 1..5").unwrap(), Value::Bool(true));
+        assert_eq!(evaluate_source("1..5 != 1..4").unwrap(), Value::Bool(true));
+        assert_eq!(
+            evaluate_source("1.5..3").unwrap_err().to_string(),
+            "Range bounds must be integers, got 1.5"
+        );
+        assert_eq!(
+            evaluate_source("1..\"a\"").unwrap_err().to_string(),
+            "Range bounds must be integers, got a"
+        );
+    }
+
+    #[test]
+    fn let_binds_only_within_its_body() {
+        assert_eq!(
+            evaluate_source("let x = 2 in x * x").unwrap(),
+            Value::Number(4.0)
+        );
+        assert_eq!(
+            evaluate_source("def x = 1; let x = x + 1 in x").unwrap(),
+            Value::Number(2.0)
+        );
+        assert_eq!(
+            evaluate_source("def x = 1; (let x = 5 in x) + x").unwrap(),
+            Value::Number(6.0)
+        );
+        assert_eq!(
+            evaluate_source("(let y = 2 in y); y")
+                .unwrap_err()
+                .to_string(),
+            "Undefined variable: y"
+        );
+        assert_eq!(
+            evaluate_source("let f = fn n { n * 2 } in f(f(1))").unwrap(),
+            Value::Number(4.0)
+        );
+    }
+
+    #[test]
+    fn discard_binds_nothing() {
+        assert_eq!(
+            evaluate_source("def f _ b = b; f(1, 2)").unwrap(),
+            Value::Number(2.0)
+        );
+        assert_eq!(
+            evaluate_source("fn _, _ { 3 }(1, 2)").unwrap(),
+            Value::Number(3.0)
+        );
+        assert_eq!(
+            evaluate_source("def _ = 1; let _ = 2 in 4").unwrap(),
+            Value::Number(4.0)
+        );
+        assert_eq!(
+            evaluate_source("def _ = 1 / 0;").unwrap_err().to_string(),
+            "Division by zero"
+        );
+        let mut env = Environment::new();
+        env.define("_", Value::Number(1.0));
+        assert_eq!(env.get("_"), None);
+    }
+
+    #[test]
+    fn infinite_loop_stops_at_max_iterations() {
+        let err = evaluate_source("while true { 1 }").unwrap_err();
+        assert!(!err.is::<RuntimeError>());
+        assert_eq!(err.to_string(), "Maximum loop iterations exceeded");
+    }
+
+    #[test]
+    fn evaluate_arrays() {
+        let value = evaluate_source("def x = 2; [1, x * 2, [true]]").unwrap();
+        assert_eq!(value.type_name(), "Array");
+        assert_eq!(value.to_string(), "[1, 4, [true]]");
+        assert_eq!(
+            evaluate_source("[1, 2] == [1, 2]").unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            evaluate_source("[1] == [1, 2]").unwrap(),
+            Value::Bool(false)
+        );
+        assert_eq!(
+            evaluate_source("[1000000]")
+                .unwrap()
+                .display(DisplayOptions {
+                    group_digits: true,
+                    ..DisplayOptions::default()
+                })
+                .to_string(),
+            "[1,000,000]"
+        );
+    }
+
+    #[test]
+    fn interpreter_keeps_state_between_evaluations() {
+        let parse_source = |source: &str| parse(&tokenize_with_spans(source).unwrap()).unwrap();
+        let mut interpreter = Interpreter::with_output(Vec::new(), EvalOptions::default());
+        let value = interpreter
+            .eval_program(&parse_source("def x = 2; print x;"))
+            .unwrap();
+        assert_eq!(value, Value::Unit);
+        let square = parse_source("x * x").expr.unwrap();
+        assert_eq!(interpreter.eval(&square).unwrap(), Value::Number(4.0));
+        interpreter
+            .eval_program(&parse_source("def x = 3; 1 / 0"))
+            .unwrap_err();
+        assert_eq!(interpreter.eval(&square).unwrap(), Value::Number(9.0));
+        assert_eq!(interpreter.env().get("x"), Some(&Value::Number(3.0)));
+        assert_eq!(interpreter.output(), b"2\n");
+    }
+
+    #[test]
+    fn interpreter_holds_host_hooks() {
+        let parse_source = |source: &str| parse(&tokenize_with_spans(source).unwrap()).unwrap();
+        let mut interpreter = Interpreter::with_output(io::sink(), EvalOptions::default());
+        interpreter.define("base", Value::Number(10.0));
+        interpreter.set_env_lookup(|name| (name == "HOME").then(|| "/home/sal".to_string()));
+        let value = interpreter
+            .eval_program(&parse_source("[base, env(\"HOME\")]"))
+            .unwrap();
+        assert_eq!(value.to_string(), "[10, /home/sal]");
+    }
+
+    #[test]
+    fn print_writes_to_the_given_output() {
+        let tokens = tokenize_with_spans("print 1 + 1; print \"hi\"; 3").unwrap();
+        let program = parse(&tokens).unwrap();
+        let mut out = Vec::new();
+        let value = evaluate_with(
+            &program,
+            &mut Environment::new(),
+            EvalOptions::default(),
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(value, Value::Number(3.0));
+        assert_eq!(String::from_utf8(out).unwrap(), "2\nhi\n");
+    }
+
+    #[test]
+    fn display_hex() {
+        let options = DisplayOptions {
+            hex: true,
+            scientific: true,
+            ..DisplayOptions::default()
+        };
+        for (value, expected) in [
+            (256.0, "0x100"),
+            (-255.0, "-0xFF"),
+            (0.0, "0"),
+            (2.5, "2.5"),
+            (1e30, "1e30"),
+        ] {
+            assert_eq!(
+                Value::Number(value).display(options).to_string(),
+                expected,
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn display_scientific() {
+        let options = DisplayOptions {
+            scientific: true,
+            group_digits: true,
+            ..DisplayOptions::default()
+        };
+        for (value, expected) in [
+            (1230000.0, "1.23e6"),
+            (-1230000.0, "-1.23e6"),
+            (0.00012, "1.2e-4"),
+            (999999.0, "999,999"),
+            (0.5, "0.5"),
+            (0.0, "0"),
+            (f64::INFINITY, "inf"),
+        ] {
+            assert_eq!(Value::Number(value).display(options).to_string(), expected);
         }
+        assert_eq!(Value::Number(1230000.0).to_string(), "1230000");
     }
 }
@@ -0,0 +1,8 @@
+pub mod ast;
+mod builtins;
+pub mod engine;
+pub mod formatter;
+pub mod history;
+pub mod interpreter;
+pub mod runner;
+pub mod scanner;
@@ -1,10 +1,6 @@
-use crate::runner::run;
+use sal::runner::run;
+use std::process::ExitCode;
 
-pub mod ast;
-pub mod interpreter;
-pub mod runner;
-pub mod scanner;
-
-fn main() {
+fn main() -> ExitCode {
     run()
 }
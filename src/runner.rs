@@ -1,41 +1,819 @@
-use crate::ast::parse;
-use crate::interpreter::{evaluate, Value};
-use crate::scanner::tokenize;
+use crate::ast::{grammar, parse, ParseError, Stmt};
+use crate::engine::Engine;
+use crate::history::{history_path, History, HISTORY_LIMIT};
+use crate::interpreter::{
+    evaluate_program, DisplayOptions, EvalOptions, Interpreter, Profile, Value, DEFAULT_MAX_DEPTH,
+};
+use crate::scanner::{tokenize_with_spans, LexError};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::env;
 use std::error::Error;
+use std::ffi::OsStr;
+use std::fs;
 use std::io;
-use std::io::Write;
-
-pub fn run() {
-    loop {
-        print!("> ");
-        io::stdout().flush().unwrap();
-        let mut line = String::default();
-        let res = io::stdin().read_line(&mut line);
-        match res {
-            Ok(_) => match line.as_str().trim() {
-                "quit" => {
-                    break;
+use std::io::{BufRead, IsTerminal, Write};
+use std::mem;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+const USAGE: &str =
+    "usage: sal [--no-color] [--time] [--profile] [--max-depth N] [--interactive | --batch | --stream | --grammar | --tokens FILE | --ast FILE | --ast-json FILE | FILE...]";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Mode {
+    Repl,
+    Grammar,
+    DumpTokens(String),
+    DumpAst(String),
+    #[cfg(feature = "json")]
+    DumpAstJson(String),
+    Run(Vec<String>),
+}
+
+/// How standard input is read when no files are given. `Auto` runs the REPL
+/// on a terminal and evaluates piped input as a single program. `Stream`
+/// evaluates each line on its own, without prompts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StdinMode {
+    Auto,
+    Interactive,
+    Batch,
+    Stream,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Args {
+    pub mode: Mode,
+    pub no_color: bool,
+    pub stdin_mode: StdinMode,
+    /// Report how long evaluation took once the program has run.
+    pub time: bool,
+    /// Report how many expressions of each kind were evaluated once the
+    /// program has run.
+    pub profile: bool,
+    /// How many function calls may be nested before evaluation is stopped.
+    pub max_depth: usize,
+}
+
+const FLAGS: &[&str] = &[
+    "--no-color",
+    "--interactive",
+    "-i",
+    "--batch",
+    "--stream",
+    "--time",
+    "--profile",
+];
+
+pub fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut max_depth = None;
+    let mut remaining = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--max-depth" {
+            let value = iter.next().map(String::as_str).unwrap_or_default();
+            max_depth = match value.parse::<usize>() {
+                Ok(depth) if depth > 0 => Some(depth),
+                _ => {
+                    return Err(format!(
+                        "--max-depth expects a positive integer, got {:?}",
+                        value
+                    ))
                 }
-                _ => match evaluate_line(&line) {
-                    Ok(value) => {
-                        println!("{:?}", value);
-                    }
-                    Err(err) => {
-                        println!("{}", err);
+            };
+        } else {
+            remaining.push(arg.clone());
+        }
+    }
+    let args = remaining.as_slice();
+    let has = |flag: &str| args.iter().any(|arg| arg == flag);
+    let no_color = has("--no-color");
+    let time = has("--time");
+    let profile = has("--profile");
+    let stdin_modes: Vec<(&str, StdinMode)> = [
+        (
+            "--interactive",
+            has("--interactive") || has("-i"),
+            StdinMode::Interactive,
+        ),
+        ("--batch", has("--batch"), StdinMode::Batch),
+        ("--stream", has("--stream"), StdinMode::Stream),
+    ]
+    .into_iter()
+    .filter(|(_, given, _)| *given)
+    .map(|(flag, _, stdin_mode)| (flag, stdin_mode))
+    .collect();
+    let stdin_mode = match stdin_modes.as_slice() {
+        [] => StdinMode::Auto,
+        [(_, stdin_mode)] => *stdin_mode,
+        _ => {
+            let flags: Vec<&str> = stdin_modes.iter().map(|(flag, _)| *flag).collect();
+            return Err(format!("{} cannot be used together", flags.join(" and ")));
+        }
+    };
+    let rest: Vec<&String> = args
+        .iter()
+        .filter(|arg| !FLAGS.contains(&arg.as_str()))
+        .collect();
+    let mode = match rest.as_slice() {
+        [] => Mode::Repl,
+        [flag] if *flag == "--grammar" => Mode::Grammar,
+        [flag, path] if *flag == "--tokens" => Mode::DumpTokens(path.to_string()),
+        [flag, path] if *flag == "--ast" => Mode::DumpAst(path.to_string()),
+        #[cfg(feature = "json")]
+        [flag, path] if *flag == "--ast-json" => Mode::DumpAstJson(path.to_string()),
+        paths if paths.iter().all(|path| !path.starts_with("--")) => {
+            Mode::Run(paths.iter().map(|path| path.to_string()).collect())
+        }
+        _ => return Err(USAGE.into()),
+    };
+    if stdin_mode != StdinMode::Auto && mode != Mode::Repl {
+        return Err(USAGE.into());
+    }
+    // The REPL and `--stream` print each result as it comes, so there is no
+    // single evaluation to time or profile.
+    if (time || profile)
+        && (!matches!(mode, Mode::Repl | Mode::Run(_))
+            || matches!(stdin_mode, StdinMode::Interactive | StdinMode::Stream))
+    {
+        return Err(USAGE.into());
+    }
+    if max_depth.is_some() && !matches!(mode, Mode::Repl | Mode::Run(_)) {
+        return Err(USAGE.into());
+    }
+    Ok(Args {
+        mode,
+        no_color,
+        stdin_mode,
+        time,
+        profile,
+        max_depth: max_depth.unwrap_or(DEFAULT_MAX_DEPTH),
+    })
+}
+
+/// Whether standard input should be read line by line with a prompt, rather
+/// than evaluated as one program.
+pub fn reads_interactively(stdin_mode: StdinMode, stdin_is_terminal: bool) -> bool {
+    match stdin_mode {
+        StdinMode::Interactive => true,
+        StdinMode::Batch | StdinMode::Stream => false,
+        StdinMode::Auto => stdin_is_terminal,
+    }
+}
+
+/// Decides whether output should be colorized. Color is only used on a
+/// terminal, and is turned off by `--no-color` or a non-empty `NO_COLOR`
+/// environment variable (see <https://no-color.org>).
+pub fn should_color(no_color_flag: bool, no_color_env: Option<&OsStr>, is_terminal: bool) -> bool {
+    let no_color_env = no_color_env.is_some_and(|value| !value.is_empty());
+    is_terminal && !no_color_flag && !no_color_env
+}
+
+/// What the REPL knows about the terminal it writes to.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReplOptions {
+    /// Print errors in red.
+    pub color: bool,
+    /// Output is a terminal, so control sequences such as `:clear` work.
+    pub is_terminal: bool,
+}
+
+/// The sequence that clears the screen and moves the cursor home, or nothing
+/// when output is not a terminal.
+pub fn clear_screen_sequence(is_terminal: bool) -> &'static str {
+    if is_terminal {
+        "\x1b[2J\x1b[H"
+    } else {
+        ""
+    }
+}
+
+/// Formats a duration with one decimal place in the largest unit that keeps it
+/// at least 1, e.g. `1.2ms`.
+pub fn format_duration(duration: Duration) -> String {
+    let micros = duration.as_secs_f64() * 1e6;
+    if micros < 1e3 {
+        format!("{:.1}µs", micros)
+    } else if micros < 1e6 {
+        format!("{:.1}ms", micros / 1e3)
+    } else {
+        format!("{:.1}s", micros / 1e6)
+    }
+}
+
+/// Formats the counts of a profile one kind per line, most evaluated first,
+/// e.g. `Binary: 3`.
+pub fn render_profile(profile: &Profile) -> String {
+    profile
+        .counts()
+        .iter()
+        .map(|(kind, count)| format!("{}: {}\n", kind, count))
+        .collect()
+}
+
+/// Describes `err`, which came from evaluating `source`. Syntax errors are
+/// rendered with the line they were found on and their span underlined.
+pub fn describe_error(err: &(dyn Error + 'static), source: &str) -> String {
+    if let Some(err) = err.downcast_ref::<LexError>() {
+        err.render(source)
+    } else if let Some(err) = err.downcast_ref::<ParseError>() {
+        err.render(source)
+    } else {
+        err.to_string()
+    }
+}
+
+/// Formats an error message, in red when `color` is set.
+fn paint_error(message: &dyn std::fmt::Display, color: bool) -> String {
+    if color {
+        format!("\x1b[31m{}\x1b[0m", message)
+    } else {
+        message.to_string()
+    }
+}
+
+pub fn run() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args = match parse_args(&args) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let no_color_env = env::var_os("NO_COLOR");
+    let color = |is_terminal| should_color(args.no_color, no_color_env.as_deref(), is_terminal);
+    let options = EvalOptions {
+        max_depth: args.max_depth,
+        ..EvalOptions::default()
+    };
+    match args.mode {
+        Mode::Repl if args.stdin_mode == StdinMode::Stream => {
+            let stream_ok = stream(
+                &mut io::stdin().lock(),
+                &mut io::stdout(),
+                &mut io::stderr(),
+                color(io::stderr().is_terminal()),
+                options,
+            );
+            if stream_ok {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        // Without `--batch`, `--time` and `--profile` depend on stdin being
+        // piped, which `parse_args` can't know.
+        Mode::Repl
+            if (args.time || args.profile)
+                && reads_interactively(args.stdin_mode, io::stdin().is_terminal()) =>
+        {
+            eprintln!("{}", USAGE);
+            ExitCode::FAILURE
+        }
+        Mode::Repl if reads_interactively(args.stdin_mode, io::stdin().is_terminal()) => {
+            let stdin = io::stdin();
+            let is_terminal = io::stdout().is_terminal();
+            let repl_options = ReplOptions {
+                color: color(is_terminal),
+                is_terminal,
+            };
+            // Only sessions typed at a terminal are worth remembering.
+            let history_path = history_path().filter(|_| stdin.is_terminal());
+            let mut history = history_path
+                .as_deref()
+                .and_then(|path| History::load(path).ok())
+                .unwrap_or_default();
+            let mut session = Repl::new(repl_options);
+            session.set_max_depth(args.max_depth);
+            if let Some(path) = rc_path() {
+                match fs::read_to_string(&path) {
+                    Ok(source) => {
+                        session.load_rc(&source, &path.display().to_string(), &mut io::stderr())
                     }
-                },
-            },
+                    Err(err) => eprintln!("{}: {}", path.display(), err),
+                }
+            }
+            let editor = Some(&history)
+                .filter(|_| stdin.is_terminal())
+                .and_then(LineEditor::new);
+            match editor {
+                Some(mut editor) => session.run(&mut editor, &mut io::stdout(), &mut history),
+                None => session.run(&mut stdin.lock(), &mut io::stdout(), &mut history),
+            }
+            if let Some(path) = history_path {
+                if let Err(err) = history.save(&path) {
+                    eprintln!("{}: {}", path.display(), err);
+                }
+            }
+            ExitCode::SUCCESS
+        }
+        Mode::Repl => run_sources(
+            &["<stdin>".to_string()],
+            |_| io::read_to_string(io::stdin()),
+            color(io::stderr().is_terminal()),
+            args.time,
+            args.profile,
+            options,
+        ),
+        Mode::Grammar => {
+            print!("{}", grammar());
+            ExitCode::SUCCESS
+        }
+        Mode::DumpTokens(path) => {
+            dump_file(&path, render_tokens, color(io::stderr().is_terminal()))
+        }
+        Mode::DumpAst(path) => dump_file(&path, render_ast, color(io::stderr().is_terminal())),
+        #[cfg(feature = "json")]
+        Mode::DumpAstJson(path) => dump_ast_json(&path),
+        Mode::Run(paths) => run_sources(
+            &paths,
+            |path| fs::read_to_string(path),
+            color(io::stderr().is_terminal()),
+            args.time,
+            args.profile,
+            options,
+        ),
+    }
+}
+
+// Sources are evaluated in order against one engine, so later files see the
+// definitions of earlier ones. Only the last source's result is printed. With
+// `time`, the total spent evaluating, not reading, the sources is reported on
+// stderr after the result, and with `profile` so are the counts of each kind
+// of expression evaluated.
+fn run_sources(
+    names: &[String],
+    read: impl Fn(&str) -> io::Result<String>,
+    color: bool,
+    time: bool,
+    profile: bool,
+    options: EvalOptions,
+) -> ExitCode {
+    let mut engine = Engine::with_options(options);
+    if profile {
+        engine.start_profile();
+    }
+    let mut result = Value::Unit;
+    let mut elapsed = Duration::ZERO;
+    for path in names {
+        let evaluated = read(path)
+            .map_err(|err| err.to_string())
+            .and_then(|source| {
+                let started = Instant::now();
+                let value = engine.eval(&source);
+                elapsed += started.elapsed();
+                value.map_err(|err| describe_error(&*err, &source))
+            });
+        match evaluated {
+            Ok(value) => result = value,
             Err(err) => {
-                println!("{}", err);
+                eprintln!("{}", paint_error(&format!("{}: {}", path, err), color));
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    if result != Value::Unit {
+        println!("{}", result);
+    }
+    if time {
+        eprintln!("evaluated in {}", format_duration(elapsed));
+    }
+    if let Some(profile) = engine.take_profile() {
+        eprint!("{}", render_profile(&profile));
+    }
+    ExitCode::SUCCESS
+}
+
+/// Evaluates each line of `input` as its own program against one interpreter,
+/// so definitions carry over to later lines. Results go to `output` and errors
+/// to `errors`, prefixed with their line number and kept to one line each,
+/// and a failing line does not stop the lines after it. Returns whether every
+/// line succeeded.
+pub fn stream(
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+    errors: &mut dyn Write,
+    color: bool,
+    options: EvalOptions,
+) -> bool {
+    let mut interpreter = Interpreter::with_output(output, options);
+    let mut all_ok = true;
+    for (number, line) in input.lines().enumerate() {
+        let evaluated = line.map_err(Box::<dyn Error>::from).and_then(|line| {
+            if line.trim().is_empty() {
+                return Ok(Value::Unit);
+            }
+            let program = parse(&tokenize_with_spans(&line)?)?;
+            interpreter.eval_program(&program)
+        });
+        match evaluated {
+            Ok(Value::Unit) => {}
+            Ok(value) => writeln!(interpreter.output_mut(), "{}", value).unwrap(),
+            Err(err) => {
+                all_ok = false;
+                let message = format!("<stdin>:{}: {}", number + 1, err);
+                writeln!(errors, "{}", paint_error(&message, color)).unwrap();
+            }
+        }
+    }
+    all_ok
+}
+
+fn dump_file(
+    path: &str,
+    render: fn(&str) -> Result<String, Box<dyn Error>>,
+    color: bool,
+) -> ExitCode {
+    let rendered = match fs::read_to_string(path) {
+        Ok(source) => render(&source).map_err(|err| describe_error(&*err, &source)),
+        Err(err) => Err(format!("{}: {}", path, err)),
+    };
+    match rendered {
+        Ok(text) => {
+            print!("{}", text);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{}", paint_error(&err, color));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Errors go to stderr as `{"error": "message"}` so tools can read them too.
+#[cfg(feature = "json")]
+fn dump_ast_json(path: &str) -> ExitCode {
+    let rendered = match fs::read_to_string(path) {
+        Ok(source) => render_ast_json(&source),
+        Err(err) => Err(format!("{}: {}", path, err).into()),
+    };
+    match rendered {
+        Ok(json) => {
+            println!("{}", json);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{}", serde_json::json!({ "error": err.to_string() }));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Renders the token stream of `source`, one token per line with its byte
+/// range.
+pub fn render_tokens(source: &str) -> Result<String, Box<dyn Error>> {
+    let tokens = tokenize_with_spans(source)?;
+    Ok(tokens
+        .iter()
+        .map(|(token, (start, end))| format!("{}..{} {:?}\n", start, end, token))
+        .collect())
+}
+
+/// Renders the parsed program of `source` without evaluating it.
+pub fn render_ast(source: &str) -> Result<String, Box<dyn Error>> {
+    let tokens = tokenize_with_spans(source)?;
+    let program = parse(&tokens)?;
+    Ok(format!("{:#?}\n", program))
+}
+
+/// Renders the parsed program of `source` as JSON, for editors and other
+/// tools. Spans are `[start, end]` byte offsets.
+#[cfg(feature = "json")]
+pub fn render_ast_json(source: &str) -> Result<String, Box<dyn Error>> {
+    let tokens = tokenize_with_spans(source)?;
+    let program = parse(&tokens)?;
+    Ok(serde_json::to_string_pretty(&program)?)
+}
+
+/// How many entries `:history` prints.
+const HISTORY_SHOWN: usize = 20;
+
+/// The file of REPL lines run before the first prompt.
+const RC_FILE: &str = ".salrc";
+
+/// Runs the read-eval-print loop, adding each line entered to `history`.
+pub fn repl(
+    input: &mut dyn LineReader,
+    output: &mut dyn Write,
+    repl_options: ReplOptions,
+    history: &mut History,
+) {
+    Repl::new(repl_options).run(input, output, history);
+}
+
+/// Where the REPL reads its lines from.
+pub trait LineReader {
+    /// Shows `prompt` and reads one line, or `None` at the end of input.
+    fn read_line(&mut self, prompt: &str, output: &mut dyn Write) -> io::Result<Option<String>>;
+}
+
+// Piped input, and terminals the line editor can't drive, are read a line at
+// a time with the prompt written to the REPL's output.
+impl<R: BufRead + ?Sized> LineReader for R {
+    fn read_line(&mut self, prompt: &str, output: &mut dyn Write) -> io::Result<Option<String>> {
+        write!(output, "{}", prompt)?;
+        output.flush()?;
+        let mut line = String::new();
+        match BufRead::read_line(self, &mut line)? {
+            0 => Ok(None),
+            _ => Ok(Some(line)),
+        }
+    }
+}
+
+/// A line editor for the terminal, so earlier lines can be recalled with the
+/// arrow keys.
+pub struct LineEditor {
+    editor: DefaultEditor,
+}
+
+impl LineEditor {
+    /// An editor that starts out able to recall the entries of `history`, or
+    /// `None` when the terminal does not support one.
+    pub fn new(history: &History) -> Option<Self> {
+        let mut editor = DefaultEditor::new().ok()?;
+        for (_, entry) in history.recent(HISTORY_LIMIT) {
+            editor.add_history_entry(entry).ok()?;
+        }
+        Some(Self { editor })
+    }
+}
+
+// The editor writes the prompt to the terminal itself. Ctrl-C abandons the
+// line being typed, and Ctrl-D ends the input.
+impl LineReader for LineEditor {
+    fn read_line(&mut self, prompt: &str, _output: &mut dyn Write) -> io::Result<Option<String>> {
+        match self.editor.readline(prompt) {
+            Ok(line) => {
+                if !line.trim().is_empty() {
+                    self.editor
+                        .add_history_entry(line.as_str())
+                        .map_err(io::Error::other)?;
+                }
+                Ok(Some(line))
+            }
+            Err(ReadlineError::Interrupted) => Ok(Some(String::new())),
+            Err(ReadlineError::Eof) => Ok(None),
+            Err(ReadlineError::Io(err)) => Err(err),
+            Err(err) => Err(io::Error::other(err)),
+        }
+    }
+}
+
+/// `.salrc` in the working directory, or else in the home directory, if
+/// either exists.
+pub fn rc_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME").filter(|home| !home.is_empty());
+    [env::current_dir().ok(), home.map(PathBuf::from)]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join(RC_FILE))
+        .find(|path| path.is_file())
+}
+
+enum Flow {
+    Continue,
+    Quit,
+}
+
+/// The state of a REPL session: definitions, loop and division settings, and
+/// how values are displayed.
+pub struct Repl {
+    engine: Engine,
+    display: DisplayOptions,
+    /// Print results computed only from hex literals in hex.
+    autobase: bool,
+    /// Print the tokens and AST of each line before its result.
+    debug: bool,
+    options: ReplOptions,
+}
+
+impl Repl {
+    pub fn new(options: ReplOptions) -> Self {
+        Self {
+            engine: Engine::new(),
+            display: DisplayOptions::default(),
+            autobase: false,
+            debug: false,
+            options,
+        }
+    }
+
+    /// Runs each line of `source` as if it were typed at the prompt, so an rc
+    /// file can hold both definitions and commands such as `:sci`. Their output
+    /// is discarded; a failing line is reported to `warnings` as `name:N: err`
+    /// and the remaining lines still run.
+    pub fn load_rc(&mut self, source: &str, name: &str, warnings: &mut dyn Write) {
+        for (number, line) in source.lines().enumerate() {
+            match self.handle_line(line, &mut io::sink(), &mut History::new()) {
+                Ok(Flow::Continue) => {}
+                Ok(Flow::Quit) => break,
+                Err(err) => writeln!(warnings, "{}:{}: {}", name, number + 1, err).unwrap(),
+            }
+        }
+    }
+
+    /// Prompts for and handles lines until `quit` or the end of `input`. A
+    /// line ending in `\` continues on the next one, after a `... ` prompt.
+    pub fn run(
+        &mut self,
+        input: &mut dyn LineReader,
+        output: &mut dyn Write,
+        history: &mut History,
+    ) {
+        let color = self.options.color;
+        // The lines so far of one continued with `\`, joined by spaces so
+        // their tokens stay apart.
+        let mut continued = String::new();
+        loop {
+            let prompt = if continued.is_empty() { "> " } else { "... " };
+            let res = input.read_line(prompt, output);
+            if let Ok(Some(line)) = &res {
+                if let Some(start) = line.trim_end().strip_suffix('\\') {
+                    continued.push_str(start);
+                    continued.push(' ');
+                    continue;
+                }
+            }
+            // Input that ends after a `\` is still handled.
+            let ended = matches!(res, Ok(None));
+            let res = res.map(|line| mem::take(&mut continued) + &line.unwrap_or_default());
+            if let Ok(line) = &res {
+                if !line.trim().is_empty() {
+                    history.push(line.trim());
+                }
+            }
+            match res {
+                Ok(line) if ended && line.trim().is_empty() => break,
+                Ok(line) => match self.handle_line(&line, output, history) {
+                    Ok(Flow::Continue) => {}
+                    Ok(Flow::Quit) => break,
+                    Err(err) => writeln!(output, "{}", paint_error(&err, color)).unwrap(),
+                },
+                Err(err) => {
+                    writeln!(output, "{}", paint_error(&err, color)).unwrap();
+                }
+            }
+            if ended {
+                break;
+            }
+        }
+    }
+
+    /// How many function calls may be nested before evaluation is stopped.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.engine.set_max_depth(max_depth);
+    }
+
+    fn handle_line(
+        &mut self,
+        line: &str,
+        output: &mut dyn Write,
+        history: &mut History,
+    ) -> Result<Flow, Box<dyn Error>> {
+        let engine = &mut self.engine;
+        let display = &mut self.display;
+        match line.trim() {
+            "" => {}
+            "quit" => return Ok(Flow::Quit),
+            command if command.starts_with(":maxiter") => {
+                match command[":maxiter".len()..].trim().parse::<usize>() {
+                    Ok(max_iterations) => {
+                        engine.set_max_iterations(max_iterations);
+                        writeln!(output, "Maximum loop iterations set to {}", max_iterations)?;
+                    }
+                    Err(_) => writeln!(output, "Usage: :maxiter N")?,
+                }
+            }
+            command if command.starts_with(":maxdepth") => {
+                match command[":maxdepth".len()..].trim().parse::<usize>() {
+                    Ok(max_depth) if max_depth > 0 => {
+                        engine.set_max_depth(max_depth);
+                        writeln!(output, "Maximum recursion depth set to {}", max_depth)?;
+                    }
+                    _ => writeln!(output, "Usage: :maxdepth N")?,
+                }
+            }
+            ":clear" => {
+                write!(
+                    output,
+                    "{}",
+                    clear_screen_sequence(self.options.is_terminal)
+                )?;
+            }
+            ":history" => {
+                for (number, entry) in history.recent(HISTORY_SHOWN) {
+                    writeln!(output, "{:>5}  {}", number, entry)?;
+                }
+            }
+            ":intdiv" => {
+                engine.set_integer_division(!engine.integer_division());
+                let state = if engine.integer_division() {
+                    "on"
+                } else {
+                    "off"
+                };
+                writeln!(output, "Integer division {}", state)?;
+            }
+            ":sci" => {
+                display.scientific = !display.scientific;
+                let state = if display.scientific { "on" } else { "off" };
+                writeln!(output, "Scientific notation {}", state)?;
+            }
+            ":autobase" => {
+                self.autobase = !self.autobase;
+                let state = if self.autobase { "on" } else { "off" };
+                writeln!(output, "Automatic base {}", state)?;
+            }
+            ":group" => {
+                display.group_digits = !display.group_digits;
+                let state = if display.group_digits { "on" } else { "off" };
+                writeln!(output, "Digit grouping {}", state)?;
+            }
+            // Only `def` adds to the engine's environment; `let` and function
+            // calls bind in copies of it that are gone once they finish.
+            ":vars" => {
+                for (name, value) in engine.env().bindings() {
+                    writeln!(output, "{} = {}", name, value.display(*display))?;
+                }
+            }
+            command if command.starts_with(":debug") => {
+                match command[":debug".len()..].trim() {
+                    "on" => self.debug = true,
+                    "off" => self.debug = false,
+                    _ => {
+                        writeln!(output, "Usage: :debug on|off")?;
+                        return Ok(Flow::Continue);
+                    }
+                }
+                let state = if self.debug { "on" } else { "off" };
+                writeln!(output, "Debug {}", state)?;
+            }
+            command if command.starts_with(":tokens ") => {
+                let source = &command[":tokens ".len()..];
+                let tokens = render_tokens(source).map_err(|err| describe_error(&*err, source))?;
+                write!(output, "{}", tokens)?;
+            }
+            command if command.starts_with(":ast ") => {
+                let source = &command[":ast ".len()..];
+                let ast = render_ast(source).map_err(|err| describe_error(&*err, source))?;
+                write!(output, "{}", ast)?;
+            }
+            command if command.starts_with(":type ") => {
+                let source = &command[":type ".len()..];
+                let value = engine
+                    .eval(source)
+                    .map_err(|err| describe_error(&*err, source))?;
+                writeln!(output, "{}", value.type_name())?;
+            }
+            _ => {
+                let describe = |err: Box<dyn Error>| describe_error(&*err, line);
+                if self.debug {
+                    write!(output, "{}", render_tokens(line).map_err(describe)?)?;
+                    write!(output, "{}", render_ast(line).map_err(describe)?)?;
+                }
+                let values = engine.eval_each_to(line, output).map_err(describe)?;
+                let radixes = if self.autobase {
+                    expression_radixes(line)
+                } else {
+                    vec![]
+                };
+                for (i, value) in values.into_iter().enumerate() {
+                    if value != Value::Unit {
+                        let hex = radixes.get(i) == Some(&Some(16));
+                        let display = DisplayOptions { hex, ..*display };
+                        writeln!(output, "{}", value.display(display))?;
+                    }
+                }
             }
         }
+        Ok(Flow::Continue)
     }
 }
 
+// The radix of each expression whose value `Engine::eval_each` returns for
+// `line`, in the same order.
+fn expression_radixes(line: &str) -> Vec<Option<u32>> {
+    let Ok(program) = tokenize_with_spans(line).and_then(|tokens| parse(&tokens)) else {
+        return vec![];
+    };
+    let exprs = program.stmts.iter().filter_map(|stmt| match stmt {
+        Stmt::Expr { expr } => Some(expr),
+        _ => None,
+    });
+    exprs
+        .chain(&program.expr)
+        .map(|expr| expr.radix())
+        .collect()
+}
+
 pub fn evaluate_line(line: &str) -> Result<Value, Box<dyn Error>> {
-    let tokens = tokenize(line)?;
+    let tokens = tokenize_with_spans(line)?;
     let ast = parse(&tokens)?;
-    let value = evaluate(&ast)?;
+    let value = evaluate_program(&ast)?;
     Ok(value)
 }
 
@@ -88,4 +866,460 @@ mod tests {
             assert_eq!(value, Value::Number(test.expected));
         }
     }
+
+    fn run_repl(input: &str) -> String {
+        let mut output = Vec::new();
+        repl(
+            &mut input.as_bytes(),
+            &mut output,
+            ReplOptions::default(),
+            &mut History::new(),
+        );
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn repl_prints_results() {
+        let output = run_repl("1 + 2\n2 * 1000000\nquit\n");
+        assert_eq!(output, "> 3\n> 2000000\n> ");
+    }
+
+    #[test]
+    fn repl_toggles_scientific_notation() {
+        let output = run_repl("1230000\n:sci\n1230000\n42\n:sci\n1230000\n");
+        assert_eq!(
+            output,
+            "> 1230000\n> Scientific notation on\n> 1.23e6\n> 42\n> Scientific notation off\n> 1230000\n> "
+        );
+    }
+
+    #[test]
+    fn repl_toggles_automatic_base() {
+        let output = run_repl("0xFF + 1\n:autobase\n0xFF + 0x1; 0xFF + 1\n0x5 / 0x2\n255 + 1\n");
+        assert_eq!(
+            output,
+            "> 256\n> Automatic base on\n> 0x100\n0x100\n> 2.5\n> 256\n> "
+        );
+        let output = run_repl(":autobase\n0xFF + 0x1\n:autobase\n0xFF + 0x1\n");
+        assert_eq!(
+            output,
+            "> Automatic base on\n> 0x100\n> Automatic base off\n> 256\n> "
+        );
+    }
+
+    #[test]
+    fn repl_toggles_digit_grouping() {
+        let output = run_repl("1000000\n:group\n1000000\n:group\n1000000\n");
+        assert_eq!(
+            output,
+            "> 1000000\n> Digit grouping on\n> 1,000,000\n> Digit grouping off\n> 1000000\n> "
+        );
+    }
+
+    #[test]
+    fn repl_prints_type_of_expression() {
+        let output = run_repl(":type 1 + 2\n:type 1 < 2\n:type try(1 / 0)\n:type 1 / 0\n");
+        assert_eq!(output, "> Number\n> Bool\n> Nil\n> Division by zero\n> ");
+    }
+
+    #[test]
+    fn repl_does_not_print_unit() {
+        let output = run_repl("def x = 1\n");
+        assert_eq!(output, "> > ");
+    }
+
+    #[test]
+    fn repl_keeps_definitions_between_lines() {
+        let output = run_repl("def x = 2\nx * 3\ndef y = x / 0\ny\n");
+        assert_eq!(
+            output,
+            "> > 6\n> Division by zero\n> Undefined variable: y\n> "
+        );
+    }
+
+    fn args_of(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_dump_args() {
+        let mode = |args: &[&str]| parse_args(&args_of(args)).map(|args| args.mode);
+        assert_eq!(mode(&[]), Ok(Mode::Repl));
+        assert_eq!(
+            mode(&["--tokens", "a.sal"]),
+            Ok(Mode::DumpTokens("a.sal".into()))
+        );
+        assert_eq!(mode(&["--ast", "a.sal"]), Ok(Mode::DumpAst("a.sal".into())));
+        assert_eq!(
+            mode(&["a.sal", "b.sal"]),
+            Ok(Mode::Run(vec!["a.sal".into(), "b.sal".into()]))
+        );
+        #[cfg(feature = "json")]
+        assert_eq!(
+            mode(&["--ast-json", "a.sal"]),
+            Ok(Mode::DumpAstJson("a.sal".into()))
+        );
+        assert_eq!(mode(&["--ast"]), Err(USAGE.into()));
+        assert_eq!(mode(&["--bogus", "a.sal"]), Err(USAGE.into()));
+    }
+
+    #[test]
+    fn render_token_stream() {
+        let text = render_tokens("1 + x").unwrap();
+        assert_eq!(
+            text,
+            "0..1 NumericLiteral { value: \"1\" }\n2..3 Plus\n4..5 Identifier { name: \"x\" }\n"
+        );
+    }
+
+    #[test]
+    fn repl_dumps_tokens_and_ast() {
+        let output = run_repl(":tokens 1\n:ast 1\n:ast (\n");
+        assert!(output.starts_with("> 0..1 NumericLiteral { value: \"1\" }\n> Program {\n"));
+        assert!(output.ends_with("> Unexpected end of file\n1 | (\n  |  ^\n> "));
+    }
+
+    #[test]
+    fn debug_prints_tokens_and_ast_before_the_result() {
+        let plain = run_repl("1 + 2\n");
+        assert_eq!(plain, "> 3\n> ");
+
+        let output = run_repl(":debug on\n1 + 2\n:debug off\n1 + 2\n:debug\n");
+        let debugged = format!(
+            "{}{}3\n",
+            render_tokens("1 + 2").unwrap(),
+            render_ast("1 + 2").unwrap()
+        );
+        assert_eq!(
+            output,
+            format!(
+                "> Debug on\n> {}> Debug off\n> 3\n> Usage: :debug on|off\n> ",
+                debugged
+            )
+        );
+        assert!(debugged.starts_with("0..1 NumericLiteral"));
+    }
+
+    #[test]
+    fn vars_lists_only_top_level_definitions() {
+        let output = run_repl(":vars\ndef x = 1;\nlet y = 2 in y\ndef f n = n * 2;\nf(3)\n:vars\n");
+        assert_eq!(output, "> > > 2\n> > 6\n> f = <fn f>\nx = 1\n> ");
+    }
+
+    #[test]
+    fn repl_prints_each_expression_statement() {
+        let output = run_repl("1+1; 2+2\n1+1; def x = 2; x * 3;\n");
+        assert_eq!(output, "> 2\n4\n> 2\n6\n> ");
+    }
+
+    #[test]
+    fn repl_writes_print_output_to_its_output() {
+        let output = run_repl("print 6 * 7;\n");
+        assert_eq!(output, "> 42\n> ");
+    }
+
+    #[test]
+    fn repl_lists_and_records_history() {
+        let output = run_repl("1 + 1\n\n:history\n");
+        assert_eq!(output, "> 2\n> >     1  1 + 1\n    2  :history\n> ");
+
+        let mut history = History::new();
+        history.push("def x = 5;");
+        let mut output = Vec::new();
+        repl(
+            &mut "x\n".as_bytes(),
+            &mut output,
+            ReplOptions::default(),
+            &mut history,
+        );
+        let entries: Vec<(usize, &str)> = history.recent(5).collect();
+        assert_eq!(entries, vec![(1, "def x = 5;"), (2, "x")]);
+    }
+
+    #[test]
+    fn repl_toggles_integer_division() {
+        let output = run_repl("7 / 2\n:intdiv\n7 / 2\n7.5 / 2\n:intdiv\n7 / 2\n");
+        assert_eq!(
+            output,
+            "> 3.5\n> Integer division on\n> 3\n> 3.75\n> Integer division off\n> 3.5\n> "
+        );
+    }
+
+    #[test]
+    fn rc_file_runs_before_the_first_prompt() {
+        let path = env::temp_dir().join(format!("sal-rc-{}", std::process::id()));
+        fs::write(&path, "def double x = x * 2;\n:sci\nundefined\n:intdiv\n").unwrap();
+        let source = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let mut session = Repl::new(ReplOptions::default());
+        let mut warnings = Vec::new();
+        session.load_rc(&source, ".salrc", &mut warnings);
+        assert_eq!(
+            String::from_utf8(warnings).unwrap(),
+            ".salrc:3: Undefined variable: undefined\n"
+        );
+
+        let mut output = Vec::new();
+        session.run(
+            &mut "double(2)\ndouble(1000000)\n7 / 2\n".as_bytes(),
+            &mut output,
+            &mut History::new(),
+        );
+        assert_eq!(String::from_utf8(output).unwrap(), "> 4\n> 2e6\n> 3\n> ");
+    }
+
+    #[test]
+    fn trailing_backslash_continues_the_line() {
+        let mut history = History::new();
+        let mut output = Vec::new();
+        repl(
+            &mut "1 + \\\n2\ndef f x = \\\n  x * 2;\\\n\nf(4)\n[1,\\\n".as_bytes(),
+            &mut output,
+            ReplOptions::default(),
+            &mut history,
+        );
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "> ... 3\n> ... ... > 8\n> ... Unexpected end of file\n1 | [1, \n  |    ^\n"
+        );
+        let entries: Vec<&str> = history.recent(3).map(|(_, entry)| entry).collect();
+        assert_eq!(entries, ["def f x =    x * 2;", "f(4)", "[1,"]);
+    }
+
+    #[test]
+    fn repl_ignores_blank_lines() {
+        let output = run_repl("\n   \n1 + 1\n\t\n");
+        assert_eq!(output, "> > > 2\n> > ");
+    }
+
+    #[test]
+    fn parse_no_color_flag() {
+        assert_eq!(
+            parse_args(&args_of(&["--no-color", "--ast", "a.sal"])),
+            Ok(Args {
+                mode: Mode::DumpAst("a.sal".into()),
+                no_color: true,
+                stdin_mode: StdinMode::Auto,
+                time: false,
+                profile: false,
+                max_depth: DEFAULT_MAX_DEPTH,
+            })
+        );
+        assert_eq!(
+            parse_args(&args_of(&["--no-color"])),
+            Ok(Args {
+                mode: Mode::Repl,
+                no_color: true,
+                stdin_mode: StdinMode::Auto,
+                time: false,
+                profile: false,
+                max_depth: DEFAULT_MAX_DEPTH,
+            })
+        );
+        assert!(!parse_args(&args_of(&[])).unwrap().no_color);
+    }
+
+    #[test]
+    fn parse_max_depth_flag() {
+        let args = parse_args(&args_of(&["--max-depth", "500", "a.sal"])).unwrap();
+        assert_eq!(args.mode, Mode::Run(vec!["a.sal".into()]));
+        assert_eq!(args.max_depth, 500);
+        assert_eq!(
+            parse_args(&args_of(&["--stream", "--max-depth", "5"]))
+                .unwrap()
+                .max_depth,
+            5
+        );
+        assert_eq!(
+            parse_args(&args_of(&[])).unwrap().max_depth,
+            DEFAULT_MAX_DEPTH
+        );
+        assert_eq!(
+            parse_args(&args_of(&["--max-depth", "0"])),
+            Err("--max-depth expects a positive integer, got \"0\"".into())
+        );
+        assert_eq!(
+            parse_args(&args_of(&["--max-depth", "lots"])),
+            Err("--max-depth expects a positive integer, got \"lots\"".into())
+        );
+        assert_eq!(
+            parse_args(&args_of(&["--max-depth"])),
+            Err("--max-depth expects a positive integer, got \"\"".into())
+        );
+        assert_eq!(
+            parse_args(&args_of(&["--max-depth", "5", "--tokens", "a.sal"])),
+            Err(USAGE.into())
+        );
+    }
+
+    #[test]
+    fn parse_grammar_flag() {
+        assert_eq!(
+            parse_args(&args_of(&["--grammar"])).unwrap().mode,
+            Mode::Grammar
+        );
+        assert_eq!(
+            parse_args(&args_of(&["--grammar", "a.sal"])),
+            Err(USAGE.into())
+        );
+    }
+
+    #[test]
+    fn parse_time_flag() {
+        let args = parse_args(&args_of(&["--time", "a.sal"])).unwrap();
+        assert_eq!(args.mode, Mode::Run(vec!["a.sal".into()]));
+        assert!(args.time);
+        assert!(parse_args(&args_of(&["--time", "--batch"])).unwrap().time);
+        assert_eq!(
+            parse_args(&args_of(&["--time", "--ast", "a.sal"])),
+            Err(USAGE.into())
+        );
+        assert_eq!(
+            parse_args(&args_of(&["--time", "--interactive"])),
+            Err(USAGE.into())
+        );
+    }
+
+    #[test]
+    fn parse_profile_flag() {
+        let args = parse_args(&args_of(&["--profile", "a.sal"])).unwrap();
+        assert_eq!(args.mode, Mode::Run(vec!["a.sal".into()]));
+        assert!(args.profile);
+        assert_eq!(
+            parse_args(&args_of(&["--profile", "--tokens", "a.sal"])),
+            Err(USAGE.into())
+        );
+        assert_eq!(
+            parse_args(&args_of(&["--profile", "-i"])),
+            Err(USAGE.into())
+        );
+    }
+
+    #[test]
+    fn format_durations_in_the_largest_unit() {
+        assert_eq!(format_duration(Duration::from_nanos(1500)), "1.5µs");
+        assert_eq!(format_duration(Duration::from_micros(1234)), "1.2ms");
+        assert_eq!(format_duration(Duration::from_millis(2500)), "2.5s");
+    }
+
+    #[test]
+    fn parse_stdin_mode_flags() {
+        let stdin_mode = |args: &[&str]| parse_args(&args_of(args)).map(|args| args.stdin_mode);
+        assert_eq!(stdin_mode(&[]), Ok(StdinMode::Auto));
+        assert_eq!(stdin_mode(&["--interactive"]), Ok(StdinMode::Interactive));
+        assert_eq!(stdin_mode(&["-i"]), Ok(StdinMode::Interactive));
+        assert_eq!(stdin_mode(&["--batch"]), Ok(StdinMode::Batch));
+        assert_eq!(
+            stdin_mode(&["-i", "--batch"]),
+            Err("--interactive and --batch cannot be used together".into())
+        );
+        assert_eq!(stdin_mode(&["--stream"]), Ok(StdinMode::Stream));
+        assert_eq!(
+            stdin_mode(&["--batch", "--stream"]),
+            Err("--batch and --stream cannot be used together".into())
+        );
+        assert_eq!(stdin_mode(&["--batch", "a.sal"]), Err(USAGE.into()));
+        assert_eq!(
+            parse_args(&args_of(&["--stream", "--time"])),
+            Err(USAGE.into())
+        );
+    }
+
+    #[test]
+    fn stream_evaluates_each_line_and_continues_after_errors() {
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        let all_ok = stream(
+            &mut "def rate = 2;\nrate * 3\n\n1 / 0\nprint rate;\nundefined + 1\nrate + 1\n"
+                .as_bytes(),
+            &mut output,
+            &mut errors,
+            false,
+            EvalOptions::default(),
+        );
+        assert!(!all_ok);
+        assert_eq!(String::from_utf8(output).unwrap(), "6\n2\n3\n");
+        assert_eq!(
+            String::from_utf8(errors).unwrap(),
+            "<stdin>:4: Division by zero\n<stdin>:6: Undefined variable: undefined\n"
+        );
+    }
+
+    #[test]
+    fn stdin_mode_overrides_terminal_detection() {
+        assert!(reads_interactively(StdinMode::Auto, true));
+        assert!(!reads_interactively(StdinMode::Auto, false));
+        assert!(reads_interactively(StdinMode::Interactive, false));
+        assert!(!reads_interactively(StdinMode::Batch, true));
+    }
+
+    #[test]
+    fn should_color_honors_no_color_and_terminal() {
+        assert!(should_color(false, None, true));
+        assert!(!should_color(false, None, false));
+        assert!(!should_color(true, None, true));
+        assert!(!should_color(false, Some(OsStr::new("1")), true));
+        assert!(should_color(false, Some(OsStr::new("")), true));
+    }
+
+    #[test]
+    fn repl_colors_errors() {
+        let mut output = Vec::new();
+        let options = ReplOptions {
+            color: true,
+            is_terminal: true,
+        };
+        repl(
+            &mut "1 / 0\n1\n".as_bytes(),
+            &mut output,
+            options,
+            &mut History::new(),
+        );
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, "> \x1b[31mDivision by zero\x1b[0m\n> 1\n> ");
+    }
+
+    #[test]
+    fn clear_sequence_needs_a_terminal() {
+        assert_eq!(clear_screen_sequence(true), "\x1b[2J\x1b[H");
+        assert_eq!(clear_screen_sequence(false), "");
+    }
+
+    #[test]
+    fn repl_clears_screen_and_keeps_definitions() {
+        let mut output = Vec::new();
+        let options = ReplOptions {
+            color: false,
+            is_terminal: true,
+        };
+        repl(
+            &mut "def x = 4\n:clear\nx\n".as_bytes(),
+            &mut output,
+            options,
+            &mut History::new(),
+        );
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, "> > \x1b[2J\x1b[H> 4\n> ");
+
+        assert_eq!(run_repl(":clear\n1\n"), "> > 1\n> ");
+    }
+
+    #[test]
+    fn repl_sets_max_depth() {
+        let output = run_repl("def f n = f(n);\n:maxdepth 3\nf(1)\n:maxdepth 0\n:maxdepth deep\n");
+        assert_eq!(
+            output,
+            "> > Maximum recursion depth set to 3\n> Maximum recursion depth exceeded\n> Usage: :maxdepth N\n> Usage: :maxdepth N\n> "
+        );
+    }
+
+    #[test]
+    fn repl_sets_max_iterations() {
+        let output = run_repl(":maxiter 5\nwhile true { 1 }\n:maxiter lots\n");
+        assert_eq!(
+            output,
+            "> Maximum loop iterations set to 5\n> Maximum loop iterations exceeded\n> Usage: :maxiter N\n> "
+        );
+    }
 }
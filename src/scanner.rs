@@ -1,25 +1,87 @@
-use rpds::Vector;
 use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+use std::sync::OnceLock;
 
 type Source = Vec<char>;
-type Tokens = Vector<Token>;
+type Tokens = Vec<(Token, Span)>;
+
+/// Byte offsets `(start, end)` of a token or expression in the source text.
+pub type Span = (usize, usize);
+
+/// A token with the byte range of its text in the source.
+pub type SpannedToken = (Token, Range<usize>);
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
 pub enum Token {
     EOF,
+    // Text that could not be lexed. Only `tokenize_all` produces it, in place
+    // of the text, so scanning can continue after an error.
+    Error,
 
     // Literals
-    NumericLiteral { value: String },
+    NumericLiteral {
+        value: String,
+    },
+    #[cfg(feature = "complex")]
+    ImaginaryLiteral {
+        value: String,
+    },
+    StringLiteral {
+        value: String,
+    },
+    Identifier {
+        name: String,
+    },
+
+    // Keywords
+    Catch,
+    Cond,
+    Def,
+    Else,
+    Fn,
+    If,
+    In,
+    Let,
+    Print,
+    True,
+    False,
+    Try,
+    While,
 
     // Punctuation
     OpenParen,
     CloseParen,
+    OpenBrace,
+    CloseBrace,
+    OpenBracket,
+    CloseBracket,
+    Comma,
+    Semicolon,
+    Colon,
+    Dot,
+    DotDot,
 
     // Operators
     Plus,
     Minus,
     Astrix,
     Slash,
+    SlashSlash,
+    Percent,
+    Caret,
+    Equal,
+    EqualEqual,
+    EqualGreater,
+    BangEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    AndAnd,
+    PipePipe,
+    PipeGreater,
 }
 
 // TODO: Make Lexer an iterator and remove mutable used variable
@@ -34,17 +96,41 @@ fn is_end(lex: &Lexer, used: usize) -> bool {
     lex.source.len() <= used
 }
 
-fn number<'a>(lex: &'a Lexer) -> (Lexer<'a>, Option<Token>) {
+fn number<'a>(lex: &Lexer<'a>) -> (Lexer<'a>, Option<Token>) {
+    if peek_is(lex, 0, '0') && (peek_is(lex, 1, 'x') || peek_is(lex, 1, 'X')) {
+        return hex_number(lex);
+    }
+
     let mut used = 0;
     while !is_end(lex, used) && lex.source[used].is_ascii_digit() {
         used += 1;
     }
-    if !is_end(lex, used) && lex.source[used] == '.' {
+    // `1..5` is a range, not the number `1.` followed by `.5`.
+    if peek_is(lex, used, '.') && !peek_is(lex, used + 1, '.') {
         used += 1;
     }
     while !is_end(lex, used) && lex.source[used].is_ascii_digit() {
         used += 1;
     }
+    used += exponent(lex, used);
+
+    // A number immediately followed by a lone `i` is imaginary: `3i`.
+    #[cfg(feature = "complex")]
+    if !is_end(lex, used)
+        && lex.source[used] == 'i'
+        && (is_end(lex, used + 1) || !is_identifier_char(lex.source[used + 1]))
+    {
+        return (
+            Lexer {
+                source: &(lex.source[used + 1..]),
+            },
+            Some(Token::ImaginaryLiteral {
+                value: lex.source[..used].iter().collect(),
+            }),
+        );
+    }
+
+    used += suffix(lex, used);
 
     (
         Lexer {
@@ -56,7 +142,137 @@ fn number<'a>(lex: &'a Lexer) -> (Lexer<'a>, Option<Token>) {
     )
 }
 
-fn eat_whitespace<'a>(lex: &'a Lexer) -> Option<Lexer<'a>> {
+// The length of a type suffix such as `_i64` starting at `start`, or 0 when
+// there is none. The suffix is part of the literal; the parser checks it.
+fn suffix(lex: &Lexer, start: usize) -> usize {
+    let mut used = start;
+    if peek_is(lex, used, '_') {
+        while !is_end(lex, used) && is_identifier_char(lex.source[used]) {
+            used += 1;
+        }
+    }
+    used - start
+}
+
+// The length of a `e3`, `E-2` style exponent starting at `start`, or 0 when
+// there is none. An `e` without digits after it is left for the next token.
+fn exponent(lex: &Lexer, start: usize) -> usize {
+    if !(peek_is(lex, start, 'e') || peek_is(lex, start, 'E')) {
+        return 0;
+    }
+    let mut used = start + 1;
+    if peek_is(lex, used, '+') || peek_is(lex, used, '-') {
+        used += 1;
+    }
+    let digits = used;
+    while !is_end(lex, used) && lex.source[used].is_ascii_digit() {
+        used += 1;
+    }
+    if used == digits {
+        0
+    } else {
+        used - start
+    }
+}
+
+// `0x` followed by hex digits. The literal keeps its prefix, so a malformed one
+// such as `0x` is reported when it is parsed.
+fn hex_number<'a>(lex: &Lexer<'a>) -> (Lexer<'a>, Option<Token>) {
+    let mut used = 2;
+    while !is_end(lex, used) && lex.source[used].is_ascii_hexdigit() {
+        used += 1;
+    }
+    used += suffix(lex, used);
+
+    (
+        Lexer {
+            source: &(lex.source[used..]),
+        },
+        Some(Token::NumericLiteral {
+            value: lex.source[..used].iter().collect(),
+        }),
+    )
+}
+
+// Strings run to the next `"` and have no escape sequences.
+fn string<'a>(lex: &Lexer<'a>) -> Result<(Lexer<'a>, Option<Token>), Box<dyn Error>> {
+    let mut used = 1;
+    while !is_end(lex, used) && lex.source[used] != '"' {
+        used += 1;
+    }
+    if is_end(lex, used) {
+        return Err("Unterminated string".into());
+    }
+
+    Ok((
+        Lexer {
+            source: &(lex.source[used + 1..]),
+        },
+        Some(Token::StringLiteral {
+            value: lex.source[1..used].iter().collect(),
+        }),
+    ))
+}
+
+// Reserved words and the tokens they lex to. This is the only list of
+// keywords; `keywords` and `is_keyword` are derived from it.
+const KEYWORDS: &[(&str, Token)] = &[
+    ("catch", Token::Catch),
+    ("cond", Token::Cond),
+    ("def", Token::Def),
+    ("else", Token::Else),
+    ("fn", Token::Fn),
+    ("if", Token::If),
+    ("in", Token::In),
+    ("let", Token::Let),
+    ("print", Token::Print),
+    ("true", Token::True),
+    ("false", Token::False),
+    ("try", Token::Try),
+    ("while", Token::While),
+];
+
+/// The reserved words of the language, for completion and highlighting.
+pub fn keywords() -> &'static [&'static str] {
+    static NAMES: OnceLock<Vec<&'static str>> = OnceLock::new();
+    NAMES.get_or_init(|| KEYWORDS.iter().map(|(keyword, _)| *keyword).collect())
+}
+
+pub fn is_keyword(word: &str) -> bool {
+    KEYWORDS.iter().any(|(keyword, _)| *keyword == word)
+}
+
+// Identifiers follow Unicode's identifier rules, as Rust's do, so `π` and
+// `número` are names while operators stay ASCII.
+fn is_identifier_start(c: char) -> bool {
+    c == '_' || unicode_ident::is_xid_start(c)
+}
+
+fn is_identifier_char(c: char) -> bool {
+    unicode_ident::is_xid_continue(c)
+}
+
+fn identifier<'a>(lex: &Lexer<'a>) -> (Lexer<'a>, Option<Token>) {
+    let mut used = 0;
+    while !is_end(lex, used) && is_identifier_char(lex.source[used]) {
+        used += 1;
+    }
+
+    let name: String = lex.source[..used].iter().collect();
+    let token = match KEYWORDS.iter().find(|(keyword, _)| *keyword == name) {
+        Some((_, token)) => token.clone(),
+        None => Token::Identifier { name },
+    };
+
+    (
+        Lexer {
+            source: &(lex.source[used..]),
+        },
+        Some(token),
+    )
+}
+
+fn eat_whitespace<'a>(lex: &Lexer<'a>) -> Option<Lexer<'a>> {
     let mut used: usize = 0;
     while !is_end(lex, used) && lex.source[used].is_whitespace() {
         used += 1;
@@ -70,45 +286,53 @@ fn eat_whitespace<'a>(lex: &'a Lexer) -> Option<Lexer<'a>> {
     }
 }
 
-fn next_token<'a>(lex: &'a Lexer) -> Result<(Lexer<'a>, Option<Token>), Box<dyn Error>> {
+fn peek_is(lex: &Lexer, offset: usize, expected: char) -> bool {
+    lex.source.get(offset) == Some(&expected)
+}
+
+fn symbol<'a>(lex: &Lexer<'a>, used: usize, token: Token) -> (Lexer<'a>, Option<Token>) {
+    (
+        Lexer {
+            source: &(lex.source[used..]),
+        },
+        Some(token),
+    )
+}
+
+fn next_token<'a>(lex: &Lexer<'a>) -> Result<(Lexer<'a>, Option<Token>), Box<dyn Error>> {
     match lex.source[0] {
         '0'..='9' => Ok(number(lex)),
-        '+' => Ok((
-            Lexer {
-                source: &(lex.source[1..]),
-            },
-            Some(Token::Plus),
-        )),
-        '-' => Ok((
-            Lexer {
-                source: &(lex.source[1..]),
-            },
-            Some(Token::Minus),
-        )),
-        '*' => Ok((
-            Lexer {
-                source: &(lex.source[1..]),
-            },
-            Some(Token::Astrix),
-        )),
-        '/' => Ok((
-            Lexer {
-                source: &(lex.source[1..]),
-            },
-            Some(Token::Slash),
-        )),
-        '(' => Ok((
-            Lexer {
-                source: &(lex.source[1..]),
-            },
-            Some(Token::OpenParen),
-        )),
-        ')' => Ok((
-            Lexer {
-                source: &(lex.source[1..]),
-            },
-            Some(Token::CloseParen),
-        )),
+        c if is_identifier_start(c) => Ok(identifier(lex)),
+        '"' => string(lex),
+        '+' => Ok(symbol(lex, 1, Token::Plus)),
+        '-' => Ok(symbol(lex, 1, Token::Minus)),
+        '*' => Ok(symbol(lex, 1, Token::Astrix)),
+        '/' if peek_is(lex, 1, '/') => Ok(symbol(lex, 2, Token::SlashSlash)),
+        '/' => Ok(symbol(lex, 1, Token::Slash)),
+        '%' => Ok(symbol(lex, 1, Token::Percent)),
+        '^' => Ok(symbol(lex, 1, Token::Caret)),
+        '=' if peek_is(lex, 1, '=') => Ok(symbol(lex, 2, Token::EqualEqual)),
+        '=' if peek_is(lex, 1, '>') => Ok(symbol(lex, 2, Token::EqualGreater)),
+        '=' => Ok(symbol(lex, 1, Token::Equal)),
+        '!' if peek_is(lex, 1, '=') => Ok(symbol(lex, 2, Token::BangEqual)),
+        '<' if peek_is(lex, 1, '=') => Ok(symbol(lex, 2, Token::LessEqual)),
+        '<' => Ok(symbol(lex, 1, Token::Less)),
+        '>' if peek_is(lex, 1, '=') => Ok(symbol(lex, 2, Token::GreaterEqual)),
+        '>' => Ok(symbol(lex, 1, Token::Greater)),
+        '&' if peek_is(lex, 1, '&') => Ok(symbol(lex, 2, Token::AndAnd)),
+        '|' if peek_is(lex, 1, '|') => Ok(symbol(lex, 2, Token::PipePipe)),
+        '|' if peek_is(lex, 1, '>') => Ok(symbol(lex, 2, Token::PipeGreater)),
+        ',' => Ok(symbol(lex, 1, Token::Comma)),
+        ';' => Ok(symbol(lex, 1, Token::Semicolon)),
+        ':' => Ok(symbol(lex, 1, Token::Colon)),
+        '.' if peek_is(lex, 1, '.') => Ok(symbol(lex, 2, Token::DotDot)),
+        '.' => Ok(symbol(lex, 1, Token::Dot)),
+        '(' => Ok(symbol(lex, 1, Token::OpenParen)),
+        ')' => Ok(symbol(lex, 1, Token::CloseParen)),
+        '{' => Ok(symbol(lex, 1, Token::OpenBrace)),
+        '}' => Ok(symbol(lex, 1, Token::CloseBrace)),
+        '[' => Ok(symbol(lex, 1, Token::OpenBracket)),
+        ']' => Ok(symbol(lex, 1, Token::CloseBracket)),
         _ => {
             if let Some(lex) = eat_whitespace(lex) {
                 Ok((lex, None))
@@ -119,29 +343,232 @@ fn next_token<'a>(lex: &'a Lexer) -> Result<(Lexer<'a>, Option<Token>), Box<dyn
     }
 }
 
-fn do_tokenize(lex: &Lexer, tokens: Tokens) -> Result<Tokens, Box<dyn Error>> {
-    if is_end(lex, 0) {
-        Ok(tokens)
-    } else {
-        let next = next_token(lex)?;
-        let new_tokens = if let Some(token) = next.1 {
-            tokens.push_back(token)
-        } else {
-            tokens
-        };
-        do_tokenize(&next.0, new_tokens)
+// Scans `source` front to back in one pass, so tokenizing stays linear in the
+// length of the input however long its tokens are. Spans are tracked as char
+// indexes while scanning; `length` is the total number of chars so the current
+// index is `length - remaining`.
+fn do_tokenize(source: &[char]) -> Result<Tokens, Box<dyn Error>> {
+    let length = source.len();
+    let mut tokens = Tokens::new();
+    let mut lex = Lexer { source };
+    while !is_end(&lex, 0) {
+        let start = length - lex.source.len();
+        let (next, token) = next_token(&lex)?;
+        let end = length - next.source.len();
+        if let Some(token) = token {
+            tokens.push((token, (start, end)));
+        }
+        lex = next;
     }
+    Ok(tokens)
 }
 
-pub fn tokenize(source: &str) -> Result<Vec<Token>, Box<dyn Error>> {
+pub fn tokenize_with_spans(source: &str) -> Result<Vec<(Token, Span)>, Box<dyn Error>> {
+    let mut offsets: Vec<usize> = source.char_indices().map(|(offset, _)| offset).collect();
+    offsets.push(source.len());
     let source: Source = source.chars().collect();
-    let tokens = Tokens::new();
-    let lex = Lexer {
-        source: &source[..],
-    };
+    let tokens = do_tokenize(&source)?;
+    Ok(tokens
+        .into_iter()
+        .map(|(token, (start, end))| (token, (offsets[start], offsets[end])))
+        .collect())
+}
+
+/// Tokenizes `source`, pairing each token with the byte range it covers, so
+/// `&source[range]` is the token's text. Intended for editor integrations.
+pub fn tokenize_spanned(source: &str) -> Result<Vec<SpannedToken>, Box<dyn Error>> {
+    let tokens = tokenize_with_spans(source)?;
+    Ok(tokens
+        .into_iter()
+        .map(|(token, (start, end))| (token, start..end))
+        .collect())
+}
+
+pub fn tokenize(source: &str) -> Result<Vec<Token>, Box<dyn Error>> {
+    let tokens = tokenize_with_spans(source)?;
+    Ok(tokens.into_iter().map(|(token, _)| token).collect())
+}
+
+/// A change to source text: the byte range of the old text that was replaced,
+/// and the text that replaced it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub text: String,
+}
 
-    let tokens = do_tokenize(&lex, tokens)?;
-    Ok(tokens.iter().cloned().collect())
+/// Updates the tokens of a source after `edit`, lexing only the text around
+/// it. `tokens` are those of the source before the edit and `source` is the
+/// text after it; the result is what `tokenize_with_spans(source)` gives.
+///
+/// Lexing restarts at the last token before the edit that follows whitespace,
+/// since no earlier token could have looked past that whitespace, and stops at
+/// the first token after the edit that starts where an old one did: from there
+/// on the text is unchanged, so the old tokens are reused, shifted by the
+/// change in length.
+pub fn retokenize(
+    tokens: &[(Token, Span)],
+    edit: &Edit,
+    source: &str,
+) -> Result<Vec<(Token, Span)>, Box<dyn Error>> {
+    let restart = (0..tokens.len())
+        .rev()
+        .find(|&i| {
+            tokens[i].1 .0 <= edit.range.start && (i == 0 || tokens[i - 1].1 .1 < tokens[i].1 .0)
+        })
+        .unwrap_or(0);
+    let restart_offset = tokens
+        .get(restart)
+        .map_or(0, |(_, span)| span.0.min(edit.range.start));
+    let edited_end = edit.range.start + edit.text.len();
+    let shift = |offset: usize| offset + edited_end - edit.range.end;
+
+    let rest = &source[restart_offset..];
+    let mut offsets: Vec<usize> = rest
+        .char_indices()
+        .map(|(offset, _)| restart_offset + offset)
+        .collect();
+    offsets.push(source.len());
+    let chars: Source = rest.chars().collect();
+
+    let mut retokenized = tokens[..restart].to_vec();
+    let mut lex = Lexer { source: &chars };
+    while !is_end(&lex, 0) {
+        let start = chars.len() - lex.source.len();
+        let (next, token) = next_token(&lex)?;
+        let end = chars.len() - next.source.len();
+        if let Some(token) = token {
+            let span = (offsets[start], offsets[end]);
+            if span.0 >= edited_end {
+                let old_start = span.0 + edit.range.end - edited_end;
+                if let Ok(i) = tokens.binary_search_by_key(&old_start, |(_, span)| span.0) {
+                    retokenized.extend(tokens[i..].iter().map(|(token, (start, end))| {
+                        (token.clone(), (shift(*start), shift(*end)))
+                    }));
+                    return Ok(retokenized);
+                }
+            }
+            retokenized.push((token, span));
+        }
+        lex = next;
+    }
+    Ok(retokenized)
+}
+
+/// A lexing error and the byte offsets of the text it was found in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for LexError {
+    // Columns count from the start of the input, starting at 1.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.span.0 + 1)
+    }
+}
+
+impl LexError {
+    /// Renders the error over the `source` it came from, with its span
+    /// underlined.
+    pub fn render(&self, source: &str) -> String {
+        render_span(&self.message, self.span, source)
+    }
+}
+
+/// Renders `message` over `source`: the message, then the line holding the
+/// start of `span` with the span underlined. A span running past the end of
+/// that line is underlined to the end of the line.
+pub fn render_span(message: &str, span: Span, source: &str) -> String {
+    let start = span.0.min(source.len());
+    let end = span.1.clamp(start, source.len());
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[start..]
+        .find('\n')
+        .map_or(source.len(), |i| start + i);
+    let line_number = source[..start].matches('\n').count() + 1;
+    let gutter = line_number.to_string();
+    let indent = source[line_start..start].chars().count();
+    let width = source[start..end.min(line_end)].chars().count().max(1);
+    format!(
+        "{}\n{} | {}\n{} | {}{}",
+        message,
+        gutter,
+        &source[line_start..line_end],
+        " ".repeat(gutter.len()),
+        " ".repeat(indent),
+        "^".repeat(width)
+    )
+}
+
+impl Error for LexError {}
+
+/// Checks that the parentheses of `tokens` pair up, reporting the first
+/// unmatched one: a `)` with no `(` before it, or else the outermost `(` left
+/// open. This is a quick check that can run before parsing, so a missing `)`
+/// in a long input is reported where it starts; the parser still checks
+/// parentheses itself.
+pub fn check_parens(tokens: &[(Token, Span)]) -> Result<(), LexError> {
+    let mut open = vec![];
+    for (token, span) in tokens {
+        match token {
+            Token::OpenParen => open.push(*span),
+            Token::CloseParen if open.pop().is_none() => {
+                return Err(LexError {
+                    message: "Unmatched )".into(),
+                    span: *span,
+                });
+            }
+            _ => {}
+        }
+    }
+    match open.first() {
+        Some(span) => Err(LexError {
+            message: "Unmatched (".into(),
+            span: *span,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Tokenizes all of `source`, collecting every lexing error instead of
+/// stopping at the first. Each piece of text that fails to lex becomes a
+/// `Token::Error`: a single unknown character, or an unterminated string
+/// through to the end of the input.
+pub fn tokenize_all(source: &str) -> (Vec<Token>, Vec<LexError>) {
+    let mut offsets: Vec<usize> = source.char_indices().map(|(offset, _)| offset).collect();
+    offsets.push(source.len());
+    let chars: Source = source.chars().collect();
+    let length = chars.len();
+    let mut tokens = vec![];
+    let mut errors = vec![];
+    let mut lex = Lexer { source: &chars };
+    while !is_end(&lex, 0) {
+        let start = length - lex.source.len();
+        match next_token(&lex) {
+            Ok((next, token)) => {
+                tokens.extend(token);
+                lex = next;
+            }
+            Err(err) => {
+                let used = if lex.source[0] == '"' {
+                    lex.source.len()
+                } else {
+                    1
+                };
+                errors.push(LexError {
+                    message: err.to_string(),
+                    span: (offsets[start], offsets[start + used]),
+                });
+                tokens.push(Token::Error);
+                lex = Lexer {
+                    source: &lex.source[used..],
+                };
+            }
+        }
+    }
+    (tokens, errors)
 }
 
 #[cfg(test)]
@@ -187,6 +614,24 @@ mod tests {
                     value: "0.123456789".into(),
                 },
             },
+            Test {
+                source: "0xFF",
+                expected: Token::NumericLiteral {
+                    value: "0xFF".into(),
+                },
+            },
+            Test {
+                source: "1e3",
+                expected: Token::NumericLiteral {
+                    value: "1e3".into(),
+                },
+            },
+            Test {
+                source: "2.5E-2",
+                expected: Token::NumericLiteral {
+                    value: "2.5E-2".into(),
+                },
+            },
         ];
         for test in tests {
             let tokens = tokenize(test.source).unwrap();
@@ -328,6 +773,167 @@ mod tests {
                 source: "/",
                 expected: vec![Token::Slash],
             },
+            Test {
+                source: "//",
+                expected: vec![Token::SlashSlash],
+            },
+            Test {
+                source: "/ /",
+                expected: vec![Token::Slash, Token::Slash],
+            },
+            Test {
+                source: "%",
+                expected: vec![Token::Percent],
+            },
+            Test {
+                source: "^",
+                expected: vec![Token::Caret],
+            },
+            Test {
+                source: "=",
+                expected: vec![Token::Equal],
+            },
+            Test {
+                source: "==",
+                expected: vec![Token::EqualEqual],
+            },
+            Test {
+                source: "!=",
+                expected: vec![Token::BangEqual],
+            },
+            Test {
+                source: "<",
+                expected: vec![Token::Less],
+            },
+            Test {
+                source: "<=",
+                expected: vec![Token::LessEqual],
+            },
+            Test {
+                source: ">",
+                expected: vec![Token::Greater],
+            },
+            Test {
+                source: ">=",
+                expected: vec![Token::GreaterEqual],
+            },
+            Test {
+                source: "&&",
+                expected: vec![Token::AndAnd],
+            },
+            Test {
+                source: "||",
+                expected: vec![Token::PipePipe],
+            },
+            Test {
+                source: "p.x",
+                expected: vec![
+                    Token::Identifier { name: "p".into() },
+                    Token::Dot,
+                    Token::Identifier { name: "x".into() },
+                ],
+            },
+            Test {
+                source: "3.14",
+                expected: vec![Token::NumericLiteral {
+                    value: "3.14".into(),
+                }],
+            },
+            // A type suffix belongs to the number.
+            Test {
+                source: "5_i64 + 2.5e1_f64",
+                expected: vec![
+                    Token::NumericLiteral {
+                        value: "5_i64".into(),
+                    },
+                    Token::Plus,
+                    Token::NumericLiteral {
+                        value: "2.5e1_f64".into(),
+                    },
+                ],
+            },
+            Test {
+                source: "0xFF_i64",
+                expected: vec![Token::NumericLiteral {
+                    value: "0xFF_i64".into(),
+                }],
+            },
+            // A `.` after a name is always a field access, even before digits.
+            Test {
+                source: "a.1",
+                expected: vec![
+                    Token::Identifier { name: "a".into() },
+                    Token::Dot,
+                    Token::NumericLiteral { value: "1".into() },
+                ],
+            },
+            // A `.` right after a number's digits belongs to the number.
+            Test {
+                source: "1.5.x",
+                expected: vec![
+                    Token::NumericLiteral {
+                        value: "1.5".into(),
+                    },
+                    Token::Dot,
+                    Token::Identifier { name: "x".into() },
+                ],
+            },
+            // Two dots make a range, even right after a number's digits.
+            Test {
+                source: "1..5",
+                expected: vec![
+                    Token::NumericLiteral { value: "1".into() },
+                    Token::DotDot,
+                    Token::NumericLiteral { value: "5".into() },
+                ],
+            },
+            Test {
+                source: "1.5..2",
+                expected: vec![
+                    Token::NumericLiteral {
+                        value: "1.5".into(),
+                    },
+                    Token::DotDot,
+                    Token::NumericLiteral { value: "2".into() },
+                ],
+            },
+            Test {
+                source: "3.x",
+                expected: vec![
+                    Token::NumericLiteral { value: "3.".into() },
+                    Token::Identifier { name: "x".into() },
+                ],
+            },
+            Test {
+                source: "(p).x",
+                expected: vec![
+                    Token::OpenParen,
+                    Token::Identifier { name: "p".into() },
+                    Token::CloseParen,
+                    Token::Dot,
+                    Token::Identifier { name: "x".into() },
+                ],
+            },
+            Test {
+                source: "=>",
+                expected: vec![Token::EqualGreater],
+            },
+            Test {
+                source: "|>",
+                expected: vec![Token::PipeGreater],
+            },
+            Test {
+                source: "||>",
+                expected: vec![Token::PipePipe, Token::Greater],
+            },
+            Test {
+                source: "1<=2",
+                expected: vec![
+                    Token::NumericLiteral { value: "1".into() },
+                    Token::LessEqual,
+                    Token::NumericLiteral { value: "2".into() },
+                ],
+            },
         ];
         for test in tests {
             let tokens = tokenize(test.source).unwrap();
@@ -350,10 +956,466 @@ mod tests {
                 source: ")",
                 expected: vec![Token::CloseParen],
             },
+            Test {
+                source: "{}",
+                expected: vec![Token::OpenBrace, Token::CloseBrace],
+            },
+            Test {
+                source: "[]",
+                expected: vec![Token::OpenBracket, Token::CloseBracket],
+            },
+            Test {
+                source: ",",
+                expected: vec![Token::Comma],
+            },
+            Test {
+                source: ";",
+                expected: vec![Token::Semicolon],
+            },
+        ];
+        for test in tests {
+            let tokens = tokenize(test.source).unwrap();
+            assert_eq!(tokens, test.expected);
+        }
+    }
+
+    #[test]
+    fn tokenize_identifiers_and_keywords() {
+        struct Test {
+            source: &'static str,
+            expected: Vec<Token>,
+        }
+        let tests = [
+            Test {
+                source: "x",
+                expected: vec![Token::Identifier { name: "x".into() }],
+            },
+            Test {
+                source: "_width2",
+                expected: vec![Token::Identifier {
+                    name: "_width2".into(),
+                }],
+            },
+            Test {
+                source: "def",
+                expected: vec![Token::Def],
+            },
+            Test {
+                source: "print",
+                expected: vec![Token::Print],
+            },
+            Test {
+                source: "true false",
+                expected: vec![Token::True, Token::False],
+            },
+            Test {
+                source: "define",
+                expected: vec![Token::Identifier {
+                    name: "define".into(),
+                }],
+            },
+            Test {
+                source: "número",
+                expected: vec![Token::Identifier {
+                    name: "número".into(),
+                }],
+            },
+            Test {
+                source: "π*r",
+                expected: vec![
+                    Token::Identifier { name: "π".into() },
+                    Token::Astrix,
+                    Token::Identifier { name: "r".into() },
+                ],
+            },
+            Test {
+                // An `e` followed by a combining acute accent.
+                source: "cafe\u{301}",
+                expected: vec![Token::Identifier {
+                    name: "cafe\u{301}".into(),
+                }],
+            },
+            Test {
+                source: "def x = 1;",
+                expected: vec![
+                    Token::Def,
+                    Token::Identifier { name: "x".into() },
+                    Token::Equal,
+                    Token::NumericLiteral { value: "1".into() },
+                    Token::Semicolon,
+                ],
+            },
+        ];
+        for test in tests {
+            let tokens = tokenize(test.source).unwrap();
+            assert_eq!(tokens, test.expected);
+        }
+    }
+
+    #[test]
+    fn incomplete_logical_operators_are_errors() {
+        for source in ["&", "|", "!", "1 & 2"] {
+            assert!(tokenize(source).is_err(), "{} should not tokenize", source);
+        }
+    }
+
+    #[test]
+    fn tokenize_with_byte_spans() {
+        let tokens = tokenize_with_spans(" 12 +\n(3.5)").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                (Token::NumericLiteral { value: "12".into() }, (1, 3)),
+                (Token::Plus, (4, 5)),
+                (Token::OpenParen, (6, 7)),
+                (
+                    Token::NumericLiteral {
+                        value: "3.5".into()
+                    },
+                    (7, 10)
+                ),
+                (Token::CloseParen, (10, 11)),
+            ]
+        );
+    }
+
+    #[test]
+    fn spans_are_byte_offsets() {
+        // U+3000 is whitespace that takes three bytes in UTF-8.
+        let tokens = tokenize_with_spans("\u{3000}1+2").unwrap();
+        let spans: Vec<Span> = tokens.iter().map(|(_, span)| *span).collect();
+        assert_eq!(spans, vec![(3, 4), (4, 5), (5, 6)]);
+    }
+
+    #[cfg(feature = "complex")]
+    #[test]
+    fn tokenize_imaginary_literals() {
+        struct Test {
+            source: &'static str,
+            expected: Vec<Token>,
+        }
+        let tests = [
+            Test {
+                source: "3i",
+                expected: vec![Token::ImaginaryLiteral { value: "3".into() }],
+            },
+            Test {
+                source: "2.5i+1",
+                expected: vec![
+                    Token::ImaginaryLiteral {
+                        value: "2.5".into(),
+                    },
+                    Token::Plus,
+                    Token::NumericLiteral { value: "1".into() },
+                ],
+            },
+            Test {
+                source: "2 i",
+                expected: vec![
+                    Token::NumericLiteral { value: "2".into() },
+                    Token::Identifier { name: "i".into() },
+                ],
+            },
+            Test {
+                source: "2ix",
+                expected: vec![
+                    Token::NumericLiteral { value: "2".into() },
+                    Token::Identifier { name: "ix".into() },
+                ],
+            },
         ];
         for test in tests {
             let tokens = tokenize(test.source).unwrap();
             assert_eq!(tokens, test.expected);
         }
     }
+
+    #[test]
+    fn tokenize_all_reports_every_error() {
+        let (tokens, errors) = tokenize_all("1 $ ¬ @ \"ab");
+        assert_eq!(
+            tokens,
+            vec![
+                Token::NumericLiteral { value: "1".into() },
+                Token::Error,
+                Token::Error,
+                Token::Error,
+                Token::Error,
+            ]
+        );
+        let errors: Vec<(String, Span)> = errors
+            .into_iter()
+            .map(|err| (err.message, err.span))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                ("Unknown token".into(), (2, 3)),
+                ("Unknown token".into(), (4, 6)),
+                ("Unknown token".into(), (7, 8)),
+                ("Unterminated string".into(), (9, 12)),
+            ]
+        );
+
+        let (tokens, errors) = tokenize_all("x + 1");
+        assert_eq!(tokens, tokenize("x + 1").unwrap());
+        assert!(errors.is_empty());
+        let (_, errors) = tokenize_all("a # b");
+        assert_eq!(errors[0].to_string(), "Unknown token at column 3");
+    }
+
+    #[test]
+    fn check_parens_reports_first_unmatched() {
+        let check = |source| check_parens(&tokenize_with_spans(source).unwrap());
+        let err = check("(1 + 2").unwrap_err();
+        assert_eq!((err.message.as_str(), err.span), ("Unmatched (", (0, 1)));
+        let err = check("1 + 2)").unwrap_err();
+        assert_eq!((err.message.as_str(), err.span), ("Unmatched )", (5, 6)));
+        assert_eq!(err.to_string(), "Unmatched ) at column 6");
+
+        assert_eq!(check("((1) + (2)").unwrap_err().span, (0, 1));
+        assert_eq!(check("(1)) + (2").unwrap_err().span, (3, 4));
+        assert_eq!(check("f((1), (2 + 3) * 4)"), Ok(()));
+        assert_eq!(check(""), Ok(()));
+    }
+
+    #[test]
+    fn render_underlines_the_span() {
+        let source = "def x = 1;\nf((x + 2) * 3";
+        let err = check_parens(&tokenize_with_spans(source).unwrap()).unwrap_err();
+        assert_eq!(err.render(source), "Unmatched (\n2 | f((x + 2) * 3\n  |  ^");
+
+        let source = "1 + \"abc\ndef";
+        let (_, errors) = tokenize_all(source);
+        assert_eq!(
+            errors[0].render(source),
+            "Unterminated string\n1 | 1 + \"abc\n  |     ^^^^"
+        );
+        let err = LexError {
+            message: "Unknown token".into(),
+            span: (4, 4),
+        };
+        assert_eq!(err.render("1 + "), "Unknown token\n1 | 1 + \n  |     ^");
+    }
+
+    #[test]
+    fn retokenize_matches_a_full_tokenize() {
+        struct Test {
+            source: &'static str,
+            range: Range<usize>,
+            text: &'static str,
+        }
+        let tests = [
+            // Replacing a number in the middle.
+            Test {
+                source: "def x = 12 + 34;\nx * 2",
+                range: 8..10,
+                text: "99",
+            },
+            // Joining two tokens into one.
+            Test {
+                source: "abc + def",
+                range: 3..6,
+                text: "",
+            },
+            // Extending an exponent that was lexed as a number and a name.
+            Test {
+                source: "y + 1e+ z",
+                range: 7..7,
+                text: "3",
+            },
+            // Deleting the quotes between two strings merges them.
+            Test {
+                source: "x + \"a\" + \"b\" + y",
+                range: 6..11,
+                text: "",
+            },
+            // Editing at the start and at the end.
+            Test {
+                source: "1 + 2",
+                range: 0..0,
+                text: "-",
+            },
+            Test {
+                source: "1 + 2",
+                range: 5..5,
+                text: "0 * 3",
+            },
+            // Multi-byte characters before and in the edit.
+            Test {
+                source: "\"é\" + x",
+                range: 7..8,
+                text: "\"ü\"",
+            },
+            Test {
+                source: "",
+                range: 0..0,
+                text: "fn x { x }",
+            },
+        ];
+        for test in tests {
+            let tokens = tokenize_with_spans(test.source).unwrap();
+            let mut source = test.source.to_string();
+            source.replace_range(test.range.clone(), test.text);
+            let edit = Edit {
+                range: test.range,
+                text: test.text.into(),
+            };
+            assert_eq!(
+                retokenize(&tokens, &edit, &source).unwrap(),
+                tokenize_with_spans(&source).unwrap(),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn retokenize_reports_lexing_errors() {
+        let tokens = tokenize_with_spans("a + b").unwrap();
+        let edit = Edit {
+            range: 4..4,
+            text: "\"".into(),
+        };
+        assert_eq!(
+            retokenize(&tokens, &edit, "a + \"b")
+                .unwrap_err()
+                .to_string(),
+            "Unterminated string"
+        );
+    }
+
+    #[test]
+    fn tokenize_strings() {
+        let tokens = tokenize_with_spans("\"a b\" + \"\"").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                (
+                    Token::StringLiteral {
+                        value: "a b".into()
+                    },
+                    (0, 5)
+                ),
+                (Token::Plus, (6, 7)),
+                (Token::StringLiteral { value: "".into() }, (8, 10)),
+            ]
+        );
+        assert_eq!(
+            tokenize("\"abc").unwrap_err().to_string(),
+            "Unterminated string"
+        );
+    }
+
+    #[test]
+    fn exponent_needs_digits() {
+        let tokens = tokenize("2e").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::NumericLiteral { value: "2".into() },
+                Token::Identifier { name: "e".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn spanned_ranges_cover_token_text() {
+        let source = "12 + 34";
+        let tokens = tokenize_spanned(source).unwrap();
+        let slices: Vec<&str> = tokens
+            .iter()
+            .map(|(_, range)| &source[range.clone()])
+            .collect();
+        assert_eq!(slices, vec!["12", "+", "34"]);
+        assert_eq!(
+            tokens[2],
+            (Token::NumericLiteral { value: "34".into() }, 5..7)
+        );
+    }
+
+    #[test]
+    fn keyword_introspection() {
+        assert!(is_keyword("def"));
+        assert!(!is_keyword("defx"));
+        assert!(!is_keyword("x"));
+        assert_eq!(
+            keywords(),
+            [
+                "catch", "cond", "def", "else", "fn", "if", "in", "let", "print", "true", "false",
+                "try", "while"
+            ]
+        );
+        for keyword in keywords() {
+            let tokens = tokenize(keyword).unwrap();
+            assert!(
+                !matches!(tokens[0], Token::Identifier { .. }),
+                "{}",
+                keyword
+            );
+        }
+    }
+
+    #[test]
+    fn many_tokens_do_not_overflow_the_stack() {
+        let source = "1+".repeat(100_000) + "1";
+        assert_eq!(tokenize(&source).unwrap().len(), 200_001);
+    }
+
+    #[test]
+    #[ignore = "slow: tokenizes a 10MB literal"]
+    fn huge_literal_tokenizes_in_linear_time() {
+        let digits = "7".repeat(10_000_000);
+        let started = std::time::Instant::now();
+        let tokens = tokenize_with_spans(&digits).unwrap();
+        assert_eq!(tokens[0].1, (0, digits.len()));
+        let name = "x".repeat(10_000_000);
+        let tokens = tokenize_with_spans(&name).unwrap();
+        assert_eq!(tokens[0].1, (0, name.len()));
+        assert!(started.elapsed().as_secs() < 30);
+    }
+
+    #[test]
+    fn equal_and_equal_equal_are_disambiguated() {
+        let a = || Token::Identifier { name: "a".into() };
+        let b = || Token::Identifier { name: "b".into() };
+        struct Test {
+            source: &'static str,
+            expected: Vec<Token>,
+        }
+        let tests = [
+            Test {
+                source: "==",
+                expected: vec![Token::EqualEqual],
+            },
+            Test {
+                source: "=",
+                expected: vec![Token::Equal],
+            },
+            Test {
+                source: "= =",
+                expected: vec![Token::Equal, Token::Equal],
+            },
+            Test {
+                source: "===",
+                expected: vec![Token::EqualEqual, Token::Equal],
+            },
+            Test {
+                source: "a==b",
+                expected: vec![a(), Token::EqualEqual, b()],
+            },
+            Test {
+                source: "a == b",
+                expected: vec![a(), Token::EqualEqual, b()],
+            },
+            Test {
+                source: "a = b",
+                expected: vec![a(), Token::Equal, b()],
+            },
+        ];
+        for test in tests {
+            let tokens = tokenize(test.source).unwrap();
+            assert_eq!(tokens, test.expected, "{}", test.source);
+        }
+    }
 }
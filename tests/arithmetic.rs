@@ -0,0 +1,112 @@
+use proptest::prelude::*;
+use sal::ast::parse;
+use sal::engine::Engine;
+use sal::formatter::format_program;
+use sal::interpreter::Value;
+use sal::scanner::tokenize_with_spans;
+
+// Integers up to 2^53 are exact in an f64, so an expression whose every
+// intermediate result stays below it evaluates without rounding.
+const EXACT: i64 = 1 << 53;
+
+#[derive(Debug, Clone)]
+enum Arith {
+    Number(i64),
+    Negate(Box<Arith>),
+    Group(Box<Arith>),
+    Binary(Box<Arith>, char, Box<Arith>),
+}
+
+fn arith() -> impl Strategy<Value = Arith> {
+    (0..100i64)
+        .prop_map(Arith::Number)
+        .prop_recursive(4, 24, 2, |inner| {
+            prop_oneof![
+                inner.clone().prop_map(|expr| Arith::Negate(Box::new(expr))),
+                inner.clone().prop_map(|expr| Arith::Group(Box::new(expr))),
+                (
+                    inner.clone(),
+                    prop::sample::select(vec!['+', '-', '*']),
+                    inner
+                )
+                    .prop_map(|(left, operator, right)| Arith::Binary(
+                        Box::new(left),
+                        operator,
+                        Box::new(right)
+                    )),
+            ]
+        })
+}
+
+// The value of the tree itself, independent of how it is written, or None if
+// it would not be exact in an f64.
+fn reference(expr: &Arith) -> Option<i64> {
+    let value = match expr {
+        Arith::Number(number) => *number,
+        Arith::Negate(expr) => -reference(expr)?,
+        Arith::Group(expr) => reference(expr)?,
+        Arith::Binary(left, operator, right) => {
+            let (left, right) = (reference(left)?, reference(right)?);
+            match operator {
+                '+' => left.checked_add(right)?,
+                '-' => left.checked_sub(right)?,
+                _ => left.checked_mul(right)?,
+            }
+        }
+    };
+    (value.abs() < EXACT).then_some(value)
+}
+
+fn precedence(expr: &Arith) -> u8 {
+    match expr {
+        Arith::Binary(_, '*', _) => 2,
+        Arith::Binary(..) => 1,
+        Arith::Negate(_) => 3,
+        Arith::Number(_) | Arith::Group(_) => 4,
+    }
+}
+
+// Writes `expr` with parentheses only where the tree has a group or where the
+// usual precedence and left associativity would otherwise regroup it, so a
+// precedence bug in the parser changes the value.
+fn render(expr: &Arith, min_precedence: u8) -> String {
+    let text = match expr {
+        Arith::Number(number) => number.to_string(),
+        Arith::Negate(expr) => format!("-{}", render(expr, 3)),
+        Arith::Group(expr) => format!("({})", render(expr, 0)),
+        Arith::Binary(left, operator, right) => {
+            let precedence = precedence(expr);
+            format!(
+                "{} {} {}",
+                render(left, precedence),
+                operator,
+                render(right, precedence + 1)
+            )
+        }
+    };
+    if precedence(expr) < min_precedence {
+        format!("({})", text)
+    } else {
+        text
+    }
+}
+
+proptest! {
+    #[test]
+    fn arithmetic_evaluates_like_the_tree(expr in arith()) {
+        let expected = reference(&expr);
+        prop_assume!(expected.is_some());
+        let source = render(&expr, 0);
+        let value = Engine::new().eval(&source).unwrap();
+        prop_assert_eq!(value, Value::Number(expected.unwrap() as f64), "{}", source);
+    }
+
+    #[test]
+    fn formatted_arithmetic_parses_to_the_same_program(expr in arith()) {
+        let source = render(&expr, 0);
+        let program = parse(&tokenize_with_spans(&source).unwrap()).unwrap();
+        let formatted = format_program(&program);
+        let reparsed = parse(&tokenize_with_spans(&formatted).unwrap()).unwrap();
+        prop_assert_eq!(reparsed, program, "{}", formatted);
+    }
+}
@@ -0,0 +1,283 @@
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+fn temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("sal-cli-{}-{}", std::process::id(), name));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+fn sal(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_sal"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn sal_with_stdin(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sal"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn dump_tokens() {
+    let path = temp_file("tokens.sal", "def x = 1;\nx + 2");
+    let output = sal(&["--tokens", path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 8);
+    assert_eq!(lines[0], "0..3 Def");
+    assert_eq!(lines[7], "15..16 NumericLiteral { value: \"2\" }");
+}
+
+#[test]
+fn dump_ast() {
+    let path = temp_file("ast.sal", "1 + 2 * 3");
+    let output = sal(&["--ast", path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("Program {"));
+    assert!(stdout.contains("operator: Astrix"));
+}
+
+#[test]
+fn dump_ast_reports_parse_errors() {
+    let path = temp_file("bad.sal", "1 +");
+    let output = sal(&["--ast", path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Unexpected end of file\n1 | 1 +\n  |    ^\n"
+    );
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn dump_ast_json() {
+    let path = temp_file("ast.json.sal", "def x = 1;\nx + 2");
+    let output = sal(&["--ast-json", path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["stmts"][0]["NamedValue"]["name"], "x");
+    assert_eq!(json["expr"]["Binary"]["operator"], "Plus");
+    assert_eq!(json["expr"]["Binary"]["span"], serde_json::json!([11, 16]));
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn dump_ast_json_reports_errors_as_json() {
+    let path = temp_file("bad.json.sal", "1 +");
+    let output = sal(&["--ast-json", path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    let json: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({ "error": "Unexpected end of file" })
+    );
+}
+
+#[test]
+fn grammar_flag_prints_the_grammar() {
+    let output = sal(&["--grammar"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("program "), "{}", stdout);
+    assert!(stdout.contains("\nprimary "), "{}", stdout);
+}
+
+#[test]
+fn dump_missing_file() {
+    let output = sal(&["--tokens", "/nonexistent/missing.sal"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("/nonexistent/missing.sal: "));
+}
+
+#[test]
+fn run_files_share_definitions() {
+    let first = temp_file("first.sal", "def x = 20;\ndef y = 1;");
+    let second = temp_file("second.sal", "x * 2 + y");
+    let output = sal(&[first.to_str().unwrap(), second.to_str().unwrap()]);
+    fs::remove_file(&first).unwrap();
+    fs::remove_file(&second).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "41\n");
+}
+
+#[test]
+fn run_files_reports_failing_file() {
+    let first = temp_file("ok.sal", "def x = 0;");
+    let second = temp_file("fails.sal", "1 / x");
+    let output = sal(&[first.to_str().unwrap(), second.to_str().unwrap()]);
+    fs::remove_file(&first).unwrap();
+    fs::remove_file(&second).unwrap();
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!("{}: Division by zero\n", second.to_str().unwrap())
+    );
+}
+
+#[test]
+fn run_files_shows_where_a_syntax_error_is() {
+    let path = temp_file("syntax.sal", "def x = 1;\nx + * 2");
+    let output = sal(&[path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!(
+            "{}: Unexpected token: Astrix\n2 | x + * 2\n  |     ^\n",
+            path.to_str().unwrap()
+        )
+    );
+}
+
+#[test]
+fn time_flag_reports_evaluation_time() {
+    let path = temp_file("time.sal", "1 + 1");
+    let output = sal(&["--time", path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("evaluated in "), "{}", stderr);
+    assert_eq!(stderr.lines().count(), 1);
+}
+
+#[test]
+fn profile_flag_reports_counts_after_the_result() {
+    let path = temp_file("profile.sal", "def x = 2;\nx * x + 1");
+    let output = sal(&["--profile", path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "5\n");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Binary: 2\nNumericLiteral: 2\nVariable: 2\n"
+    );
+}
+
+#[test]
+fn max_depth_flag_limits_recursion() {
+    let path = temp_file(
+        "depth.sal",
+        "def down n = cond { n == 0 => 0, else => down(n - 1) };\ndown(20)",
+    );
+    let limited = sal(&["--max-depth", "10", path.to_str().unwrap()]);
+    let raised = sal(&["--max-depth", "30", path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+
+    assert!(!limited.status.success());
+    assert_eq!(
+        String::from_utf8(limited.stderr).unwrap(),
+        format!(
+            "{}: Maximum recursion depth exceeded\n",
+            path.to_str().unwrap()
+        )
+    );
+    assert!(raised.status.success());
+    assert_eq!(String::from_utf8(raised.stdout).unwrap(), "0\n");
+}
+
+#[test]
+fn deep_recursion_does_not_overflow_the_stack() {
+    let path = temp_file(
+        "deep.sal",
+        "def down n = cond { n == 0 => 0, else => down(n - 1) };\ndown(20000)",
+    );
+    let output = sal(&["--max-depth", "100000", path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "0\n");
+}
+
+#[test]
+fn piped_stdin_is_one_program() {
+    let output = sal_with_stdin(&[], "def x = 2;\nx * 3\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "6\n");
+
+    let output = sal_with_stdin(&["--batch"], "def x = 2;\nx * 3\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "6\n");
+}
+
+#[test]
+fn stream_flag_evaluates_each_line() {
+    let output = sal_with_stdin(&["--stream"], "def x = 2;\nx +\nx * 3\n");
+    assert!(!output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "6\n");
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "<stdin>:2: Unexpected end of file\n"
+    );
+}
+
+#[test]
+fn interactive_flag_forces_the_repl() {
+    let output = sal_with_stdin(&["-i"], "1 + 1\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "> 2\n> ");
+}
+
+#[test]
+fn repl_loads_rc_file_from_working_directory() {
+    let dir = env::temp_dir().join(format!("sal-cli-{}-rc", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join(".salrc"), "def answer = 42;\n:group\n").unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sal"))
+        .arg("-i")
+        .current_dir(&dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"answer * 1000\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "> 42,000\n> ");
+    assert!(output.stderr.is_empty());
+}
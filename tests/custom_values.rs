@@ -0,0 +1,92 @@
+use sal::engine::Engine;
+use sal::interpreter::{BinaryOp, CustomValue, Value};
+use sal::scanner::Token;
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq)]
+struct Vector2 {
+    x: f64,
+    y: f64,
+}
+
+impl fmt::Display for Vector2 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Vector2({}, {})", self.x, self.y)
+    }
+}
+
+impl CustomValue for Vector2 {
+    fn type_name(&self) -> &'static str {
+        "Vector2"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+fn vector(x: f64, y: f64) -> Value {
+    Value::Custom(Rc::new(Vector2 { x, y }))
+}
+
+fn as_vector(value: &Value) -> Option<&Vector2> {
+    match value {
+        Value::Custom(custom) => custom.as_any().downcast_ref(),
+        _ => None,
+    }
+}
+
+struct VectorAdd;
+
+impl BinaryOp for VectorAdd {
+    fn apply(
+        &self,
+        operator: &Token,
+        left: &Value,
+        right: &Value,
+    ) -> Option<Result<Value, Box<dyn Error>>> {
+        match (operator, as_vector(left), as_vector(right)) {
+            (Token::Plus, Some(a), Some(b)) => Some(Ok(vector(a.x + b.x, a.y + b.y))),
+            _ => None,
+        }
+    }
+}
+
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.register_operator(VectorAdd);
+    engine.define("a", vector(1.0, 2.0));
+    engine.define("b", vector(0.5, -1.0));
+    engine
+}
+
+#[test]
+fn registered_operator_adds_custom_values() {
+    let mut engine = engine();
+    let sum = engine.eval("a + b + a").unwrap();
+    assert_eq!(as_vector(&sum), Some(&Vector2 { x: 2.5, y: 3.0 }));
+    assert_eq!(sum.to_string(), "Vector2(2.5, 3)");
+    assert_eq!(
+        engine.eval("type_of(a)").unwrap(),
+        Value::String("vector2".into())
+    );
+}
+
+#[test]
+fn unregistered_operators_are_still_unsupported() {
+    let mut engine = engine();
+    assert_eq!(
+        engine.eval("a - b").unwrap_err().to_string(),
+        "Not supported"
+    );
+    assert_eq!(
+        engine.eval("a + 1").unwrap_err().to_string(),
+        "Not supported"
+    );
+    assert_eq!(engine.eval("1 + 2").unwrap(), Value::Number(3.0));
+    assert_eq!(engine.eval("a == a").unwrap(), Value::Bool(true));
+    assert_eq!(engine.eval("a == b").unwrap(), Value::Bool(false));
+}
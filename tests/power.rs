@@ -0,0 +1,62 @@
+use sal::ast::{parse, strip_groupings, Expr};
+use sal::engine::Engine;
+use sal::formatter::format_program;
+use sal::interpreter::Value;
+use sal::scanner::tokenize_with_spans;
+
+fn parse_expr(source: &str) -> Expr {
+    let program = parse(&tokenize_with_spans(source).unwrap()).unwrap();
+    strip_groupings(program.expr.unwrap())
+}
+
+fn eval(source: &str) -> Value {
+    Engine::new().eval(source).unwrap()
+}
+
+// Each source parses like the fully parenthesized form beside it, and
+// evaluates to the value standard mathematical notation gives it.
+const CASES: &[(&str, &str, f64)] = &[
+    ("-2^2", "-(2^2)", -4.0),
+    ("2^-2", "2^(-2)", 0.25),
+    ("2^2^3", "2^(2^3)", 256.0),
+    ("(2^2)^3", "(2^2)^3", 64.0),
+    ("-2^-2", "-(2^(-2))", -0.25),
+    ("(-2)^2", "(-2)^2", 4.0),
+    ("2 * 3^2", "2 * (3^2)", 18.0),
+    ("2^3 * 2", "(2^3) * 2", 16.0),
+    ("-3^2 + 1", "(-(3^2)) + 1", -8.0),
+    ("2^-1^2", "2^(-(1^2))", 0.5),
+    ("10 - 2^3 // 3", "10 - ((2^3) // 3)", 8.0),
+    ("4^0.5%", "4^(0.5%)", 1.0069555500567189),
+];
+
+#[test]
+fn power_groups_like_standard_notation() {
+    for (source, grouped, _) in CASES {
+        assert_eq!(parse_expr(source), parse_expr(grouped), "{}", source);
+    }
+}
+
+#[test]
+fn power_evaluates_like_standard_notation() {
+    for (source, _, expected) in CASES {
+        assert_eq!(eval(source), Value::Number(*expected), "{}", source);
+    }
+}
+
+#[test]
+fn formatted_power_round_trips() {
+    for (source, _, _) in CASES {
+        let program = parse(&tokenize_with_spans(source).unwrap()).unwrap();
+        let formatted = format_program(&program);
+        let reparsed = parse(&tokenize_with_spans(&formatted).unwrap()).unwrap();
+        assert_eq!(reparsed, program, "{}", formatted);
+    }
+}
+
+#[test]
+fn zero_to_a_negative_power_is_division_by_zero() {
+    let err = Engine::new().eval("0 ^ -1").unwrap_err();
+    assert_eq!(err.to_string(), "Division by zero");
+    assert_eq!(eval("0 ^ 0"), Value::Number(1.0));
+}